@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::error::{IxMatchError, Result};
+use crate::{IIQCollection, IIQFile};
+
+/// The path of an archive entry, without the leading `./` some tools write.
+fn entry_path<R: std::io::Read>(entry: &tar::Entry<R>) -> Result<PathBuf> {
+    let path = entry.path()?;
+    Ok(path.strip_prefix(".").unwrap_or(&path).to_path_buf())
+}
+
+impl IIQCollection {
+    /// Build a collection from the entries of the `archive` tar file under `inner_dir`, with
+    /// any of the given extensions, from their names and sizes alone. Nothing is extracted.
+    ///
+    /// Each file's path is `archive` joined with the entry's path in it, e.g.
+    /// `line1.tar/CAMERA_RGB/240101_120000000.iiq`, so the collection can be matched as usual
+    /// and the matched files extracted with [`extract_tar_entries`]. An empty `inner_dir`
+    /// takes the entries of the whole archive.
+    pub fn from_tar(archive: &Path, inner_dir: &Path, extensions: &[&str]) -> Result<Self> {
+        let mut tar = tar::Archive::new(File::open(archive)?);
+        let mut files = Vec::new();
+        for entry in tar.entries()? {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry_path(&entry)?;
+            let has_extension = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.contains(&ext));
+            if has_extension && path.starts_with(inner_dir) {
+                files.push(IIQFile::new_unchecked(&archive.join(path), entry.size())?);
+            }
+        }
+        Ok(IIQCollection::from(files))
+    }
+}
+
+/// Extract `files`, from a collection built with [`IIQCollection::from_tar`], out of `archive`
+/// into `dest`, which is created if needed, and return where each was written. The archive is
+/// read once, whatever the number of files.
+///
+/// Existing files in `dest` are not overwritten, and files that aren't in the archive are an
+/// error.
+pub fn extract_tar_entries(archive: &Path, files: &[IIQFile], dest: &Path) -> Result<Vec<PathBuf>> {
+    let mut wanted: HashSet<PathBuf> = files.iter().map(|f| f.path().to_path_buf()).collect();
+    fs::create_dir_all(dest)?;
+
+    let mut tar = tar::Archive::new(File::open(archive)?);
+    let mut extracted = Vec::with_capacity(files.len());
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = archive.join(entry_path(&entry)?);
+        if !wanted.remove(&path) {
+            continue;
+        }
+        let to = dest.join(path.file_name().unwrap_or_default());
+        if to.exists() {
+            return Err(IxMatchError::DestinationExists { from: path, to });
+        }
+        entry.unpack(&to)?;
+        extracted.push(to);
+    }
+
+    match wanted.into_iter().next() {
+        Some(path) => Err(IxMatchError::EntryNotInArchive {
+            archive: archive.to_path_buf(),
+            path,
+        }),
+        None => Ok(extracted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use tempfile::TempDir;
+
+    fn write_tar(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_from_tar() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("line1.tar");
+        write_tar(
+            &archive,
+            &[
+                ("CAMERA_RGB/210101_120000000.iiq", b"content"),
+                ("CAMERA_RGB/210101_120001000.iiq", b""),
+                ("CAMERA_RGB/notes.txt", b"text"),
+                ("CAMERA_NIR/210101_120000100.iiq", b"content"),
+            ],
+        );
+
+        let rgb = IIQCollection::from_tar(&archive, Path::new("CAMERA_RGB"), &["iiq"]).unwrap();
+        assert_eq!(rgb.len(), 2);
+        assert_eq!(
+            rgb[0].path(),
+            archive.join("CAMERA_RGB/210101_120000000.iiq")
+        );
+        assert_eq!(rgb[0].bytes, 7);
+        assert_eq!(rgb[1].bytes, 0);
+        assert_eq!(
+            rgb[1].datetime(),
+            NaiveDateTime::parse_from_str("210101_120001000", "%y%m%d_%H%M%S%3f").unwrap()
+        );
+
+        let all = IIQCollection::from_tar(&archive, Path::new(""), &["iiq"]).unwrap();
+        assert_eq!(all.len(), 3);
+
+        // Only the requested entries are extracted
+        let nir = IIQCollection::from_tar(&archive, Path::new("CAMERA_NIR"), &["iiq"]).unwrap();
+        let dest = temp_dir.path().join("out");
+        let extracted = extract_tar_entries(&archive, &nir.files, &dest).unwrap();
+        assert_eq!(extracted, vec![dest.join("210101_120000100.iiq")]);
+        assert_eq!(fs::read(&extracted[0]).unwrap(), b"content");
+
+        let result = extract_tar_entries(&archive, &nir.files, &dest);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::DestinationExists { .. })
+        ));
+
+        let missing = IIQFile::new_unchecked(&archive.join("210101_130000000.iiq"), 1).unwrap();
+        let result = extract_tar_entries(&archive, &[missing], &dest);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::EntryNotInArchive { .. })
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+
+use chrono::NaiveDateTime;
+
+use crate::error::{IxMatchError, Result};
+use crate::IIQFile;
+
+/// First line of a cache file, bumped whenever the format changes
+const CACHE_HEADER: &str = "ix-match scan cache v1";
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheEntry {
+    bytes: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    modified: u128,
+    datetime: NaiveDateTime,
+}
+
+impl CacheEntry {
+    fn parse(line: &str) -> Option<(PathBuf, CacheEntry)> {
+        // The path goes last, so it may contain tabs
+        let mut fields = line.splitn(4, '\t');
+        let bytes = fields.next()?.parse().ok()?;
+        let modified = fields.next()?.parse().ok()?;
+        let datetime = NaiveDateTime::parse_from_str(fields.next()?, DATETIME_FORMAT).ok()?;
+        let path = PathBuf::from(fields.next()?);
+        Some((
+            path,
+            CacheEntry {
+                bytes,
+                modified,
+                datetime,
+            },
+        ))
+    }
+}
+
+/// Size and modification time of a file, or `None` if the platform doesn't record the latter.
+fn file_stamp(path: &Path) -> Result<Option<(u64, u128)>> {
+    let metadata = path.metadata().map_err(|source| IxMatchError::Metadata {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos());
+    Ok(modified.map(|modified| (metadata.len(), modified)))
+}
+
+/// Capture times from a previous scan, saved to disk so later runs can skip reading files
+/// that have not changed.
+///
+/// An entry is only used if the file still has the size and modification time it had when it
+/// was cached. Files can be looked up from several threads at once.
+#[derive(Debug)]
+pub struct ScanCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, CacheEntry>,
+    lookups: Mutex<Lookups>,
+}
+
+/// What has been looked up in a [`ScanCache`] since loading.
+#[derive(Debug, Default)]
+struct Lookups {
+    /// Entries for the files looked up, which replace the loaded ones when saving
+    current: HashMap<PathBuf, CacheEntry>,
+    /// Directories scanned, whose loaded entries are dropped if their file is gone
+    scanned: Vec<PathBuf>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ScanCache {
+    /// Load the cache at `path`. A missing or empty cache file gives an empty cache, and any
+    /// other file fails with [`IxMatchError::InvalidCache`] rather than being overwritten when
+    /// the cache is saved.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut lines = content.lines();
+        let entries = match lines.next() {
+            None => HashMap::new(),
+            Some(CACHE_HEADER) => lines.filter_map(CacheEntry::parse).collect(),
+            Some(_) => {
+                return Err(IxMatchError::InvalidCache {
+                    path: path.to_path_buf(),
+                })
+            }
+        };
+        Ok(ScanCache {
+            path: path.to_path_buf(),
+            entries,
+            lookups: Mutex::default(),
+        })
+    }
+
+    fn lookups(&self) -> MutexGuard<'_, Lookups> {
+        // The lookups are only counters and finished entries, so still usable after a panic
+        self.lookups.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Note that `dir` was scanned, so the loaded entries for files in it that are gone, e.g.
+    /// because they were moved, can be dropped when saving.
+    pub(crate) fn scanned(&self, dir: &Path) {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        self.lookups().scanned.push(dir);
+    }
+
+    /// Write the entries for the files looked up since loading back to the cache file, along
+    /// with the loaded entries for other directories, so one cache can be shared by runs on
+    /// different directories.
+    pub fn save(&self) -> Result<()> {
+        let lookups = self.lookups();
+        let stale = |path: &Path| {
+            lookups.scanned.iter().any(|dir| path.starts_with(dir)) && !path.is_file()
+        };
+        let kept = self
+            .entries
+            .iter()
+            .filter(|(path, _)| !lookups.current.contains_key(*path) && !stale(path));
+
+        let mut writer = BufWriter::new(fs::File::create(&self.path)?);
+        writeln!(writer, "{}", CACHE_HEADER)?;
+        for (path, entry) in lookups.current.iter().chain(kept) {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                entry.bytes,
+                entry.modified,
+                entry.datetime.format(DATETIME_FORMAT),
+                path.display()
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Number of files found in the cache since loading
+    pub fn hits(&self) -> usize {
+        self.lookups().hits
+    }
+
+    /// Number of files that had to be read since loading
+    pub fn misses(&self) -> usize {
+        self.lookups().misses
+    }
+
+    /// Build the [`IIQFile`] for `path` from the cache, or with `read` if it is not cached or
+    /// has changed since.
+    pub(crate) fn get_or_read(
+        &self,
+        path: &PathBuf,
+        read: impl FnOnce(&PathBuf) -> Result<IIQFile>,
+    ) -> Result<IIQFile> {
+        let Some((bytes, modified)) = file_stamp(path)? else {
+            self.lookups().misses += 1;
+            return read(path);
+        };
+
+        if let Some(entry) = self.entries.get(path) {
+            if entry.bytes == bytes && entry.modified == modified {
+                let file = IIQFile::with_datetime(path, bytes, entry.datetime)?;
+                let mut lookups = self.lookups();
+                lookups.hits += 1;
+                lookups.current.insert(path.clone(), entry.clone());
+                return Ok(file);
+            }
+        }
+
+        self.lookups().misses += 1;
+        // Not holding the lock, so other threads can read files at the same time
+        let file = read(path)?;
+        // Paths with line breaks can't be stored in the line based format
+        if !path.to_string_lossy().contains(['\n', '\r']) {
+            self.lookups().current.insert(
+                path.clone(),
+                CacheEntry {
+                    bytes,
+                    modified,
+                    datetime: file.datetime,
+                },
+            );
+        }
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_path = temp_dir.path().join("scan.cache");
+        let paths = vec![
+            temp_dir.path().join("210101_120000000.iiq"),
+            temp_dir.path().join("210101_120001000.iiq"),
+        ];
+        for path in &paths {
+            fs::write(path, "content").unwrap();
+        }
+
+        // Nothing is cached on the first run
+        let cache = ScanCache::load(&cache_path).unwrap();
+        for path in &paths {
+            cache.get_or_read(path, IIQFile::new).unwrap();
+        }
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+        cache.save().unwrap();
+
+        // The second run doesn't need to read the files
+        let cache = ScanCache::load(&cache_path).unwrap();
+        for path in &paths {
+            let file = cache
+                .get_or_read(path, |_| panic!("File should be cached"))
+                .unwrap();
+            assert_eq!(file, IIQFile::new(path).unwrap());
+        }
+        assert_eq!((cache.hits(), cache.misses()), (2, 0));
+        cache.save().unwrap();
+
+        // A changed file is read again
+        fs::write(&paths[1], "changed content").unwrap();
+        let cache = ScanCache::load(&cache_path).unwrap();
+        for path in &paths {
+            cache.get_or_read(path, IIQFile::new).unwrap();
+        }
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        cache.save().unwrap();
+
+        // A run on another directory keeps the entries for this one
+        let other_dir = temp_dir.path().join("other");
+        fs::create_dir(&other_dir).unwrap();
+        let other = other_dir.join("210101_130000000.iiq");
+        fs::write(&other, "content").unwrap();
+        let cache = ScanCache::load(&cache_path).unwrap();
+        cache.scanned(&other_dir);
+        cache.get_or_read(&other, IIQFile::new).unwrap();
+        cache.save().unwrap();
+        let cache = ScanCache::load(&cache_path).unwrap();
+        for path in paths.iter().chain([&other]) {
+            cache
+                .get_or_read(path, |_| panic!("File should be cached"))
+                .unwrap();
+        }
+        assert_eq!((cache.hits(), cache.misses()), (3, 0));
+
+        // Files gone from a scanned directory are dropped
+        fs::remove_file(&other).unwrap();
+        let cache = ScanCache::load(&cache_path).unwrap();
+        cache.scanned(&other_dir);
+        cache.save().unwrap();
+        let cache = ScanCache::load(&cache_path).unwrap();
+        assert_eq!(cache.entries.len(), 2);
+        assert!(!cache.entries.contains_key(&other));
+    }
+
+    #[test]
+    fn test_scan_cache_invalid() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_path = temp_dir.path().join("scan.cache");
+
+        fs::write(&cache_path, "").unwrap();
+        assert!(ScanCache::load(&cache_path).unwrap().entries.is_empty());
+
+        // Some other file given by mistake is left alone
+        fs::write(&cache_path, "notes\n").unwrap();
+        assert!(matches!(
+            ScanCache::load(&cache_path),
+            Err(IxMatchError::InvalidCache { path }) if path == cache_path
+        ));
+        assert_eq!(fs::read_to_string(&cache_path).unwrap(), "notes\n");
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use crate::{IIQFile, ProcessConfig};
+
+/// What processing found out about a file, which decides where it is moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileStatus {
+    Matched,
+    /// Matched, but set aside by [`ProcessConfig::size_ratio_max`] or
+    /// [`ProcessConfig::min_quality`]
+    Suspect,
+    /// Further apart than [`ProcessConfig::match_threshold`], but within
+    /// [`ProcessConfig::review_threshold`]
+    Review,
+    Unmatched,
+    /// 0 bytes, or below [`ProcessConfig::min_size_bytes`]
+    Empty,
+    /// Failed [`ProcessConfig::validate`]
+    Corrupt,
+    /// Captured outside [`ProcessConfig::time_of_day`]
+    OffHours,
+}
+
+impl FileStatus {
+    /// The subdirectory files with this status are moved to by default, or `None` for matched
+    /// files, which go to [`ProcessConfig::matched_subdir`] or stay in the band directory.
+    pub fn dir_name(self) -> Option<&'static str> {
+        match self {
+            FileStatus::Matched => None,
+            FileStatus::Suspect => Some("suspect"),
+            FileStatus::Review => Some("review"),
+            FileStatus::Unmatched => Some("unmatched"),
+            FileStatus::Empty => Some("empty"),
+            FileStatus::Corrupt => Some("corrupt"),
+            FileStatus::OffHours => Some("offhours"),
+        }
+    }
+}
+
+/// Decides where each file is moved, for routing files by e.g. subfolder, size or name. See
+/// [`process_images_with_classifier`](crate::process_images_with_classifier).
+///
+/// Only [`destination`](Self::destination) needs to be implemented, and it can fall back to
+/// [`DefaultClassifier`] for the files it doesn't care about.
+pub trait Classifier {
+    /// The directory to move `file` to, relative to its band directory. An empty path is the
+    /// band directory itself.
+    fn destination(&self, file: &IIQFile, status: FileStatus, config: &ProcessConfig) -> PathBuf {
+        DefaultClassifier.destination(file, status, config)
+    }
+}
+
+/// Moves files the way [`process_images`](crate::process_images) does: matched files to the
+/// band directory or [`ProcessConfig::matched_subdir`], and the others to the
+/// [`ProcessConfig::subdir_name`] subdirectory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultClassifier;
+
+impl Classifier for DefaultClassifier {
+    fn destination(&self, _file: &IIQFile, status: FileStatus, config: &ProcessConfig) -> PathBuf {
+        config
+            .subdir_name(status)
+            .map(PathBuf::from)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,780 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::{FixedOffset, NaiveDateTime, NaiveTime};
+use regex::Regex;
+
+use crate::classify::FileStatus;
+use crate::error::{IxMatchError, Result};
+use crate::filesystem::{CollisionPolicy, FileOp};
+use crate::IIQFile;
+
+/// How RGB and NIR files are paired up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchStrategy {
+    /// Pair each file with the other band's file closest in time, within the match threshold.
+    #[default]
+    ByTime,
+    /// Pair the i-th RGB file with the i-th NIR file, ignoring the match threshold. Useful when
+    /// both cameras fired the same frames but one clock has drifted or was never set.
+    ByOrder {
+        /// Fail if the offset between the paired files changes direction, which suggests a
+        /// dropped frame has shifted the sequences out of step.
+        validate_monotonic: bool,
+    },
+    /// Pair files with the same frame index, the number after the datetime at the end of the
+    /// file name (e.g. the 42 in `240101_120000000_0042.iiq` or `240101_1200_0042.iiq`). Falls back to
+    /// [`ByTime`](Self::ByTime) unless every file has an index, the indices in each band are
+    /// unique, and both bands have the same number of files.
+    ByIndex,
+    /// Pair each RGB file with every NIR file within the match threshold that is closer to it
+    /// than to any other RGB file, for a NIR camera firing faster than the RGB one. The report
+    /// then has a pair for each NIR file, see [`MatchReport::groups`](crate::MatchReport::groups).
+    ManyToOne,
+}
+
+/// Which file to pick when two are equally close in time to the file being matched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Pick the file captured first
+    #[default]
+    PreferEarlier,
+    /// Pick the file captured last
+    PreferLater,
+}
+
+/// How much is printed while processing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Print nothing but errors
+    Quiet = 0,
+    /// Print the results
+    #[default]
+    Normal = 1,
+    /// Also print each file as it is moved
+    Debug = 2,
+}
+
+impl Verbosity {
+    /// Whether to print details of each step, such as the files being moved.
+    pub fn is_debug(self) -> bool {
+        self >= Verbosity::Debug
+    }
+}
+
+/// Options controlling [`process_images`](crate::process_images).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessConfig {
+    /// Maximum time offset between the files of a matched RGB/NIR pair
+    pub match_threshold: Duration,
+    /// Maximum offset when the NIR file was captured before the RGB file, instead of
+    /// [`match_threshold`](Self::match_threshold). With a fixed firing order, a match on the
+    /// unexpected side can be held to a much smaller offset.
+    pub match_threshold_before: Option<Duration>,
+    /// Maximum offset when the NIR file was captured after the RGB file, instead of
+    /// [`match_threshold`](Self::match_threshold)
+    pub match_threshold_after: Option<Duration>,
+    /// Leave 0 byte files in place instead of moving them to `empty/`
+    pub keep_empty_files: bool,
+    /// Leave unmatched files where they are instead of moving them to `unmatched/`. They are
+    /// still counted and listed in the report.
+    pub keep_unmatched_in_place: bool,
+    /// After moving, check every file is where the run put it and that as many of the run's
+    /// files are on disk as before, see [`MatchReport::discrepancies`](crate::MatchReport::discrepancies).
+    /// Skipped with [`dry_run`](Self::dry_run) or a [`Destination`](crate::Destination).
+    pub check_locations: bool,
+    /// Write a `<stem>.match.json` next to each matched file, recording its partner and the
+    /// offset between them. Skipped with [`dry_run`](Self::dry_run) or a
+    /// [`Destination`](crate::Destination). The sidecars of an earlier run are removed from
+    /// every file that is processed again, but not from those out of range or off hours, and
+    /// by [`revert_changes`](crate::revert_changes).
+    pub write_sidecars: bool,
+    /// Treat files smaller than this as empty too, such as transfers that were cut off after
+    /// the header. 0 byte files are always empty.
+    pub min_size_bytes: u64,
+    /// Work out the matches without moving any files
+    pub dry_run: bool,
+    /// How much to print, see [`Verbosity`]
+    pub verbosity: Verbosity,
+    pub match_strategy: MatchStrategy,
+    /// Which file to pair with when two are equally close in time, when matching by time
+    pub tie_break: TieBreak,
+    /// UTC offset the RGB camera clock was set to. Capture times are converted to UTC before
+    /// matching, so cameras set to different time zones can still be paired.
+    pub rgb_utc_offset: FixedOffset,
+    /// UTC offset the NIR camera clock was set to, see
+    /// [`rgb_utc_offset`](Self::rgb_utc_offset)
+    pub nir_utc_offset: FixedOffset,
+    /// Split the RGB files into flight lines wherever they are more than this far apart, and
+    /// take the NIR clock's offset from the RGB one out of each line before matching, for clocks
+    /// drifting apart over a long flight. The offsets are estimated from the nearest NIR file to
+    /// each RGB file, so should stay under half the time between frames.
+    pub line_offset_gap: Option<Duration>,
+    /// Only consider files captured at or after this time, in UTC if the cameras have an offset
+    pub start: Option<NaiveDateTime>,
+    /// Only consider files captured at or before this time, in UTC if the cameras have an offset
+    pub end: Option<NaiveDateTime>,
+    /// Only consider files last modified on disk at or after this time, e.g. to process just
+    /// the latest card dump. Unlike [`start`](Self::start), this goes by the file's mtime, not
+    /// the capture time in its name.
+    pub modified_since: Option<SystemTime>,
+    /// Only match files captured within this time of day, from the first time up to the
+    /// second, e.g. for a day-only survey. The others are moved to `offhours/`. Times are as
+    /// set on each camera's clock, like in the file names, and a window whose start is after
+    /// its end spans midnight.
+    pub time_of_day: Option<(NaiveTime, NaiveTime)>,
+    /// chrono format of the capture time at the start of the RGB file names, for cameras that
+    /// don't use PhaseOne's `%y%m%d_%H%M%S%3f`. See
+    /// [`IIQFile::with_datetime_format`](crate::IIQFile::with_datetime_format).
+    pub rgb_datetime_format: Option<String>,
+    /// chrono format of the capture time at the start of the NIR file names, see
+    /// [`rgb_datetime_format`](Self::rgb_datetime_format)
+    pub nir_datetime_format: Option<String>,
+    /// Regex finding the capture time in the file names of both bands, for names where it
+    /// isn't at the start, e.g. `SITE12_240101_120000000.iiq`. See
+    /// [`IIQFile::with_datetime_regex`](crate::IIQFile::with_datetime_regex).
+    pub datetime_regex: Option<String>,
+    /// Regex telling the bands apart by file name, for datasets with both in one directory,
+    /// e.g. `_RGB\.iiq$`. Files whose name matches are RGB, the rest NIR. The RGB directory is
+    /// searched, with [`rgb_extensions`](Self::rgb_extensions), so pass the mixed directory as
+    /// both band directories.
+    pub band_regex: Option<String>,
+    /// Move files, or copy them and leave the originals in place
+    pub file_op: FileOp,
+    /// Move matched files into this subdirectory of each band directory, e.g. `matched`,
+    /// instead of its root
+    pub matched_subdir: Option<String>,
+    /// Names for the subdirectories files are moved to instead of their
+    /// [`FileStatus::dir_name`], e.g. `no_match` for [`FileStatus::Unmatched`]. Matched files go
+    /// to [`matched_subdir`](Self::matched_subdir) instead.
+    pub subdir_names: HashMap<FileStatus, String>,
+    /// Keep the subdirectories files were found in below each band directory when moving them,
+    /// instead of moving them all into the same directory. Matched files are left where they
+    /// are, unless [`matched_subdir`](Self::matched_subdir) is set.
+    pub preserve_structure: bool,
+    /// Before matching, move the files found in subdirectories of each band directory, e.g.
+    /// one per card download, into the band directory itself, adding a numeric suffix to
+    /// names that are already taken. The files are moved even with [`FileOp::Copy`]. Only the
+    /// files left to match are moved: those that are empty, corrupt, off hours or out of range
+    /// are moved from, or left, where they were found.
+    pub flatten: bool,
+    /// Put the sorted files under this directory instead of in the band directories: the RGB
+    /// files in `rgb/` and the NIR files in `nir/`, with `unmatched/`, `empty/` and so on
+    /// inside each as they would be in place. With [`FileOp::Copy`] the source directories are
+    /// left untouched. Not used by [`watch_images`](crate::watch_images).
+    pub output_dir: Option<PathBuf>,
+    /// What to do when a moved file would overwrite an existing one
+    pub collision_policy: CollisionPolicy,
+    /// Process directories even if they look like they were already processed
+    pub force: bool,
+    /// Return [`IxMatchError::Unmatched`](crate::IxMatchError::Unmatched) if any file was left
+    /// without a match
+    pub strict: bool,
+    /// How many directory levels below each band directory to search for files, where files
+    /// directly in it are at depth 1. `None` searches the whole tree.
+    pub max_depth: Option<usize>,
+    /// Match these RGB files instead of searching the RGB directory, e.g. a list picked out in
+    /// a selection tool. Each has to exist. They are moved into the RGB directory like found
+    /// files.
+    pub rgb_paths: Option<Vec<PathBuf>>,
+    /// Match these NIR files instead of searching the NIR directory, see
+    /// [`rgb_paths`](Self::rgb_paths)
+    pub nir_paths: Option<Vec<PathBuf>>,
+    /// Globs for files and directories to skip, relative to each band directory
+    pub exclude: Vec<String>,
+    /// Extensions of the files searched for in the RGB directory, without the dot. Matched
+    /// case-sensitively, so list e.g. both `iiq` and `IIQ` if a camera writes either.
+    pub rgb_extensions: Vec<String>,
+    /// Extensions of the files searched for in the NIR directory, see
+    /// [`rgb_extensions`](Self::rgb_extensions)
+    pub nir_extensions: Vec<String>,
+    /// Search symlinked directories as if they were real ones
+    pub follow_links: bool,
+    /// Check that files start with TIFF/IIQ magic bytes, moving those that don't to `corrupt/`
+    pub validate: bool,
+    /// Move matched pairs where one file is more than this many times the size of the other
+    /// to `suspect/`
+    pub size_ratio_max: Option<f64>,
+    /// Move matched pairs with a [`match_quality`](crate::match_quality) below this, between 0
+    /// and 1, to `suspect/`. The quality is for the threshold on the side of the RGB file the
+    /// NIR file is on, see [`match_threshold_before`](Self::match_threshold_before).
+    pub min_quality: Option<f64>,
+    /// Move pairs further apart than [`match_threshold`](Self::match_threshold), but within
+    /// this, to `review/` instead of `unmatched/`, to look over the near misses by hand
+    pub review_threshold: Option<Duration>,
+    /// Fail before moving the matched files if fewer than this fraction, between 0 and 1, of
+    /// the smaller band's files are matched, as when pointed at the wrong directories or with
+    /// a badly wrong threshold. The files already moved are put back.
+    pub min_match_rate: Option<f64>,
+    /// Flag runs where one band has more than this many times as many files as the other,
+    /// e.g. 2, in [`MatchReport::count_skew`](crate::MatchReport::count_skew), or fail with
+    /// [`IxMatchError::CountSkew`](crate::IxMatchError::CountSkew) before matching if
+    /// [`strict`](Self::strict) is set
+    pub max_count_skew: Option<f64>,
+    /// Flag runs whose match threshold is more than this fraction of the median time between
+    /// frames, e.g. 0.5, in [`MatchReport::frame_interval`](crate::MatchReport::frame_interval),
+    /// or fail with
+    /// [`IxMatchError::ThresholdExceedsInterval`](crate::IxMatchError::ThresholdExceedsInterval)
+    /// before matching if [`strict`](Self::strict) is set. Only checked with
+    /// [`MatchStrategy::ByTime`].
+    pub max_thresh_interval_ratio: Option<f64>,
+    /// File to keep the scanned capture times in, so unchanged files are not read again on the
+    /// next run. See [`ScanCache`](crate::ScanCache). The cache does not record where the
+    /// times came from, so use a separate file for runs with and without header times.
+    pub cache: Option<PathBuf>,
+    /// How many threads read the files at once, e.g. to avoid saturating a shared network
+    /// drive. `None` uses one per CPU core, and 1 reads them one at a time.
+    pub threads: Option<NonZeroUsize>,
+    /// Move or copy the files on [`threads`](Self::threads) threads at once too, which helps
+    /// on network storage where each move waits on the server
+    pub parallel_moves: bool,
+    /// Record the matched files in this file, see [`Manifest`](crate::Manifest), and leave
+    /// the files it already lists alone. This makes repeated runs during a long ingest
+    /// incremental: only files that arrived since, and files still unmatched, are considered.
+    /// The directories are not checked for an earlier run, as with `force`.
+    pub manifest: Option<PathBuf>,
+    /// Take capture times from the EXIF data in the file headers instead of the file names
+    #[cfg(feature = "exif")]
+    pub header_datetime: bool,
+    /// Read the GPS position of each matched pair from the EXIF data in the file headers into
+    /// [`MatchReport::positions`](crate::MatchReport::positions)
+    #[cfg(feature = "exif")]
+    pub read_gps: bool,
+}
+
+impl ProcessConfig {
+    /// A [`ProcessConfigBuilder`] starting from the defaults
+    pub fn builder() -> ProcessConfigBuilder {
+        ProcessConfigBuilder::default()
+    }
+
+    /// How many threads to read, and with [`parallel_moves`](Self::parallel_moves) move, the
+    /// files on, see [`threads`](Self::threads)
+    pub(crate) fn thread_count(&self) -> usize {
+        self.threads
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    /// Find the files with any of `extensions` under `dir`, honouring
+    /// [`max_depth`](Self::max_depth), [`exclude`](Self::exclude) and
+    /// [`follow_links`](Self::follow_links). Pass [`rgb_extensions`](Self::rgb_extensions) or
+    /// [`nir_extensions`](Self::nir_extensions) for the band `dir` holds.
+    pub fn find_files(&self, dir: &Path, extensions: &[String]) -> Result<Vec<PathBuf>> {
+        self.find_files_below(dir, extensions, self.max_depth, &self.exclude)
+    }
+
+    /// Like [`find_files`](Self::find_files), at any depth and without the excludes, to find
+    /// the files processing moved into subdirectories of `dir`
+    pub(crate) fn find_moved_files(
+        &self,
+        dir: &Path,
+        extensions: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        self.find_files_below(dir, extensions, None, &[])
+    }
+
+    fn find_files_below(
+        &self,
+        dir: &Path,
+        extensions: &[String],
+        max_depth: Option<usize>,
+        exclude: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let mut searched = HashSet::new();
+        // An extension listed twice would find its files twice
+        for extension in extensions
+            .iter()
+            .filter(|ext| searched.insert(ext.as_str()))
+        {
+            paths.extend(crate::find_files(
+                dir,
+                extension,
+                max_depth,
+                exclude,
+                self.follow_links,
+            )?);
+        }
+        Ok(paths)
+    }
+
+    /// [`datetime_regex`](Self::datetime_regex), compiled
+    pub(crate) fn compiled_datetime_regex(&self) -> Result<Option<Regex>> {
+        self.datetime_regex
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|source| IxMatchError::InvalidDatetimeRegex {
+                    pattern: pattern.to_string(),
+                    source,
+                })
+            })
+            .transpose()
+    }
+
+    /// [`band_regex`](Self::band_regex), compiled
+    pub(crate) fn compiled_band_regex(&self) -> Result<Option<Regex>> {
+        self.band_regex
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|source| IxMatchError::InvalidBandRegex {
+                    pattern: pattern.to_string(),
+                    source,
+                })
+            })
+            .transpose()
+    }
+
+    /// The subdirectory files with `status` are moved to: their name in
+    /// [`subdir_names`](Self::subdir_names) or their [`FileStatus::dir_name`], or
+    /// [`matched_subdir`](Self::matched_subdir) for matched files.
+    pub fn subdir_name(&self, status: FileStatus) -> Option<&str> {
+        match status {
+            FileStatus::Matched => self.matched_subdir.as_deref(),
+            _ => self
+                .subdir_names
+                .get(&status)
+                .map(String::as_str)
+                .or_else(|| status.dir_name()),
+        }
+    }
+
+    /// Where the sorted files from `band_dir`, the `band` (`rgb` or `nir`) directory, go, see
+    /// [`output_dir`](Self::output_dir)
+    pub(crate) fn output_root(&self, band_dir: &Path, band: &str) -> PathBuf {
+        match &self.output_dir {
+            Some(dir) => dir.join(band),
+            None => band_dir.to_path_buf(),
+        }
+    }
+
+    /// The maximum offsets for NIR files captured before and after the RGB file
+    pub(crate) fn directional_thresholds(&self) -> (Duration, Duration) {
+        (
+            self.match_threshold_before.unwrap_or(self.match_threshold),
+            self.match_threshold_after.unwrap_or(self.match_threshold),
+        )
+    }
+
+    /// The threshold on the side of `rgb` that `nir` was captured on, from
+    /// [`directional_thresholds`](Self::directional_thresholds), which the pair's
+    /// [`match_quality`](crate::match_quality) is scaled by
+    pub(crate) fn pair_threshold(&self, rgb: &IIQFile, nir: &IIQFile) -> Duration {
+        let (before, after) = self.directional_thresholds();
+        if nir.datetime() < rgb.datetime() {
+            before
+        } else {
+            after
+        }
+    }
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        ProcessConfig {
+            match_threshold: Duration::from_millis(500),
+            match_threshold_before: None,
+            match_threshold_after: None,
+            keep_empty_files: false,
+            keep_unmatched_in_place: false,
+            check_locations: false,
+            write_sidecars: false,
+            min_size_bytes: 0,
+            dry_run: false,
+            verbosity: Verbosity::default(),
+            match_strategy: MatchStrategy::default(),
+            tie_break: TieBreak::default(),
+            rgb_utc_offset: FixedOffset::east_opt(0).unwrap(),
+            nir_utc_offset: FixedOffset::east_opt(0).unwrap(),
+            line_offset_gap: None,
+            start: None,
+            end: None,
+            modified_since: None,
+            time_of_day: None,
+            rgb_datetime_format: None,
+            nir_datetime_format: None,
+            datetime_regex: None,
+            band_regex: None,
+            file_op: FileOp::default(),
+            matched_subdir: None,
+            subdir_names: HashMap::new(),
+            preserve_structure: false,
+            flatten: false,
+            output_dir: None,
+            collision_policy: CollisionPolicy::default(),
+            force: false,
+            strict: false,
+            max_depth: None,
+            rgb_paths: None,
+            nir_paths: None,
+            exclude: Vec::new(),
+            rgb_extensions: vec!["iiq".to_string()],
+            nir_extensions: vec!["iiq".to_string()],
+            follow_links: true,
+            validate: false,
+            size_ratio_max: None,
+            min_quality: None,
+            review_threshold: None,
+            min_match_rate: None,
+            max_count_skew: None,
+            max_thresh_interval_ratio: None,
+            cache: None,
+            threads: None,
+            parallel_moves: false,
+            manifest: None,
+            #[cfg(feature = "exif")]
+            header_datetime: false,
+            #[cfg(feature = "exif")]
+            read_gps: false,
+        }
+    }
+}
+
+/// Builds a [`ProcessConfig`] one option at a time, starting from the defaults, so code
+/// setting a few options keeps compiling as more are added.
+///
+/// ```
+/// use std::time::Duration;
+/// use ix_match::ProcessConfig;
+///
+/// let config = ProcessConfig::builder()
+///     .match_threshold(Duration::from_millis(250))
+///     .matched_subdir("matched")
+///     .dry_run(true)
+///     .build();
+/// assert_eq!(config.matched_subdir.as_deref(), Some("matched"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessConfigBuilder {
+    config: ProcessConfig,
+}
+
+impl From<ProcessConfig> for ProcessConfigBuilder {
+    /// Start from an existing config, e.g. to change a few options of it
+    fn from(config: ProcessConfig) -> Self {
+        ProcessConfigBuilder { config }
+    }
+}
+
+impl ProcessConfigBuilder {
+    pub fn build(self) -> ProcessConfig {
+        self.config
+    }
+
+    /// See [`ProcessConfig::match_threshold`]
+    pub fn match_threshold(mut self, threshold: Duration) -> Self {
+        self.config.match_threshold = threshold;
+        self
+    }
+
+    /// See [`ProcessConfig::match_threshold_before`]
+    pub fn match_threshold_before(mut self, threshold: Duration) -> Self {
+        self.config.match_threshold_before = Some(threshold);
+        self
+    }
+
+    /// See [`ProcessConfig::match_threshold_after`]
+    pub fn match_threshold_after(mut self, threshold: Duration) -> Self {
+        self.config.match_threshold_after = Some(threshold);
+        self
+    }
+
+    /// See [`ProcessConfig::keep_empty_files`]
+    pub fn keep_empty_files(mut self, keep: bool) -> Self {
+        self.config.keep_empty_files = keep;
+        self
+    }
+
+    /// See [`ProcessConfig::keep_unmatched_in_place`]
+    pub fn keep_unmatched_in_place(mut self, keep: bool) -> Self {
+        self.config.keep_unmatched_in_place = keep;
+        self
+    }
+
+    /// See [`ProcessConfig::check_locations`]
+    pub fn check_locations(mut self, check: bool) -> Self {
+        self.config.check_locations = check;
+        self
+    }
+
+    /// See [`ProcessConfig::write_sidecars`]
+    pub fn write_sidecars(mut self, write: bool) -> Self {
+        self.config.write_sidecars = write;
+        self
+    }
+
+    /// See [`ProcessConfig::min_size_bytes`]
+    pub fn min_size_bytes(mut self, bytes: u64) -> Self {
+        self.config.min_size_bytes = bytes;
+        self
+    }
+
+    /// See [`ProcessConfig::dry_run`]
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    /// See [`ProcessConfig::verbosity`]
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.config.verbosity = verbosity;
+        self
+    }
+
+    /// See [`ProcessConfig::match_strategy`]
+    pub fn match_strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.config.match_strategy = strategy;
+        self
+    }
+
+    /// See [`ProcessConfig::tie_break`]
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.config.tie_break = tie_break;
+        self
+    }
+
+    /// See [`ProcessConfig::rgb_utc_offset`]
+    pub fn rgb_utc_offset(mut self, offset: FixedOffset) -> Self {
+        self.config.rgb_utc_offset = offset;
+        self
+    }
+
+    /// See [`ProcessConfig::nir_utc_offset`]
+    pub fn nir_utc_offset(mut self, offset: FixedOffset) -> Self {
+        self.config.nir_utc_offset = offset;
+        self
+    }
+
+    /// See [`ProcessConfig::line_offset_gap`]
+    pub fn line_offset_gap(mut self, gap: Duration) -> Self {
+        self.config.line_offset_gap = Some(gap);
+        self
+    }
+
+    /// See [`ProcessConfig::start`]
+    pub fn start(mut self, start: NaiveDateTime) -> Self {
+        self.config.start = Some(start);
+        self
+    }
+
+    /// See [`ProcessConfig::end`]
+    pub fn end(mut self, end: NaiveDateTime) -> Self {
+        self.config.end = Some(end);
+        self
+    }
+
+    /// See [`ProcessConfig::modified_since`]
+    pub fn modified_since(mut self, since: SystemTime) -> Self {
+        self.config.modified_since = Some(since);
+        self
+    }
+
+    /// See [`ProcessConfig::time_of_day`]
+    pub fn time_of_day(mut self, start: NaiveTime, end: NaiveTime) -> Self {
+        self.config.time_of_day = Some((start, end));
+        self
+    }
+
+    /// See [`ProcessConfig::rgb_datetime_format`]
+    pub fn rgb_datetime_format(mut self, format: impl Into<String>) -> Self {
+        self.config.rgb_datetime_format = Some(format.into());
+        self
+    }
+
+    /// See [`ProcessConfig::nir_datetime_format`]
+    pub fn nir_datetime_format(mut self, format: impl Into<String>) -> Self {
+        self.config.nir_datetime_format = Some(format.into());
+        self
+    }
+
+    /// See [`ProcessConfig::band_regex`]
+    pub fn band_regex(mut self, regex: impl Into<String>) -> Self {
+        self.config.band_regex = Some(regex.into());
+        self
+    }
+
+    /// See [`ProcessConfig::datetime_regex`]
+    pub fn datetime_regex(mut self, regex: impl Into<String>) -> Self {
+        self.config.datetime_regex = Some(regex.into());
+        self
+    }
+
+    /// See [`ProcessConfig::file_op`]
+    pub fn file_op(mut self, file_op: FileOp) -> Self {
+        self.config.file_op = file_op;
+        self
+    }
+
+    /// See [`ProcessConfig::matched_subdir`]
+    pub fn matched_subdir(mut self, name: impl Into<String>) -> Self {
+        self.config.matched_subdir = Some(name.into());
+        self
+    }
+
+    /// See [`ProcessConfig::subdir_names`]
+    pub fn subdir_name(mut self, status: FileStatus, name: impl Into<String>) -> Self {
+        self.config.subdir_names.insert(status, name.into());
+        self
+    }
+
+    /// See [`ProcessConfig::preserve_structure`]
+    pub fn preserve_structure(mut self, preserve: bool) -> Self {
+        self.config.preserve_structure = preserve;
+        self
+    }
+
+    /// See [`ProcessConfig::flatten`]
+    pub fn flatten(mut self, flatten: bool) -> Self {
+        self.config.flatten = flatten;
+        self
+    }
+
+    /// See [`ProcessConfig::output_dir`]
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.output_dir = Some(dir.into());
+        self
+    }
+
+    /// See [`ProcessConfig::collision_policy`]
+    pub fn collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.config.collision_policy = policy;
+        self
+    }
+
+    /// See [`ProcessConfig::force`]
+    pub fn force(mut self, force: bool) -> Self {
+        self.config.force = force;
+        self
+    }
+
+    /// See [`ProcessConfig::strict`]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// See [`ProcessConfig::max_depth`]
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.config.max_depth = Some(depth);
+        self
+    }
+
+    /// See [`ProcessConfig::rgb_paths`]
+    pub fn rgb_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.config.rgb_paths = Some(paths);
+        self
+    }
+
+    /// See [`ProcessConfig::nir_paths`]
+    pub fn nir_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.config.nir_paths = Some(paths);
+        self
+    }
+
+    /// Add a glob to [`ProcessConfig::exclude`], so this can be called more than once
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.config.exclude.push(glob.into());
+        self
+    }
+
+    /// See [`ProcessConfig::rgb_extensions`]
+    pub fn rgb_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.config.rgb_extensions = extensions;
+        self
+    }
+
+    /// See [`ProcessConfig::nir_extensions`]
+    pub fn nir_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.config.nir_extensions = extensions;
+        self
+    }
+
+    /// See [`ProcessConfig::follow_links`]
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.config.follow_links = follow;
+        self
+    }
+
+    /// See [`ProcessConfig::validate`]
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.config.validate = validate;
+        self
+    }
+
+    /// See [`ProcessConfig::size_ratio_max`]
+    pub fn size_ratio_max(mut self, ratio: f64) -> Self {
+        self.config.size_ratio_max = Some(ratio);
+        self
+    }
+
+    /// See [`ProcessConfig::min_quality`]
+    pub fn min_quality(mut self, quality: f64) -> Self {
+        self.config.min_quality = Some(quality);
+        self
+    }
+
+    /// See [`ProcessConfig::review_threshold`]
+    pub fn review_threshold(mut self, threshold: Duration) -> Self {
+        self.config.review_threshold = Some(threshold);
+        self
+    }
+
+    /// See [`ProcessConfig::min_match_rate`]
+    pub fn min_match_rate(mut self, rate: f64) -> Self {
+        self.config.min_match_rate = Some(rate);
+        self
+    }
+
+    /// See [`ProcessConfig::max_count_skew`]
+    pub fn max_count_skew(mut self, skew: f64) -> Self {
+        self.config.max_count_skew = Some(skew);
+        self
+    }
+
+    /// See [`ProcessConfig::max_thresh_interval_ratio`]
+    pub fn max_thresh_interval_ratio(mut self, ratio: f64) -> Self {
+        self.config.max_thresh_interval_ratio = Some(ratio);
+        self
+    }
+
+    /// See [`ProcessConfig::cache`]
+    pub fn cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.cache = Some(path.into());
+        self
+    }
+
+    /// See [`ProcessConfig::threads`]
+    pub fn threads(mut self, threads: NonZeroUsize) -> Self {
+        self.config.threads = Some(threads);
+        self
+    }
+
+    /// See [`ProcessConfig::parallel_moves`]
+    pub fn parallel_moves(mut self, parallel_moves: bool) -> Self {
+        self.config.parallel_moves = parallel_moves;
+        self
+    }
+
+    /// See [`ProcessConfig::manifest`]
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.manifest = Some(path.into());
+        self
+    }
+
+    /// See [`ProcessConfig::header_datetime`]
+    #[cfg(feature = "exif")]
+    pub fn header_datetime(mut self, header_datetime: bool) -> Self {
+        self.config.header_datetime = header_datetime;
+        self
+    }
+
+    /// See [`ProcessConfig::read_gps`]
+    #[cfg(feature = "exif")]
+    pub fn read_gps(mut self, read_gps: bool) -> Self {
+        self.config.read_gps = read_gps;
+        self
+    }
+}
@@ -0,0 +1,177 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::report::MatchReport;
+
+pub type Result<T, E = IxMatchError> = std::result::Result<T, E>;
+
+/// Errors returned by the ix-match library.
+///
+/// Non-exhaustive since some errors depend on crate features.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum IxMatchError {
+    #[error("RGB and NIR directories do not exist")]
+    RgbAndNirDirsNotFound,
+
+    #[error("RGB directory does not exist")]
+    RgbDirNotFound,
+
+    #[error("NIR directory does not exist")]
+    NirDirNotFound,
+
+    #[error(
+        "{} looks like it was already processed (found {}), use force to process it again",
+        dir.display(),
+        found.display()
+    )]
+    AlreadyProcessed { dir: PathBuf, found: PathBuf },
+
+    #[error("Listed file {} does not exist", path.display())]
+    ListedFileNotFound { path: PathBuf },
+
+    #[error("{} is not an ix-match manifest, or one from another version", path.display())]
+    InvalidManifest { path: PathBuf },
+
+    #[error("{} is not an ix-match scan cache, or one from another version", path.display())]
+    InvalidCache { path: PathBuf },
+
+    #[error("Invalid file name {}", path.display())]
+    InvalidFileName { path: PathBuf },
+
+    #[error("Failed to parse datetime from {}", path.display())]
+    ParseDatetime {
+        path: PathBuf,
+        #[source]
+        source: chrono::ParseError,
+    },
+
+    #[error("Invalid datetime regex '{pattern}'")]
+    InvalidDatetimeRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    #[error("Invalid band regex '{pattern}'")]
+    InvalidBandRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    #[error("Failed to get file metadata for {}", path.display())]
+    Metadata {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to move {} to {}", from.display(), to.display())]
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to copy {} to {}", from.display(), to.display())]
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error(
+        "Destination {} already exists, refusing to overwrite with {}",
+        to.display(),
+        from.display()
+    )]
+    DestinationExists { from: PathBuf, to: PathBuf },
+
+    #[error(
+        "Offset between paired files changed direction at {} and {}",
+        rgb.display(),
+        nir.display()
+    )]
+    NonMonotonicOrder { rgb: PathBuf, nir: PathBuf },
+
+    #[error("{rgb} RGB and {nir} NIR files were not matched")]
+    Unmatched {
+        rgb: usize,
+        nir: usize,
+        /// The report of the run, which otherwise completed as normal
+        report: Box<MatchReport>,
+    },
+
+    #[error(
+        "Only {matched} of {possible} files were matched, below the minimum match rate of {min_rate}"
+    )]
+    LowMatchRate {
+        matched: usize,
+        /// Files in the smaller band, which could all have been matched
+        possible: usize,
+        min_rate: f64,
+    },
+
+    #[error(
+        "{rgb} RGB and {nir} NIR files, more than {max_skew} times as many in one band as the other"
+    )]
+    CountSkew {
+        rgb: usize,
+        nir: usize,
+        max_skew: f64,
+    },
+
+    #[error(
+        "match threshold of {} ms is more than {max_ratio} of the {} ms between frames",
+        .threshold.as_millis(),
+        .interval.as_millis()
+    )]
+    ThresholdExceedsInterval {
+        threshold: Duration,
+        /// Median time between frames of the band firing fastest
+        interval: Duration,
+        max_ratio: f64,
+    },
+
+    #[error("{error}, and undoing the changes made so far also failed: {rollback}")]
+    RollbackFailed {
+        /// The error that stopped the run
+        error: Box<IxMatchError>,
+        /// The error hit while putting files back
+        rollback: Box<IxMatchError>,
+    },
+
+    #[error("No files in collection")]
+    EmptyCollection,
+
+    #[error("Invalid exclude pattern '{pattern}'")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+
+    #[cfg(feature = "verify")]
+    #[error("Copy of {} at {} does not match the original, removed it", from.display(), to.display())]
+    CopyMismatch { from: PathBuf, to: PathBuf },
+
+    #[cfg(feature = "tar")]
+    #[error("{} is not in {}", path.display(), archive.display())]
+    EntryNotInArchive { archive: PathBuf, path: PathBuf },
+
+    #[cfg(feature = "watch")]
+    #[error("Failed to watch for new files")]
+    Watch(#[from] notify::Error),
+
+    #[error(transparent)]
+    Glob(#[from] globwalker::GlobError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
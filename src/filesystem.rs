@@ -1,26 +1,51 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
 
-use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use globwalker::{FileType, GlobWalkerBuilder};
 
+use crate::error::{IxMatchError, Result};
+
+/// Which directory [`find_dir_by_pattern_with_policy`] picks when several match the pattern.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultipleDirPolicy {
+    /// Pick none of them.
+    #[default]
+    Fail,
+    /// Pick the most recently modified directory.
+    Newest,
+    /// Pick the directory with the most files in it, counting subdirectories.
+    MostFiles,
+}
+
 pub fn find_dir_by_pattern(
-    base_dir: &PathBuf,
+    base_dir: &Path,
     dir_pattern: &str,
     case_sensitive: bool,
+    follow_links: bool,
 ) -> Option<PathBuf> {
-    let walker = GlobWalkerBuilder::from_patterns(base_dir, &[dir_pattern])
-        .case_insensitive(!case_sensitive)
-        .follow_links(true)
-        .max_depth(1)
-        .file_type(FileType::DIR)
-        .build()
-        .expect("Failed to create glob walker");
+    find_dir_by_pattern_with_policy(
+        base_dir,
+        dir_pattern,
+        case_sensitive,
+        follow_links,
+        MultipleDirPolicy::Fail,
+    )
+}
 
-    let mut dirs: Vec<_> = walker
-        .filter_map(Result::ok)
-        .map(|entry| entry.into_path())
-        .collect();
+/// Like [`find_dir_by_pattern`], using `policy` to pick between several matching directories.
+pub fn find_dir_by_pattern_with_policy(
+    base_dir: &Path,
+    dir_pattern: &str,
+    case_sensitive: bool,
+    follow_links: bool,
+    policy: MultipleDirPolicy,
+) -> Option<PathBuf> {
+    let mut dirs = find_dirs_by_pattern(base_dir, dir_pattern, case_sensitive, follow_links);
 
     match dirs.len() {
         1 => dirs.pop(),
@@ -32,55 +57,765 @@ pub fn find_dir_by_pattern(
             None
         }
         _ => {
-            println!(
-                "Multiple directories matching '{}' found in {:?}",
-                dir_pattern, base_dir
-            );
-            None
+            let picked = match policy {
+                MultipleDirPolicy::Fail => None,
+                MultipleDirPolicy::Newest => dirs
+                    .into_iter()
+                    .max_by_key(|dir| dir.metadata().and_then(|m| m.modified()).ok()),
+                MultipleDirPolicy::MostFiles => dirs
+                    .into_iter()
+                    .max_by_key(|dir| count_files(dir, follow_links)),
+            };
+            match &picked {
+                Some(dir) => println!(
+                    "Multiple directories matching '{}' found in {:?}, using {:?}",
+                    dir_pattern, base_dir, dir
+                ),
+                None => println!(
+                    "Multiple directories matching '{}' found in {:?}",
+                    dir_pattern, base_dir
+                ),
+            }
+            picked
         }
     }
 }
 
-pub fn find_files(base_dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
-    let canonical_base_dir = base_dir
-        .canonicalize()
-        .context("Failed to canonicalize base dir")?;
-    let pattern = format!("**/*.{}", extension);
+/// Find the RGB and NIR directories in `base_dir` with one `pattern` matching both, e.g.
+/// `CAMERA_*`, telling them apart by their names ending in `rgb_suffix` or `nir_suffix`.
+/// Matching directories with neither suffix are ignored.
+pub fn find_band_dirs(
+    base_dir: &Path,
+    pattern: &str,
+    rgb_suffix: &str,
+    nir_suffix: &str,
+    case_sensitive: bool,
+    follow_links: bool,
+) -> Option<(PathBuf, PathBuf)> {
+    let dirs = find_dirs_by_pattern(base_dir, pattern, case_sensitive, follow_links);
+    let find_band = |band: &str, suffix: &str| {
+        let suffix = if case_sensitive {
+            suffix.to_string()
+        } else {
+            suffix.to_lowercase()
+        };
+        let mut found: Vec<&PathBuf> = dirs
+            .iter()
+            .filter(|dir| {
+                let name = dir.file_name().unwrap_or_default().to_string_lossy();
+                if case_sensitive {
+                    name.ends_with(&suffix)
+                } else {
+                    name.to_lowercase().ends_with(&suffix)
+                }
+            })
+            .collect();
+        match found.len() {
+            1 => found.pop().cloned(),
+            0 => {
+                println!(
+                    "No {} directory matching '{}' and ending in '{}' found in {:?}",
+                    band, pattern, suffix, base_dir
+                );
+                None
+            }
+            _ => {
+                println!(
+                    "Multiple {} directories matching '{}' and ending in '{}' found in {:?}",
+                    band, pattern, suffix, base_dir
+                );
+                None
+            }
+        }
+    };
+    Some((find_band("RGB", rgb_suffix)?, find_band("NIR", nir_suffix)?))
+}
 
-    let walker = GlobWalkerBuilder::from_patterns(canonical_base_dir, &[pattern])
-        .follow_links(true)
+/// Number of files anywhere below `dir`.
+fn count_files(dir: &Path, follow_links: bool) -> usize {
+    GlobWalkerBuilder::from_patterns(dir, &["**"])
+        .follow_links(follow_links)
         .file_type(FileType::FILE)
         .build()
+        .map(|walker| walker.filter_map(Result::ok).count())
+        .unwrap_or(0)
+}
+
+/// Find every directory directly in `base_dir` matching `dir_pattern`, sorted by path, so the
+/// caller can decide what to do when there is more than one.
+pub fn find_dirs_by_pattern(
+    base_dir: &Path,
+    dir_pattern: &str,
+    case_sensitive: bool,
+    follow_links: bool,
+) -> Vec<PathBuf> {
+    find_dirs_within(base_dir, dir_pattern, case_sensitive, 1, follow_links)
+}
+
+/// Find the directories matching `dir_pattern` up to `max_depth` levels below `base_dir`.
+fn find_dirs_within(
+    base_dir: &Path,
+    dir_pattern: &str,
+    case_sensitive: bool,
+    max_depth: usize,
+    follow_links: bool,
+) -> Vec<PathBuf> {
+    let walker = GlobWalkerBuilder::from_patterns(base_dir, &[dir_pattern])
+        .case_insensitive(!case_sensitive)
+        .follow_links(follow_links)
+        .max_depth(max_depth)
+        .file_type(FileType::DIR)
+        .build()
         .expect("Failed to create glob walker");
 
+    let mut dirs: Vec<_> = walker
+        .filter_map(Result::ok)
+        .map(|entry| entry.into_path())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Find every directory under `base_dir` holding exactly one RGB and one NIR directory.
+///
+/// Returns the `(rgb_dir, nir_dir)` pairs sorted by path. Directories where either band is
+/// missing or ambiguous are skipped.
+pub fn find_session_dirs(
+    base_dir: &Path,
+    rgb_pattern: &str,
+    nir_pattern: &str,
+    case_sensitive: bool,
+    follow_links: bool,
+) -> Vec<(PathBuf, PathBuf)> {
+    let rgb_pattern_any_depth = format!("**/{}", rgb_pattern);
+    let mut session_dirs: Vec<PathBuf> = find_dirs_within(
+        base_dir,
+        &rgb_pattern_any_depth,
+        case_sensitive,
+        usize::MAX,
+        follow_links,
+    )
+    .iter()
+    .filter_map(|rgb_dir| rgb_dir.parent().map(Path::to_path_buf))
+    .collect();
+    session_dirs.dedup();
+
+    session_dirs
+        .iter()
+        .filter_map(|session_dir| {
+            let mut rgb_dirs =
+                find_dirs_within(session_dir, rgb_pattern, case_sensitive, 1, follow_links);
+            let mut nir_dirs =
+                find_dirs_within(session_dir, nir_pattern, case_sensitive, 1, follow_links);
+            if rgb_dirs.len() == 1 && nir_dirs.len() == 1 {
+                Some((rgb_dirs.pop()?, nir_dirs.pop()?))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Find all files with `extension` under `base_dir`.
+///
+/// `max_depth` limits how far down the tree to look, where files directly in `base_dir` are at
+/// depth 1. `None` searches the whole tree.
+///
+/// Files are skipped if their path relative to `base_dir`, or that of any directory they are
+/// in, matches one of the `exclude` globs. So `thumbnails` skips everything in the top level
+/// `thumbnails/` directory, and `**/thumbnails` does so at any depth.
+///
+/// With `follow_links`, symlinked directories are searched as if they were real ones.
+pub fn find_files(
+    base_dir: &Path,
+    extension: &str,
+    max_depth: Option<usize>,
+    exclude: &[String],
+    follow_links: bool,
+) -> Result<Vec<PathBuf>> {
+    let canonical_base_dir = base_dir.canonicalize()?;
+    let pattern = format!("**/*.{}", extension);
+    let exclude = build_glob_set(exclude)?;
+
+    let walker = GlobWalkerBuilder::from_patterns(&canonical_base_dir, &[pattern])
+        .follow_links(follow_links)
+        .max_depth(max_depth.unwrap_or(usize::MAX))
+        .file_type(FileType::FILE)
+        .build()?;
+
     let files: Vec<_> = walker
         .filter_map(Result::ok)
         .map(|entry| entry.into_path())
+        .filter(|path| !is_excluded(path, &canonical_base_dir, &exclude))
         .collect();
 
     Ok(files)
 }
 
-pub fn move_files(paths: Vec<PathBuf>, dir: &Path, verbose: bool) -> Result<()> {
-    // Move files to 'unmatched' directory
-    for path in paths {
-        let dest = dir.join(
-            path.file_name()
-                .context("Failed to get file destination name")?,
-        );
-        if verbose {
-            println!("{} -> {}", path.display(), dest.display());
+/// Keep only the `paths` last modified at or after `since`.
+pub(crate) fn retain_modified_since(paths: &mut Vec<PathBuf>, since: SystemTime) -> Result<()> {
+    let mut kept = Vec::with_capacity(paths.len());
+    for path in paths.drain(..) {
+        if fs::metadata(&path)?.modified()? >= since {
+            kept.push(path);
+        }
+    }
+    *paths = kept;
+    Ok(())
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|source| IxMatchError::InvalidPattern {
+                pattern: pattern.clone(),
+                source,
+            })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|source| IxMatchError::InvalidPattern {
+            pattern: patterns.join(", "),
+            source,
+        })
+}
+
+fn is_excluded(path: &Path, base_dir: &Path, exclude: &GlobSet) -> bool {
+    if exclude.is_empty() {
+        return false;
+    }
+    let Ok(relative) = path.strip_prefix(base_dir) else {
+        return false;
+    };
+    relative
+        .ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .any(|p| exclude.is_match(p))
+}
+
+/// Little and big endian TIFF headers, and the Phase One IIQ header
+const IIQ_MAGIC: [&[u8; 4]; 3] = [b"II*\0", b"MM\0*", b"IIII"];
+
+/// Check whether the file starts with the magic bytes of a TIFF based IIQ file.
+pub(crate) fn has_iiq_magic(path: &Path) -> Result<bool> {
+    let mut magic = [0; 4];
+    let mut file = fs::File::open(path)?;
+    match file.read_exact(&mut magic) {
+        Ok(()) => Ok(IIQ_MAGIC.contains(&&magic)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// What to do when a file being moved would land on an existing file at the destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Fail with an error, leaving both files untouched.
+    #[default]
+    Error,
+    /// Leave the source file where it is.
+    Skip,
+    /// Append a numeric suffix to the file stem, e.g. `name-1.iiq`. Unlike `_1`, it isn't taken
+    /// for a frame index, see [`IIQFile::frame_index`](crate::IIQFile::frame_index).
+    Rename,
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn next_free_path(dest: &Path) -> Result<PathBuf> {
+    let invalid_name = || IxMatchError::InvalidFileName {
+        path: dest.to_path_buf(),
+    };
+    let parent = dest.parent().ok_or_else(invalid_name)?;
+    let stem = dest.file_stem().ok_or_else(invalid_name)?.to_string_lossy();
+    let extension = dest.extension().map(|e| e.to_string_lossy());
+
+    let mut n = 1;
+    loop {
+        let name = match &extension {
+            Some(ext) => format!("{}-{}.{}", stem, n, ext),
+            None => format!("{}-{}", stem, n),
+        };
+        let candidate = parent.join(name);
+        if !candidate.exists() {
+            return Ok(candidate);
         }
-        fs::rename(&path, &dest)?;
+        n += 1;
     }
+}
+
+/// Whether files are moved or copied to their destination.
+///
+/// Non-exhaustive since some operations depend on crate features.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileOp {
+    #[default]
+    Move,
+    /// Copy files, leaving the originals in place. Copies keep the original modification time.
+    Copy,
+    /// Like [`Copy`](Self::Copy), then check the copy's checksum against the original's, e.g.
+    /// when copying to a network share. Copies that don't match are removed and fail with
+    /// [`IxMatchError::CopyMismatch`].
+    #[cfg(feature = "verify")]
+    VerifiedCopy,
+}
 
+/// A change made to the file system, see [`Journal`].
+#[derive(Debug)]
+enum Change {
+    Transfer {
+        file_op: FileOp,
+        from: PathBuf,
+        to: PathBuf,
+    },
+    CreateDir(PathBuf),
+    /// A file written or removed, with what it held before if it existed
+    Replace {
+        path: PathBuf,
+        previous: Option<Vec<u8>>,
+    },
+}
+
+/// Record of the files moved or copied and the directories created so far, so they can be put
+/// back if a later step fails.
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    changes: Vec<Change>,
+}
+
+impl Journal {
+    /// The files moved or copied so far, in order, with how and where to.
+    pub(crate) fn transfers(&self) -> impl Iterator<Item = (FileOp, &Path, &Path)> {
+        self.changes.iter().filter_map(|change| match change {
+            Change::Transfer { file_op, from, to } => {
+                Some((*file_op, from.as_path(), to.as_path()))
+            }
+            Change::CreateDir(_) | Change::Replace { .. } => None,
+        })
+    }
+
+    /// Where each file moved or copied so far ended up, by where it was first found.
+    pub(crate) fn destinations(&self) -> HashMap<&Path, &Path> {
+        let mut destinations: HashMap<&Path, &Path> = HashMap::new();
+        let mut origins: HashMap<&Path, &Path> = HashMap::new();
+        for change in &self.changes {
+            if let Change::Transfer { from, to, .. } = change {
+                // Files moved more than once, e.g. when flattening, are found by their origin
+                let origin = origins.remove(from.as_path()).unwrap_or(from);
+                destinations.insert(origin, to);
+                origins.insert(to, origin);
+            }
+        }
+        destinations
+    }
+
+    /// Create `dir` and any missing parents, recording the ones that did not exist.
+    pub(crate) fn create_dir_all(&mut self, dir: &Path) -> Result<()> {
+        let mut missing: Vec<&Path> = dir.ancestors().take_while(|d| !d.exists()).collect();
+        fs::create_dir_all(dir)?;
+        // Parents first, so the deepest directory is removed first when rolling back
+        missing.reverse();
+        for dir in missing {
+            self.changes.push(Change::CreateDir(dir.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    /// Write `contents` to the file at `path`, recording what it held before, if anything.
+    pub(crate) fn write_file(&mut self, path: &Path, contents: &str) -> Result<()> {
+        let previous = fs::read(path).ok();
+        fs::write(path, contents)?;
+        self.changes.push(Change::Replace {
+            path: path.to_path_buf(),
+            previous,
+        });
+        Ok(())
+    }
+
+    /// Remove the file at `path`, if there is one, recording what it held.
+    pub(crate) fn remove_file(&mut self, path: &Path) -> Result<()> {
+        let Ok(previous) = fs::read(path) else {
+            return Ok(());
+        };
+        fs::remove_file(path)?;
+        self.changes.push(Change::Replace {
+            path: path.to_path_buf(),
+            previous: Some(previous),
+        });
+        Ok(())
+    }
+
+    /// Undo the recorded changes, latest first. Moved files are moved back, copies are
+    /// removed, written files are put back as they were, and created directories are removed
+    /// if they are empty.
+    pub(crate) fn roll_back(&mut self) -> Result<()> {
+        while let Some(change) = self.changes.pop() {
+            match change {
+                Change::Transfer {
+                    file_op: FileOp::Move,
+                    from,
+                    to,
+                } => fs::rename(&to, &from).map_err(|source| IxMatchError::Move {
+                    from: to.clone(),
+                    to: from.clone(),
+                    source,
+                })?,
+                // Copies
+                Change::Transfer { to, .. } => fs::remove_file(&to)?,
+                Change::CreateDir(dir) => {
+                    if dir.read_dir()?.next().is_none() {
+                        fs::remove_dir(&dir)?;
+                    }
+                }
+                Change::Replace {
+                    path,
+                    previous: Some(previous),
+                } => fs::write(&path, previous)?,
+                Change::Replace { path, .. } => fs::remove_file(&path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Journal {
+    /// Move `path` into `dir`, renaming it if a different file with the same name is already
+    /// there, and return where it ended up.
+    pub(crate) fn move_renaming(
+        &mut self,
+        path: &Path,
+        dir: &Path,
+        verbose: bool,
+    ) -> Result<PathBuf> {
+        match transfer_file(path, dir, FileOp::Move, CollisionPolicy::Rename, verbose)? {
+            Some(dest) => {
+                self.changes.push(Change::Transfer {
+                    file_op: FileOp::Move,
+                    from: path.to_path_buf(),
+                    to: dest.clone(),
+                });
+                Ok(dest)
+            }
+            None => Ok(path.to_path_buf()),
+        }
+    }
+}
+
+/// Move each file in `paths` into `dir`, resolving name clashes with `collision_policy`.
+pub fn move_files(
+    paths: Vec<PathBuf>,
+    dir: &Path,
+    collision_policy: CollisionPolicy,
+    verbose: bool,
+) -> Result<()> {
+    transfer_files(
+        paths,
+        dir,
+        TransferOptions::new(FileOp::Move, collision_policy, verbose),
+        &mut Journal::default(),
+        &mut || {},
+    )
+}
+
+/// Copy each file in `paths` into `dir`, resolving name clashes with `collision_policy`.
+pub fn copy_files(
+    paths: Vec<PathBuf>,
+    dir: &Path,
+    collision_policy: CollisionPolicy,
+    verbose: bool,
+) -> Result<()> {
+    transfer_files(
+        paths,
+        dir,
+        TransferOptions::new(FileOp::Copy, collision_policy, verbose),
+        &mut Journal::default(),
+        &mut || {},
+    )
+}
+
+/// Where the sorted files are written, to send them somewhere other than the band directories,
+/// such as an object store or an archive. See
+/// [`process_images_with_destination`](crate::process_images_with_destination).
+pub trait Destination {
+    /// Write the file at `src` to `rel`, its path relative to the directory holding the band
+    /// directories, e.g. `CAMERA_RGB/unmatched/240101_120000000.iiq`.
+    fn put(&self, src: &Path, rel: &Path) -> Result<()>;
+}
+
+/// Writes the files under a local directory, the way [`move_files`] and [`copy_files`] do.
+/// Rooted at the directory holding the band directories, files are sorted in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalDestination {
+    pub root: PathBuf,
+    pub file_op: FileOp,
+    pub collision_policy: CollisionPolicy,
+    /// Print each file as it is written
+    pub verbose: bool,
+}
+
+impl LocalDestination {
+    /// Move files under `root`, failing if one would land on an existing file.
+    pub fn new(root: &Path) -> Self {
+        LocalDestination {
+            root: root.to_path_buf(),
+            file_op: FileOp::Move,
+            collision_policy: CollisionPolicy::Error,
+            verbose: false,
+        }
+    }
+}
+
+impl Destination for LocalDestination {
+    fn put(&self, src: &Path, rel: &Path) -> Result<()> {
+        let dest = self.root.join(rel);
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        transfer_file_to(src, dest, self.file_op, self.collision_policy, self.verbose)?;
+        Ok(())
+    }
+}
+
+/// How [`transfer_files`] moves or copies the files.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TransferOptions {
+    pub(crate) file_op: FileOp,
+    pub(crate) collision_policy: CollisionPolicy,
+    pub(crate) verbose: bool,
+    /// How many files to move or copy at once
+    pub(crate) threads: usize,
+}
+
+impl TransferOptions {
+    /// One file at a time
+    pub(crate) fn new(file_op: FileOp, collision_policy: CollisionPolicy, verbose: bool) -> Self {
+        TransferOptions {
+            file_op,
+            collision_policy,
+            verbose,
+            threads: 1,
+        }
+    }
+}
+
+/// Move or copy each file in `paths` into `dir`, recording each transfer in `journal` and
+/// calling `on_file` after each one is handled.
+///
+/// With more than one of `options.threads`, the files are split between that many threads,
+/// which helps on network storage where each transfer waits on the server. Each thread stops
+/// at its first error, and the error returned is the one for the first failing path, while the
+/// transfers that did happen are all recorded to be rolled back.
+pub(crate) fn transfer_files(
+    paths: Vec<PathBuf>,
+    dir: &Path,
+    options: TransferOptions,
+    journal: &mut Journal,
+    on_file: &mut dyn FnMut(),
+) -> Result<()> {
+    let TransferOptions {
+        file_op,
+        collision_policy,
+        verbose,
+        threads,
+    } = options;
+    let transfer = |path: &PathBuf| transfer_file(path, dir, file_op, collision_policy, verbose);
+    // Renaming looks for a free name in `dir`, which files with the same name would race for
+    let mut names = HashSet::new();
+    let unique_names = paths.iter().all(|path| names.insert(path.file_name()));
+    if threads <= 1 || paths.len() <= 1 || !unique_names {
+        for path in paths {
+            if let Some(dest) = transfer(&path)? {
+                journal.changes.push(Change::Transfer {
+                    file_op,
+                    from: path,
+                    to: dest,
+                });
+            }
+            on_file();
+        }
+        return Ok(());
+    }
+
+    let chunk_size = paths.len().div_ceil(threads);
+    let chunk_results: Vec<Vec<Result<Option<PathBuf>>>> = thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut results = Vec::with_capacity(chunk.len());
+                    for path in chunk {
+                        let result = transfer(path);
+                        let failed = result.is_err();
+                        results.push(result);
+                        if failed {
+                            break;
+                        }
+                    }
+                    results
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("File moving thread panicked"))
+            .collect()
+    });
+
+    let mut first_error = None;
+    for (chunk, results) in paths.chunks(chunk_size).zip(chunk_results) {
+        for (path, result) in chunk.iter().zip(results) {
+            match result {
+                Ok(Some(dest)) => journal.changes.push(Change::Transfer {
+                    file_op,
+                    from: path.clone(),
+                    to: dest,
+                }),
+                Ok(None) => {}
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                    continue;
+                }
+            }
+            on_file();
+        }
+    }
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Move or copy `path` into `dir`, returning where it ended up, or `None` if it was left alone.
+fn transfer_file(
+    path: &Path,
+    dir: &Path,
+    file_op: FileOp,
+    collision_policy: CollisionPolicy,
+    verbose: bool,
+) -> Result<Option<PathBuf>> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| IxMatchError::InvalidFileName {
+            path: path.to_path_buf(),
+        })?;
+    transfer_file_to(
+        path,
+        dir.join(file_name),
+        file_op,
+        collision_policy,
+        verbose,
+    )
+}
+
+/// Move or copy `path` to `dest`, returning where it ended up, or `None` if it was left alone.
+fn transfer_file_to(
+    path: &Path,
+    mut dest: PathBuf,
+    file_op: FileOp,
+    collision_policy: CollisionPolicy,
+    verbose: bool,
+) -> Result<Option<PathBuf>> {
+    if dest.exists() {
+        if is_same_file(path, &dest) {
+            // Already in place
+            return Ok(None);
+        }
+        match collision_policy {
+            CollisionPolicy::Error => {
+                return Err(IxMatchError::DestinationExists {
+                    from: path.to_path_buf(),
+                    to: dest,
+                });
+            }
+            CollisionPolicy::Skip => {
+                if verbose {
+                    println!("Skipping {}, {} exists", path.display(), dest.display());
+                }
+                return Ok(None);
+            }
+            CollisionPolicy::Rename => dest = next_free_path(&dest)?,
+        }
+    }
+    if verbose {
+        println!("{} -> {}", path.display(), dest.display());
+    }
+    match file_op {
+        FileOp::Move => fs::rename(path, &dest).map_err(|source| IxMatchError::Move {
+            from: path.to_path_buf(),
+            to: dest.clone(),
+            source,
+        })?,
+        FileOp::Copy => copy_file(path, &dest).map_err(|source| IxMatchError::Copy {
+            from: path.to_path_buf(),
+            to: dest.clone(),
+            source,
+        })?,
+        #[cfg(feature = "verify")]
+        FileOp::VerifiedCopy => {
+            copy_file(path, &dest).map_err(|source| IxMatchError::Copy {
+                from: path.to_path_buf(),
+                to: dest.clone(),
+                source,
+            })?;
+            verify_copy(path, &dest)?;
+        }
+    }
+    Ok(Some(dest))
+}
+
+/// Check that `dest` has the same checksum as `src`, removing it if not.
+#[cfg(feature = "verify")]
+fn verify_copy(src: &Path, dest: &Path) -> Result<()> {
+    if checksum(src)? != checksum(dest)? {
+        fs::remove_file(dest)?;
+        return Err(IxMatchError::CopyMismatch {
+            from: src.to_path_buf(),
+            to: dest.to_path_buf(),
+        });
+    }
     Ok(())
 }
 
+/// CRC32 of the contents of the file at `path`
+#[cfg(feature = "verify")]
+fn checksum(path: &Path) -> io::Result<u32> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.finalize()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+/// Copy `src` to `dest`, carrying over the modification time, which `fs::copy` does not do
+/// consistently across platforms.
+fn copy_file(src: &Path, dest: &Path) -> std::io::Result<()> {
+    fs::copy(src, dest)?;
+    let modified = src.metadata()?.modified()?;
+    fs::File::options()
+        .write(true)
+        .open(dest)?
+        .set_times(fs::FileTimes::new().set_modified(modified))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
+    use std::time::{Duration, SystemTime};
     use tempfile::TempDir;
 
     #[test]
@@ -91,26 +826,136 @@ mod tests {
         fs::create_dir(base_path.join("test_dir_123")).unwrap();
         fs::create_dir(base_path.join("another_dir_456")).unwrap();
 
-        let result = find_dir_by_pattern(&base_path, "test_dir_*", true);
+        let result = find_dir_by_pattern(&base_path, "test_dir_*", true, true);
         assert!(result.is_some());
         assert_eq!(result.unwrap().file_name().unwrap(), "test_dir_123");
 
-        let no_match = find_dir_by_pattern(&base_path, "nonexistent_*", true);
+        let no_match = find_dir_by_pattern(&base_path, "nonexistent_*", true, true);
         assert!(no_match.is_none());
 
         fs::create_dir(base_path.join("CAMERA_RGB")).unwrap();
-        let result = find_dir_by_pattern(&base_path, "C*_RGB", true);
+        let result = find_dir_by_pattern(&base_path, "C*_RGB", true, true);
         assert!(result.is_some());
         assert_eq!(result.unwrap().file_name().unwrap(), "CAMERA_RGB");
 
         fs::create_dir(base_path.join("camera_nir")).unwrap();
-        let result = find_dir_by_pattern(&base_path, "CAMERA_NIR", true);
+        let result = find_dir_by_pattern(&base_path, "CAMERA_NIR", true, true);
         assert!(result.is_none());
-        let result = find_dir_by_pattern(&base_path, "CAMERA_NIR", false);
+        let result = find_dir_by_pattern(&base_path, "CAMERA_NIR", false, true);
         assert!(result.is_some());
         assert_eq!(result.unwrap().file_name().unwrap(), "camera_nir");
     }
 
+    #[test]
+    fn test_find_dirs_by_pattern() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        fs::create_dir(base_path.join("CAMERA_RGB")).unwrap();
+        fs::create_dir(base_path.join("CAMERA_RGB_backup")).unwrap();
+        fs::create_dir(base_path.join("CAMERA_NIR")).unwrap();
+
+        let dirs = find_dirs_by_pattern(base_path, "CAMERA_RGB*", true, true);
+        assert_eq!(
+            dirs,
+            vec![
+                base_path.join("CAMERA_RGB"),
+                base_path.join("CAMERA_RGB_backup")
+            ]
+        );
+        assert_eq!(
+            find_dir_by_pattern(base_path, "CAMERA_RGB*", true, true),
+            None
+        );
+        assert!(find_dirs_by_pattern(base_path, "nonexistent_*", true, true).is_empty());
+    }
+
+    #[test]
+    fn test_find_band_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        fs::create_dir(base_path.join("CAMERA_RGB")).unwrap();
+        fs::create_dir(base_path.join("CAMERA_NIR")).unwrap();
+        fs::create_dir(base_path.join("CAMERA_notes")).unwrap();
+
+        assert_eq!(
+            find_band_dirs(base_path, "CAMERA_*", "_RGB", "_NIR", true, true),
+            Some((base_path.join("CAMERA_RGB"), base_path.join("CAMERA_NIR")))
+        );
+        assert_eq!(
+            find_band_dirs(base_path, "camera_*", "_rgb", "_nir", false, true),
+            Some((base_path.join("CAMERA_RGB"), base_path.join("CAMERA_NIR")))
+        );
+        assert_eq!(
+            find_band_dirs(base_path, "CAMERA_*", "_RGB", "_IR", true, true),
+            None
+        );
+
+        // Which one would be ambiguous
+        fs::create_dir(base_path.join("CAMERA_OLD_RGB")).unwrap();
+        assert_eq!(
+            find_band_dirs(base_path, "CAMERA_*", "_RGB", "_NIR", true, true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_dir_by_pattern_with_policy() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+        let old_dir = base_path.join("CAMERA_RGB");
+        let new_dir = base_path.join("CAMERA_RGB_backup");
+        fs::create_dir(&old_dir).unwrap();
+        fs::create_dir(&new_dir).unwrap();
+        fs::write(old_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(old_dir.join("210101_120001000.iiq"), "content").unwrap();
+        // Adding the files touched the directory, so backdate it afterwards
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::open(&old_dir)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+
+        let find =
+            |policy| find_dir_by_pattern_with_policy(base_path, "CAMERA_RGB*", true, true, policy);
+        assert_eq!(find(MultipleDirPolicy::Fail), None);
+        assert_eq!(find(MultipleDirPolicy::Newest), Some(new_dir));
+        assert_eq!(find(MultipleDirPolicy::MostFiles), Some(old_dir));
+    }
+
+    #[test]
+    fn test_find_session_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        for session in ["240101", "240102", "archive/240103"] {
+            fs::create_dir_all(base_path.join(session).join("CAMERA_RGB")).unwrap();
+            fs::create_dir_all(base_path.join(session).join("CAMERA_NIR")).unwrap();
+        }
+        // Missing its NIR directory
+        fs::create_dir_all(base_path.join("240104/CAMERA_RGB")).unwrap();
+
+        let sessions = find_session_dirs(base_path, "CAMERA_RGB", "CAMERA_NIR", true, true);
+        assert_eq!(
+            sessions,
+            vec![
+                (
+                    base_path.join("240101/CAMERA_RGB"),
+                    base_path.join("240101/CAMERA_NIR")
+                ),
+                (
+                    base_path.join("240102/CAMERA_RGB"),
+                    base_path.join("240102/CAMERA_NIR")
+                ),
+                (
+                    base_path.join("archive/240103/CAMERA_RGB"),
+                    base_path.join("archive/240103/CAMERA_NIR")
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn test_find_files() {
         let temp_dir = TempDir::new().unwrap();
@@ -120,10 +965,10 @@ mod tests {
         fs::write(base_path.join("test2.txt"), "content").unwrap();
         fs::write(base_path.join("test3.doc"), "content").unwrap();
 
-        let txt_files = find_files(base_path, "txt").unwrap();
+        let txt_files = find_files(base_path, "txt", None, &[], true).unwrap();
         assert_eq!(txt_files.len(), 2);
 
-        let doc_files = find_files(base_path, "doc").unwrap();
+        let doc_files = find_files(base_path, "doc", None, &[], true).unwrap();
         assert_eq!(doc_files.len(), 1);
     }
 
@@ -140,13 +985,130 @@ mod tests {
         fs::write(base_path.join("test1.txt"), "content").unwrap();
         fs::write(base_sub_path.join("test3.txt"), "content").unwrap();
 
-        let iiq_files = find_files(base_path, "iiq").unwrap();
+        let iiq_files = find_files(base_path, "iiq", None, &[], true).unwrap();
         assert_eq!(iiq_files.len(), 3);
 
-        let txt_files = find_files(base_path, "txt").unwrap();
+        let txt_files = find_files(base_path, "txt", None, &[], true).unwrap();
         assert_eq!(txt_files.len(), 2);
     }
 
+    #[test]
+    fn test_find_files_max_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+        let deep_path = base_path.join("subdir").join("deeper");
+        fs::create_dir_all(&deep_path).unwrap();
+
+        fs::write(base_path.join("test1.iiq"), "content").unwrap();
+        fs::write(base_path.join("subdir").join("test2.iiq"), "content").unwrap();
+        fs::write(deep_path.join("test3.iiq"), "content").unwrap();
+
+        assert_eq!(
+            find_files(base_path, "iiq", Some(1), &[], true)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let mut names: Vec<_> = find_files(base_path, "iiq", Some(2), &[], true)
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["test1.iiq", "test2.iiq"]);
+
+        assert_eq!(
+            find_files(base_path, "iiq", None, &[], true).unwrap().len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_find_files_exclude() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+        let thumbnails = base_path.join("thumbnails");
+        let nested_thumbnails = base_path.join("subdir").join("thumbnails");
+        fs::create_dir_all(&thumbnails).unwrap();
+        fs::create_dir_all(&nested_thumbnails).unwrap();
+
+        fs::write(base_path.join("test1.iiq"), "content").unwrap();
+        fs::write(base_path.join("subdir").join("test2.iiq"), "content").unwrap();
+        fs::write(thumbnails.join("test1.iiq"), "content").unwrap();
+        fs::write(nested_thumbnails.join("test2.iiq"), "content").unwrap();
+
+        let files = find_files(base_path, "iiq", None, &["thumbnails".to_string()], true).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files
+            .iter()
+            .all(|p| !p.starts_with(thumbnails.canonicalize().unwrap())));
+
+        let files =
+            find_files(base_path, "iiq", None, &["**/thumbnails".to_string()], true).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files
+            .iter()
+            .all(|p| !p.to_string_lossy().contains("thumbnails")));
+
+        let result = find_files(base_path, "iiq", None, &["[".to_string()], true);
+        assert!(matches!(result, Err(IxMatchError::InvalidPattern { .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_links() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path().join("base");
+        let elsewhere = temp_dir.path().join("elsewhere").join("CAMERA_RGB");
+        fs::create_dir_all(base_path.join("real")).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(base_path.join("real").join("test1.iiq"), "content").unwrap();
+        fs::write(elsewhere.join("test2.iiq"), "content").unwrap();
+        symlink(&elsewhere, base_path.join("CAMERA_RGB")).unwrap();
+
+        assert_eq!(
+            find_files(&base_path, "iiq", None, &[], true)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            find_files(&base_path, "iiq", None, &[], false)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert_eq!(
+            find_dir_by_pattern(&base_path, "CAMERA_RGB", true, true),
+            Some(base_path.join("CAMERA_RGB"))
+        );
+        assert_eq!(
+            find_dir_by_pattern(&base_path, "CAMERA_RGB", true, false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_has_iiq_magic() {
+        let temp_dir = TempDir::new().unwrap();
+        let cases: [(&str, &[u8], bool); 5] = [
+            ("little.iiq", b"II*\0rest", true),
+            ("big.iiq", b"MM\0*rest", true),
+            ("phase_one.iiq", b"IIIIrest", true),
+            ("bogus.iiq", b"content", false),
+            ("short.iiq", b"II", false),
+        ];
+        for (name, content, expected) in cases {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            assert_eq!(has_iiq_magic(&path).unwrap(), expected, "{}", name);
+        }
+    }
+
     #[test]
     fn test_move_files() {
         let temp_dir = TempDir::new().unwrap();
@@ -162,11 +1124,277 @@ mod tests {
             fs::write(path, "content").unwrap();
         }
 
-        move_files(paths, &dest_dir, false).unwrap();
+        move_files(paths, &dest_dir, CollisionPolicy::Error, false).unwrap();
 
         assert!(!source_dir.join("file1.txt").exists());
         assert!(!source_dir.join("file2.txt").exists());
         assert!(dest_dir.join("file1.txt").exists());
         assert!(dest_dir.join("file2.txt").exists());
     }
+
+    fn setup_collision(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&dest_dir).unwrap();
+
+        fs::write(source_dir.join("file1.txt"), "new").unwrap();
+        fs::write(dest_dir.join("file1.txt"), "old").unwrap();
+        (source_dir, dest_dir)
+    }
+
+    #[test]
+    fn test_copy_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::create_dir_all(&dest_dir).unwrap();
+
+        let src = source_dir.join("file1.txt");
+        fs::write(&src, "content").unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+        fs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        copy_files(vec![src.clone()], &dest_dir, CollisionPolicy::Error, false).unwrap();
+
+        let dest = dest_dir.join("file1.txt");
+        assert!(src.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "content");
+
+        let dest_modified = dest.metadata().unwrap().modified().unwrap();
+        let drift = dest_modified
+            .duration_since(modified)
+            .unwrap_or_else(|e| e.duration());
+        assert!(drift < Duration::from_secs(2));
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn test_verified_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("file1.txt");
+        let dest_dir = temp_dir.path().join("dest");
+        fs::create_dir_all(&dest_dir).unwrap();
+        fs::write(&src, "content").unwrap();
+
+        transfer_files(
+            vec![src.clone()],
+            &dest_dir,
+            TransferOptions::new(FileOp::VerifiedCopy, CollisionPolicy::Error, false),
+            &mut Journal::default(),
+            &mut || {},
+        )
+        .unwrap();
+        let dest = dest_dir.join("file1.txt");
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "content");
+
+        // A copy that was corrupted on the way is caught and removed
+        fs::write(&dest, "contemt").unwrap();
+        let err = verify_copy(&src, &dest).unwrap_err();
+        assert!(matches!(err, IxMatchError::CopyMismatch { .. }));
+        assert!(!dest.exists());
+        assert!(src.exists());
+    }
+
+    #[test]
+    fn test_move_files_collision_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let (source_dir, dest_dir) = setup_collision(&temp_dir);
+
+        let paths = vec![source_dir.join("file1.txt")];
+        let result = move_files(paths, &dest_dir, CollisionPolicy::Error, false);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::DestinationExists { .. })
+        ));
+
+        assert!(source_dir.join("file1.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("file1.txt")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_move_files_collision_skip() {
+        let temp_dir = TempDir::new().unwrap();
+        let (source_dir, dest_dir) = setup_collision(&temp_dir);
+
+        let paths = vec![source_dir.join("file1.txt")];
+        move_files(paths, &dest_dir, CollisionPolicy::Skip, false).unwrap();
+
+        assert!(source_dir.join("file1.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("file1.txt")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_move_files_collision_rename() {
+        let temp_dir = TempDir::new().unwrap();
+        let (source_dir, dest_dir) = setup_collision(&temp_dir);
+        fs::write(dest_dir.join("file1-1.txt"), "older").unwrap();
+
+        let paths = vec![source_dir.join("file1.txt")];
+        move_files(paths, &dest_dir, CollisionPolicy::Rename, false).unwrap();
+
+        assert!(!source_dir.join("file1.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("file1.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("file1-1.txt")).unwrap(),
+            "older"
+        );
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("file1-2.txt")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn test_move_files_already_in_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("file1.txt"), "content").unwrap();
+
+        move_files(
+            vec![dir.join("file1.txt")],
+            dir,
+            CollisionPolicy::Error,
+            false,
+        )
+        .unwrap();
+        assert!(dir.join("file1.txt").exists());
+    }
+
+    #[test]
+    fn test_transfer_files_parallel() {
+        let temp_dir = TempDir::new().unwrap();
+        let layout = |dir: &Path| -> Vec<(String, String)> {
+            let mut files: Vec<(String, String)> = fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    (name, fs::read_to_string(&path).unwrap())
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        let setup = |name: &str| -> (Vec<PathBuf>, PathBuf) {
+            let src = temp_dir.path().join(name).join("src");
+            let dest = temp_dir.path().join(name).join("dest");
+            fs::create_dir_all(&src).unwrap();
+            fs::create_dir_all(&dest).unwrap();
+            let paths = (0..20)
+                .map(|i| {
+                    let path = src.join(format!("file{i}.txt"));
+                    fs::write(&path, format!("content {i}")).unwrap();
+                    path
+                })
+                .collect();
+            (paths, dest)
+        };
+
+        let mut counted = 0;
+        let (paths, sequential) = setup("sequential");
+        let options = TransferOptions::new(FileOp::Move, CollisionPolicy::Error, false);
+        transfer_files(
+            paths,
+            &sequential,
+            options,
+            &mut Journal::default(),
+            &mut || {},
+        )
+        .unwrap();
+        let (paths, parallel) = setup("parallel");
+        let options = TransferOptions {
+            threads: 4,
+            ..options
+        };
+        transfer_files(
+            paths,
+            &parallel,
+            options,
+            &mut Journal::default(),
+            &mut || counted += 1,
+        )
+        .unwrap();
+        assert_eq!(counted, 20);
+        assert_eq!(layout(&parallel), layout(&sequential));
+        assert!(layout(&temp_dir.path().join("parallel/src")).is_empty());
+
+        // A failure in one thread still leaves every move done recorded to roll back
+        let (paths, dest) = setup("failing");
+        fs::write(dest.join("file7.txt"), "in the way").unwrap();
+        let mut journal = Journal::default();
+        let result = transfer_files(paths, &dest, options, &mut journal, &mut || {});
+        assert!(matches!(
+            result,
+            Err(IxMatchError::DestinationExists { .. })
+        ));
+        journal.roll_back().unwrap();
+        assert_eq!(layout(&temp_dir.path().join("failing/src")).len(), 20);
+        assert_eq!(
+            layout(&dest),
+            [("file7.txt".to_string(), "in the way".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_journal_write_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let new = temp_dir.path().join("new.json");
+        let existing = temp_dir.path().join("existing.json");
+        let removed = temp_dir.path().join("removed.json");
+        fs::write(&existing, "before").unwrap();
+        fs::write(&removed, "before").unwrap();
+
+        let mut journal = Journal::default();
+        journal.write_file(&new, "after").unwrap();
+        journal.write_file(&existing, "after").unwrap();
+        journal.remove_file(&removed).unwrap();
+        journal
+            .remove_file(&temp_dir.path().join("missing.json"))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "after");
+        assert!(!removed.exists());
+
+        journal.roll_back().unwrap();
+        assert!(!new.exists());
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "before");
+        assert_eq!(fs::read_to_string(&removed).unwrap(), "before");
+    }
+
+    #[test]
+    fn test_local_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("file1.txt");
+        fs::write(&src, "content").unwrap();
+        let out = temp_dir.path().join("out");
+
+        let destination = LocalDestination {
+            file_op: FileOp::Copy,
+            ..LocalDestination::new(&out)
+        };
+        destination
+            .put(&src, Path::new("RGB/unmatched/file1.txt"))
+            .unwrap();
+        assert!(src.exists());
+        assert!(out.join("RGB").join("unmatched").join("file1.txt").exists());
+        assert!(matches!(
+            destination.put(&src, Path::new("RGB/unmatched/file1.txt")),
+            Err(IxMatchError::DestinationExists { .. })
+        ));
+    }
 }
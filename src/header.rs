@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{DateTime, Exif, In, Reader, Tag, Value};
+
+use crate::report::GpsPosition;
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<&[u8]> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values.first().map(Vec::as_slice),
+        _ => None,
+    }
+}
+
+/// Degrees from a degrees, minutes, seconds field, negated if the reference field is `negative`,
+/// e.g. `S` for a latitude.
+fn gps_degrees(exif: &Exif, tag: Tag, ref_tag: Tag, negative: u8) -> Option<f64> {
+    let Value::Rational(dms) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let [degrees, minutes, seconds] = dms.as_slice() else {
+        return None;
+    };
+    let value = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+    if !value.is_finite() {
+        return None;
+    }
+    match ascii_field(exif, ref_tag)?.first() {
+        Some(r) if r.eq_ignore_ascii_case(&negative) => Some(-value),
+        _ => Some(value),
+    }
+}
+
+fn read_exif(path: &Path) -> Option<Exif> {
+    let file = File::open(path).ok()?;
+    Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+/// Read the position a file was captured at from the EXIF GPS tags of an IIQ file header.
+///
+/// Returns `None` if the file has no readable EXIF data or either coordinate is missing.
+pub(crate) fn read_gps_position(path: &Path) -> Option<GpsPosition> {
+    let exif = read_exif(path)?;
+    Some(GpsPosition {
+        latitude: gps_degrees(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?,
+        longitude: gps_degrees(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?,
+    })
+}
+
+/// Read the capture time from the EXIF DateTimeOriginal (and SubSecTimeOriginal) tags of an
+/// IIQ file header.
+///
+/// Returns `None` if the file has no readable EXIF data or the tag is missing.
+pub(crate) fn read_capture_time(path: &Path) -> Option<NaiveDateTime> {
+    let exif = read_exif(path)?;
+
+    let mut datetime = DateTime::from_ascii(ascii_field(&exif, Tag::DateTimeOriginal)?).ok()?;
+    if let Some(subsec) = ascii_field(&exif, Tag::SubSecTimeOriginal) {
+        // Whole seconds are still useful if the sub-second part is garbled
+        let _ = datetime.parse_subsec(subsec);
+    }
+
+    NaiveDate::from_ymd_opt(
+        datetime.year.into(),
+        datetime.month.into(),
+        datetime.day.into(),
+    )?
+    .and_hms_nano_opt(
+        datetime.hour.into(),
+        datetime.minute.into(),
+        datetime.second.into(),
+        datetime.nanosecond.unwrap_or(0),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use exif::experimental::Writer;
+    use exif::{Field, Rational};
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    /// Write a minimal TIFF file carrying only the given EXIF capture time.
+    pub(crate) fn write_tiff_with_capture_time(path: &Path, datetime: &str, subsec: &str) {
+        let datetime = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![datetime.as_bytes().to_vec()]),
+        };
+        let subsec = Field {
+            tag: Tag::SubSecTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![subsec.as_bytes().to_vec()]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&datetime);
+        writer.push_field(&subsec);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+        fs::write(path, buf.into_inner()).unwrap();
+    }
+
+    /// Write a minimal TIFF file carrying only the given EXIF GPS position.
+    pub(crate) fn write_tiff_with_gps_position(path: &Path, latitude: f64, longitude: f64) {
+        let dms = |degrees: f64| {
+            let degrees = degrees.abs();
+            let minutes = degrees.fract() * 60.0;
+            let seconds = (minutes.fract() * 60.0 * 1000.0).round() as u32;
+            Value::Rational(vec![
+                Rational::from((degrees as u32, 1)),
+                Rational::from((minutes as u32, 1)),
+                Rational::from((seconds, 1000)),
+            ])
+        };
+        let ascii = |value: &str| Value::Ascii(vec![value.as_bytes().to_vec()]);
+        let fields = [
+            (Tag::GPSLatitude, dms(latitude)),
+            (
+                Tag::GPSLatitudeRef,
+                ascii(if latitude < 0.0 { "S" } else { "N" }),
+            ),
+            (Tag::GPSLongitude, dms(longitude)),
+            (
+                Tag::GPSLongitudeRef,
+                ascii(if longitude < 0.0 { "W" } else { "E" }),
+            ),
+        ]
+        .map(|(tag, value)| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        });
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+        fs::write(path, buf.into_inner()).unwrap();
+    }
+
+    #[test]
+    fn test_read_gps_position() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("210101_120000000.iiq");
+        write_tiff_with_gps_position(&path, 50.1154, -125.2226);
+
+        let position = read_gps_position(&path).unwrap();
+        assert!((position.latitude - 50.1154).abs() < 1e-6);
+        assert!((position.longitude + 125.2226).abs() < 1e-6);
+
+        // Capture time only, no GPS
+        let no_gps = temp_dir.path().join("210101_120001000.iiq");
+        write_tiff_with_capture_time(&no_gps, "2021:01:01 12:00:05", "250");
+        assert_eq!(read_gps_position(&no_gps), None);
+    }
+
+    #[test]
+    fn test_read_capture_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("210101_120000000.iiq");
+        write_tiff_with_capture_time(&path, "2021:01:01 12:00:05", "250");
+
+        assert_eq!(
+            read_capture_time(&path),
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_milli_opt(12, 0, 5, 250)
+        );
+
+        let no_exif = temp_dir.path().join("210101_120001000.iiq");
+        fs::write(&no_exif, "content").unwrap();
+        assert_eq!(read_capture_time(&no_exif), None);
+    }
+}
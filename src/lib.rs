@@ -1,18 +1,57 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::hash::Hash;
+use std::ops::Index;
 use std::path::{Path, PathBuf};
+use std::thread;
 use std::time::Duration;
 
-use anyhow::{anyhow, Context, Result};
 use chrono::prelude::*;
 use chrono::TimeDelta;
-
+use regex::Regex;
+
+#[cfg(feature = "tar")]
+mod archive;
+mod cache;
+mod classify;
+mod config;
+mod error;
 mod filesystem;
-pub use filesystem::find_dir_by_pattern;
-
+#[cfg(feature = "exif")]
+mod header;
+mod manifest;
+mod progress;
+mod report;
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "tar")]
+pub use archive::extract_tar_entries;
+pub use cache::ScanCache;
+pub use classify::{Classifier, DefaultClassifier, FileStatus};
+pub use config::{MatchStrategy, ProcessConfig, ProcessConfigBuilder, TieBreak, Verbosity};
+pub use error::{IxMatchError, Result};
+pub use filesystem::{
+    copy_files, find_band_dirs, find_dir_by_pattern, find_dir_by_pattern_with_policy,
+    find_dirs_by_pattern, find_files, find_session_dirs, move_files, CollisionPolicy, Destination,
+    FileOp, LocalDestination, MultipleDirPolicy,
+};
+use filesystem::{Journal, TransferOptions};
+pub use manifest::Manifest;
+use progress::ProgressTracker;
+pub use progress::{Phase, Progress};
+pub use report::{
+    match_quality, DeltaStats, Discrepancy, GpsPosition, MatchReport, MovePlan, PartnerChange,
+    PlannedMove, ReportDiff, Timings, MAX_HISTOGRAM_BUCKETS,
+};
+#[cfg(feature = "watch")]
+pub use watch::{watch_images, WatchConfig};
+
+/// A single IIQ image, with its capture time parsed from the file name.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct IIQFile {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IIQFile {
     path: PathBuf,
     name: String,
     stem: String,
@@ -22,22 +61,127 @@ struct IIQFile {
 
 impl IIQFile {
     pub fn new(path: &PathBuf) -> Result<Self> {
+        IIQFile::new_unchecked(path, file_len(path)?)
+    }
+
+    /// Like [`IIQFile::new`], parsing the capture time at the start of the file name with a
+    /// chrono `format` instead of PhaseOne's `%y%m%d_%H%M%S%3f`, for cameras that name their
+    /// files differently. Whatever follows the capture time in the name is ignored.
+    ///
+    /// ```
+    /// # use std::fs;
+    /// use ix_match::IIQFile;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("20240101_120000_0042.iiq");
+    /// # fs::write(&path, "content").unwrap();
+    /// let file = IIQFile::with_datetime_format(&path, "%Y%m%d_%H%M%S").unwrap();
+    /// assert_eq!(file.datetime().to_string(), "2024-01-01 12:00:00");
+    /// ```
+    pub fn with_datetime_format(path: &PathBuf, format: &str) -> Result<Self> {
+        let parse_error = |source| IxMatchError::ParseDatetime {
+            path: path.clone(),
+            source,
+        };
+        let mut file = IIQFile::with_datetime(path, file_len(path)?, NaiveDateTime::MIN)?;
+        file.datetime = NaiveDateTime::parse_and_remainder(&file.stem, format)
+            .map_err(parse_error)?
+            .0;
+        Ok(file)
+    }
+
+    /// Like [`IIQFile::new`], parsing the capture time from the part of the file stem `regex`
+    /// matches, or its first capture group if it has one, for names where it isn't at the
+    /// start. It is parsed with the chrono `format`, or PhaseOne's `%y%m%d_%H%M%S%3f`.
+    ///
+    /// ```
+    /// # use std::fs;
+    /// use ix_match::IIQFile;
+    /// use regex::Regex;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("SITE12_240101_120000000.iiq");
+    /// # fs::write(&path, "content").unwrap();
+    /// let regex = Regex::new(r"_(\d{6}_\d{9})$").unwrap();
+    /// let file = IIQFile::with_datetime_regex(&path, &regex, None).unwrap();
+    /// assert_eq!(file.datetime().to_string(), "2024-01-01 12:00:00");
+    /// ```
+    pub fn with_datetime_regex(
+        path: &PathBuf,
+        regex: &Regex,
+        format: Option<&str>,
+    ) -> Result<Self> {
+        let mut file = IIQFile::with_datetime(path, file_len(path)?, NaiveDateTime::MIN)?;
+        let captures = regex
+            .captures(&file.stem)
+            .ok_or_else(|| IxMatchError::InvalidFileName { path: path.clone() })?;
+        let datetime_part = captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map_or("", |m| m.as_str());
+        file.datetime =
+            NaiveDateTime::parse_from_str(datetime_part, format.unwrap_or("%y%m%d_%H%M%S%3f"))
+                .map_err(|source| IxMatchError::ParseDatetime {
+                    path: path.clone(),
+                    source,
+                })?;
+        Ok(file)
+    }
+
+    /// [`IIQFile::new`], [`with_datetime_format`](Self::with_datetime_format) if there is a
+    /// `format`, or [`with_datetime_regex`](Self::with_datetime_regex) if there is a `regex`.
+    pub fn with_name_parsing(
+        path: &PathBuf,
+        format: Option<&str>,
+        regex: Option<&Regex>,
+    ) -> Result<Self> {
+        match (regex, format) {
+            (Some(regex), format) => IIQFile::with_datetime_regex(path, regex, format),
+            (None, Some(format)) => IIQFile::with_datetime_format(path, format),
+            (None, None) => IIQFile::new(path),
+        }
+    }
+
+    /// Build the file from its name and a known size, without reading its metadata.
+    fn new_unchecked(path: &PathBuf, bytes: u64) -> Result<Self> {
+        let invalid_name = || IxMatchError::InvalidFileName { path: path.clone() };
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(invalid_name)?;
+        let is_digit = |i| stem.as_bytes().get(i).is_some_and(u8::is_ascii_digit);
+        let (datetime_part, format) = if (7..11).all(is_digit) && !is_digit(11) {
+            // Only to the minute, with a frame index to tell files apart, e.g.
+            // `240101_1200_0042.iiq`
+            (stem.get(..11), "%y%m%d_%H%M")
+        } else if (13..16).all(is_digit) {
+            (stem.get(..16), "%y%m%d_%H%M%S%3f")
+        } else {
+            // Some firmware leaves out the milliseconds, e.g. `240101_120000.iiq`
+            (stem.get(..13).filter(|_| !is_digit(13)), "%y%m%d_%H%M%S")
+        };
+        let datetime_part = datetime_part.ok_or_else(invalid_name)?;
+        let datetime = NaiveDateTime::parse_from_str(datetime_part, format).map_err(|source| {
+            IxMatchError::ParseDatetime {
+                path: path.clone(),
+                source,
+            }
+        })?;
+        IIQFile::with_datetime(path, bytes, datetime)
+    }
+
+    /// Build the file from its path, a known size and a capture time that was already worked
+    /// out, e.g. from a [`ScanCache`].
+    fn with_datetime(path: &PathBuf, bytes: u64, datetime: NaiveDateTime) -> Result<Self> {
+        let invalid_name = || IxMatchError::InvalidFileName { path: path.clone() };
         let name = path
             .file_name()
-            .context("Failed to get file name")?
-            .to_str()
-            .context("Failed to convert file name to string")?;
+            .and_then(|n| n.to_str())
+            .ok_or_else(invalid_name)?;
         let stem = path
             .file_stem()
-            .context("Failed to get file stem")?
-            .to_str()
-            .context("Failed to convert file stem to string")?;
-        let datetime = NaiveDateTime::parse_from_str(&stem[..16], "%y%m%d_%H%M%S%3f")
-            .context("Failed to parse datetime from stem")?;
-        let bytes = path
-            .metadata()
-            .context("Failed to get file metadata")?
-            .len();
+            .and_then(|s| s.to_str())
+            .ok_or_else(invalid_name)?;
         Ok(IIQFile {
             path: path.to_owned(),
             name: name.to_owned(),
@@ -47,64 +191,531 @@ impl IIQFile {
         })
     }
 
-    fn diff(&self, other: &NaiveDateTime) -> TimeDelta {
+    /// Like [`IIQFile::new`], but takes the capture time from the EXIF DateTimeOriginal in the
+    /// file header, so it is right even if the file was renamed, whatever its name is now.
+    /// Falls back to the time in the file name if the header has none.
+    #[cfg(feature = "exif")]
+    pub fn from_header(path: &PathBuf) -> Result<Self> {
+        match header::read_capture_time(path) {
+            Some(datetime) => IIQFile::with_datetime(path, file_len(path)?, datetime),
+            None => IIQFile::new(path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// File name without the extension
+    pub fn stem(&self) -> &str {
+        &self.stem
+    }
+
+    /// Capture time parsed from the file name
+    pub fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    /// File size in bytes
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Signed time from `other` to this file's capture time.
+    pub fn diff(&self, other: &NaiveDateTime) -> TimeDelta {
         self.datetime.signed_duration_since(*other)
     }
 
+    /// Whether the file was captured within the `start..end` time of day on a clock set to
+    /// `offset`. The window spans midnight if `start` is after `end`.
+    fn is_within_hours(&self, (start, end): (NaiveTime, NaiveTime), offset: FixedOffset) -> bool {
+        let time = (self.datetime + TimeDelta::seconds(offset.local_minus_utc().into())).time();
+        if start <= end {
+            start <= time && time < end
+        } else {
+            start <= time || time < end
+        }
+    }
+
+    /// The frame index at the end of the file name after the datetime, e.g. 42 for
+    /// `240101_120000000_0042.iiq` or `240101_1200_0042.iiq`.
+    pub fn frame_index(&self) -> Option<u64> {
+        // After the minutes, with or without seconds and milliseconds
+        let (_, index) = self.stem.get(11..)?.rsplit_once('_')?;
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        index.parse().ok()
+    }
+
+    /// Whether the file is 0 bytes, or smaller than `min_size_bytes`, like a transfer that was
+    /// cut off after the header.
+    fn is_empty_below(&self, min_size_bytes: u64) -> bool {
+        self.bytes == 0 || self.bytes < min_size_bytes
+    }
+
+    /// Time between `other` and this file's capture time, saturating at [`Duration::MAX`].
     fn abs_diff(&self, other: &NaiveDateTime) -> Duration {
-        Duration::from_millis(self.diff(other).num_milliseconds().unsigned_abs())
+        self.diff(other).abs().to_std().unwrap_or(Duration::MAX)
+    }
+
+    /// Size of the larger of the two files over that of the smaller one.
+    fn size_ratio(&self, other: &IIQFile) -> f64 {
+        let (small, large) = if self.bytes < other.bytes {
+            (self.bytes, other.bytes)
+        } else {
+            (other.bytes, self.bytes)
+        };
+        if small == 0 {
+            return f64::INFINITY;
+        }
+        large as f64 / small as f64
     }
 
+    /// The name of the directory the camera wrote the file into, after the minute it was
+    /// captured, e.g. `240101_1200`.
     fn original_parent_dir_name(&self) -> String {
-        self.stem[..11].to_string()
+        self.datetime.format("%y%m%d_%H%M").to_string()
+    }
+}
+
+/// Size of the file at `path` in bytes.
+fn file_len(path: &Path) -> Result<u64> {
+    Ok(path
+        .metadata()
+        .map_err(|source| IxMatchError::Metadata {
+            path: path.to_owned(),
+            source,
+        })?
+        .len())
+}
+
+/// The name, capture time and size, e.g. `240101_120000000.iiq @ 2024-01-01 12:00:00.000 (25.3 MB)`.
+impl fmt::Display for IIQFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} @ {} ({})",
+            self.name,
+            self.datetime.format("%Y-%m-%d %H:%M:%S%.3f"),
+            report::format_bytes(self.bytes)
+        )
     }
 }
 
+/// Sort `files` by capture time, and files captured at the same time by path, so they come out
+/// in the same order whatever order the file system listed them in.
+fn sort_by_time(files: &mut [IIQFile]) {
+    files.sort_by(|a, b| {
+        a.datetime
+            .cmp(&b.datetime)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// A set of IIQ files from one camera, sorted by capture time.
 #[derive(Debug, Clone)]
-struct IIQCollection {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IIQCollection {
     files: Vec<IIQFile>,
 }
 
 impl IIQCollection {
     pub fn new(paths: &[PathBuf]) -> Result<Self> {
-        let mut files = paths
-            .iter()
-            .map(IIQFile::new)
-            .collect::<Result<Vec<IIQFile>>>()
-            .context("Could not parse all files")?;
-        // Sort files by datetime
-        files.sort_by_key(|f| f.datetime);
+        IIQCollection::with_file_reader(paths, IIQFile::new)
+    }
+
+    /// Like [`IIQCollection::new`], parsing the capture times in the file names with `format`,
+    /// see [`IIQFile::with_datetime_format`].
+    pub fn with_datetime_format(paths: &[PathBuf], format: &str) -> Result<Self> {
+        IIQCollection::with_file_reader(paths, |path| IIQFile::with_datetime_format(path, format))
+    }
+
+    /// Like [`IIQCollection::new`], parsing the capture times in the file names with `format`
+    /// or `regex` if given, see [`IIQFile::with_name_parsing`].
+    pub fn with_name_parsing(
+        paths: &[PathBuf],
+        format: Option<&str>,
+        regex: Option<&Regex>,
+    ) -> Result<Self> {
+        IIQCollection::with_file_reader(paths, |path| {
+            IIQFile::with_name_parsing(path, format, regex)
+        })
+    }
+
+    /// Like [`IIQCollection::new`], from the file names alone, without a stat of each file, which
+    /// is much quicker over a network mount. The sizes are unknown, so every file has 0 bytes
+    /// and looks empty.
+    pub fn from_names(paths: &[PathBuf]) -> Result<Self> {
+        IIQCollection::with_file_reader(paths, |path| IIQFile::new_unchecked(path, 0))
+    }
+
+    /// Like [`IIQCollection::new`], parsing the capture times in the file names with `regex`
+    /// and `format`, see [`IIQFile::with_datetime_regex`].
+    pub fn with_datetime_regex(
+        paths: &[PathBuf],
+        regex: &Regex,
+        format: Option<&str>,
+    ) -> Result<Self> {
+        IIQCollection::with_file_reader(paths, |path| {
+            IIQFile::with_datetime_regex(path, regex, format)
+        })
+    }
+
+    fn with_file_reader(
+        paths: &[PathBuf],
+        read: impl FnMut(&PathBuf) -> Result<IIQFile>,
+    ) -> Result<Self> {
+        let mut files = paths.iter().map(read).collect::<Result<Vec<IIQFile>>>()?;
+        sort_by_time(&mut files);
         Ok(IIQCollection { files })
     }
 
-    fn paths(&self) -> Vec<PathBuf> {
+    /// Like [`with_file_reader`](Self::with_file_reader), reading the files on up to `threads`
+    /// threads at once. The files end up in the same order, and the error returned is the one
+    /// for the first failing path, as when reading them one at a time.
+    fn with_parallel_reader(
+        paths: &[PathBuf],
+        threads: usize,
+        read: &(dyn Fn(&PathBuf) -> Result<IIQFile> + Sync),
+    ) -> Result<Self> {
+        if threads <= 1 || paths.len() <= 1 {
+            return IIQCollection::with_file_reader(paths, read);
+        }
+
+        // Contiguous chunks, so joining them in order keeps the order of `paths`
+        let chunk_size = paths.len().div_ceil(threads);
+        let chunks: Vec<Result<Vec<IIQFile>>> = thread::scope(|scope| {
+            let handles: Vec<_> = paths
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(read).collect()))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("File reading thread panicked"))
+                .collect()
+        });
+        let mut files = Vec::with_capacity(paths.len());
+        for chunk in chunks {
+            files.extend(chunk?);
+        }
+        sort_by_time(&mut files);
+        Ok(IIQCollection { files })
+    }
+
+    /// Build a collection from all files under `dir` with any of the given extensions.
+    pub fn from_dir(dir: &Path, extensions: &[&str]) -> Result<Self> {
+        let mut paths = Vec::new();
+        for extension in extensions {
+            paths.extend(filesystem::find_files(dir, extension, None, &[], true)?);
+        }
+        IIQCollection::new(&paths)
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
         self.files.iter().map(|f| f.path.clone()).collect()
     }
 
-    fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         self.files.len()
     }
 
-    fn empty_files_len(&self) -> usize {
-        self.files.iter().filter(|f| f.bytes == 0).count()
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Whether the files are sorted by capture time, which matching and the lookups rely on.
+    /// Collections built by this crate always are, but a deserialized one may not be.
+    pub fn is_sorted(&self) -> bool {
+        self.files.is_sorted_by_key(|f| f.datetime)
+    }
+
+    /// Number of 0 byte files
+    pub fn empty_files_len(&self) -> usize {
+        self.empty_files_len_below(0)
     }
 
-    fn pop_empty_files(&mut self) -> IIQCollection {
-        let (empty_files, non_empty_files): (Vec<IIQFile>, Vec<IIQFile>) =
-            self.files.drain(..).partition(|f| f.bytes == 0);
+    /// Number of files that are 0 bytes, or smaller than `min_size_bytes`
+    fn empty_files_len_below(&self, min_size_bytes: u64) -> usize {
+        self.files
+            .iter()
+            .filter(|f| f.is_empty_below(min_size_bytes))
+            .count()
+    }
+
+    /// Number of files with some content
+    pub fn non_empty_len(&self) -> usize {
+        self.len() - self.empty_files_len()
+    }
+
+    /// Combined size of all files in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes).sum()
+    }
+
+    /// Median time between consecutive captures, the camera's usual frame interval, or `None`
+    /// with fewer than two files.
+    pub fn median_interval(&self) -> Option<Duration> {
+        let intervals: Vec<Duration> = self
+            .files
+            .windows(2)
+            .map(|pair| pair[1].abs_diff(&pair[0].datetime))
+            .collect();
+        DeltaStats::from_deltas(&intervals).map(|stats| stats.median)
+    }
+
+    /// Remove and return the files that are 0 bytes, or smaller than `min_size_bytes`.
+    fn pop_empty_files(&mut self, min_size_bytes: u64) -> IIQCollection {
+        let (empty_files, non_empty_files): (Vec<IIQFile>, Vec<IIQFile>) = self
+            .files
+            .drain(..)
+            .partition(|f| f.is_empty_below(min_size_bytes));
 
         self.files = non_empty_files;
 
         IIQCollection { files: empty_files }
     }
 
-    fn iter(&self) -> std::slice::Iter<'_, IIQFile> {
+    /// Remove and return the non-empty files that don't start with TIFF or IIQ magic bytes.
+    fn pop_corrupt_files(&mut self) -> Result<IIQCollection> {
+        let mut corrupt_files = Vec::new();
+        let mut valid_files = Vec::with_capacity(self.files.len());
+        for file in self.files.drain(..) {
+            if file.bytes == 0 || filesystem::has_iiq_magic(&file.path)? {
+                valid_files.push(file);
+            } else {
+                corrupt_files.push(file);
+            }
+        }
+        self.files = valid_files;
+        Ok(IIQCollection {
+            files: corrupt_files,
+        })
+    }
+
+    /// Merge two sorted collections into one, dropping files with the same path.
+    pub fn merge(self, other: IIQCollection) -> IIQCollection {
+        let mut files = Vec::with_capacity(self.len() + other.len());
+        let mut seen = HashSet::with_capacity(self.len() + other.len());
+        let mut left = self.files.into_iter().peekable();
+        let mut right = other.files.into_iter().peekable();
+
+        loop {
+            let file = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) if r.datetime < l.datetime => right.next(),
+                (Some(_), _) => left.next(),
+                (None, Some(_)) => right.next(),
+                (None, None) => break,
+            };
+            let file = file.expect("peeked file is present");
+            if seen.insert(file.path.clone()) {
+                files.push(file);
+            }
+        }
+
+        IIQCollection { files }
+    }
+
+    /// Split into flight lines wherever consecutive files are more than `gap` apart.
+    pub fn split_into_lines(&self, gap: Duration) -> Vec<IIQCollection> {
+        let mut lines: Vec<Vec<IIQFile>> = Vec::new();
+        let mut prev: Option<&IIQFile> = None;
+        for file in self.files.iter() {
+            match prev {
+                Some(p) if file.abs_diff(&p.datetime) <= gap => {
+                    lines.last_mut().unwrap().push(file.clone());
+                }
+                _ => lines.push(vec![file.clone()]),
+            }
+            prev = Some(file);
+        }
+        lines.into_iter().map(IIQCollection::from).collect()
+    }
+
+    /// Consecutive files spaced more than `tolerance` over `expected_interval` apart, with the
+    /// time between them, e.g. to find frames a camera dropped while firing at a steady cadence.
+    pub fn detect_gaps(
+        &self,
+        expected_interval: Duration,
+        tolerance: Duration,
+    ) -> Vec<(IIQFile, IIQFile, Duration)> {
+        let max_interval = expected_interval.saturating_add(tolerance);
+        self.files
+            .windows(2)
+            .filter_map(|pair| {
+                let interval = pair[1].abs_diff(&pair[0].datetime);
+                (interval > max_interval).then(|| (pair[0].clone(), pair[1].clone(), interval))
+            })
+            .collect()
+    }
+
+    /// A new collection of the files `f` returns `true` for, e.g. to leave out nighttime
+    /// captures before matching. The files stay in capture order, and `self` is left as it is.
+    pub fn filter<F: Fn(&IIQFile) -> bool>(&self, f: F) -> IIQCollection {
+        IIQCollection {
+            files: self.files.iter().filter(|file| f(file)).cloned().collect(),
+        }
+    }
+
+    /// Convert the capture times from a clock set to `offset` to UTC.
+    fn convert_to_utc(&mut self, offset: FixedOffset) {
+        let offset = TimeDelta::seconds(offset.local_minus_utc().into());
+        if offset.is_zero() {
+            return;
+        }
+        for file in &mut self.files {
+            file.datetime -= offset;
+        }
+    }
+
+    /// The file captured closest to `datetime`, the earlier one of two equally close.
+    fn nearest(&self, datetime: NaiveDateTime) -> Option<&IIQFile> {
+        let i = self.files.partition_point(|f| f.datetime < datetime);
+        let before = i.checked_sub(1).and_then(|i| self.files.get(i));
+        match (before, self.files.get(i)) {
+            (Some(b), Some(a)) if a.abs_diff(&datetime) < b.abs_diff(&datetime) => Some(a),
+            (Some(b), _) => Some(b),
+            (None, a) => a,
+        }
+    }
+
+    /// Shift the capture times so that within each flight line of `reference`, split wherever
+    /// its files are more than `gap` apart, they are centred on the reference files. Each line's
+    /// offset is the median offset of the nearest file to each of its reference files, and
+    /// files are shifted by that of the line they are closest to. Returns the offsets removed.
+    fn remove_line_offsets(&mut self, reference: &IIQCollection, gap: Duration) -> Vec<TimeDelta> {
+        let lines = reference.split_into_lines(gap);
+        let offsets: Vec<TimeDelta> = lines
+            .iter()
+            .map(|line| {
+                let mut deltas: Vec<TimeDelta> = line
+                    .files
+                    .iter()
+                    .filter_map(|r| self.nearest(r.datetime).map(|f| f.diff(&r.datetime)))
+                    .collect();
+                deltas.sort();
+                deltas.get(deltas.len() / 2).copied().unwrap_or_default()
+            })
+            .collect();
+        if lines.is_empty() {
+            return offsets;
+        }
+
+        // How far `datetime` is outside the span of a line, 0 if within it
+        let distance = |line: &IIQCollection, datetime: &NaiveDateTime| {
+            let (first, last) = (&line.files[0], &line.files[line.len() - 1]);
+            if *datetime < first.datetime {
+                first.abs_diff(datetime)
+            } else if *datetime > last.datetime {
+                last.abs_diff(datetime)
+            } else {
+                Duration::ZERO
+            }
+        };
+        for file in &mut self.files {
+            let next = lines.partition_point(|line| line.files[0].datetime <= file.datetime);
+            let line = match next.checked_sub(1) {
+                Some(prev) if next < lines.len() => {
+                    if distance(&lines[next], &file.datetime)
+                        < distance(&lines[prev], &file.datetime)
+                    {
+                        next
+                    } else {
+                        prev
+                    }
+                }
+                Some(prev) => prev,
+                None => 0,
+            };
+            file.datetime -= offsets[line];
+        }
+        sort_by_time(&mut self.files);
+        offsets
+    }
+
+    /// Remove and return the files captured outside the `start..end` time of day on a clock
+    /// set to `offset`. The window spans midnight if `start` is after `end`.
+    fn pop_off_hours(
+        &mut self,
+        (start, end): (NaiveTime, NaiveTime),
+        offset: FixedOffset,
+    ) -> IIQCollection {
+        let (files, off_hours) = std::mem::take(&mut self.files)
+            .into_iter()
+            .partition(|f| f.is_within_hours((start, end), offset));
+        self.files = files;
+        IIQCollection { files: off_hours }
+    }
+
+    /// Keep only files captured within `start..=end`. A missing bound is unlimited.
+    fn retain_in_range(&mut self, start: Option<NaiveDateTime>, end: Option<NaiveDateTime>) {
+        self.files.retain(|f| {
+            start.is_none_or(|start| f.datetime >= start) && end.is_none_or(|end| f.datetime <= end)
+        });
+    }
+
+    /// Iterate over the files in capture order.
+    ///
+    /// ```
+    /// # use std::fs;
+    /// use ix_match::IIQCollection;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # for name in ["240101_120001000.iiq", "240101_120000000.iiq"] {
+    /// #     fs::write(dir.path().join(name), "content").unwrap();
+    /// # }
+    /// let collection = IIQCollection::from_dir(dir.path(), &["iiq"]).unwrap();
+    ///
+    /// for file in collection.iter() {
+    ///     println!("{}", file.stem());
+    /// }
+    /// assert_eq!(collection[0].stem(), "240101_120000000");
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &IIQFile> {
         self.files.iter()
     }
 
-    fn get_closest_file_by_datetime(&self, target_datetime: &NaiveDateTime) -> Result<&IIQFile> {
+    /// Find the file captured closest to `target_datetime`, preferring the earlier file on a tie.
+    ///
+    /// This is a binary search, so it relies on the collection being sorted by datetime, as it
+    /// is when built with [`IIQCollection::new`] or [`IIQCollection::from_dir`].
+    ///
+    /// ```
+    /// # use std::fs;
+    /// use chrono::NaiveDateTime;
+    /// use ix_match::IIQCollection;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # for name in ["240101_120000000.iiq", "240101_120001000.iiq"] {
+    /// #     fs::write(dir.path().join(name), "content").unwrap();
+    /// # }
+    /// let collection = IIQCollection::from_dir(dir.path(), &["iiq"]).unwrap();
+    ///
+    /// let gps_fix = NaiveDateTime::parse_from_str("240101_120000800", "%y%m%d_%H%M%S%3f").unwrap();
+    /// let closest = collection.get_closest_file_by_datetime(&gps_fix).unwrap();
+    /// assert_eq!(closest.name(), "240101_120001000.iiq");
+    /// assert_eq!(closest.diff(&gps_fix).num_milliseconds(), 200);
+    /// ```
+    pub fn get_closest_file_by_datetime(
+        &self,
+        target_datetime: &NaiveDateTime,
+    ) -> Result<&IIQFile> {
+        self.get_closest_file_with_tie_break(target_datetime, TieBreak::PreferEarlier)
+    }
+
+    /// Like [`get_closest_file_by_datetime`](Self::get_closest_file_by_datetime), using
+    /// `tie_break` to pick between two files equally close to `target_datetime`.
+    pub fn get_closest_file_with_tie_break(
+        &self,
+        target_datetime: &NaiveDateTime,
+        tie_break: TieBreak,
+    ) -> Result<&IIQFile> {
         if self.files.is_empty() {
-            return Err(anyhow!("No files in collection"));
+            return Err(IxMatchError::EmptyCollection);
         }
 
         // Do binary search for the closest file
@@ -126,9 +737,11 @@ impl IIQCollection {
                 return Ok(&self.files[mid]);
             }
 
-            if diff < closest_diff
-                || (diff == closest_diff && self.files[mid].datetime < *target_datetime)
-            {
+            let wins_tie = match tie_break {
+                TieBreak::PreferEarlier => self.files[mid].datetime < *target_datetime,
+                TieBreak::PreferLater => self.files[mid].datetime > *target_datetime,
+            };
+            if diff < closest_diff || (diff == closest_diff && wins_tie) {
                 closest_diff = diff;
                 closest_file = Some(&self.files[mid]);
             }
@@ -145,13 +758,87 @@ impl IIQCollection {
         if let Some(closest_file) = closest_file {
             Ok(closest_file)
         } else {
-            Err(anyhow!("Failed to get closest file by datetime"))
+            Err(IxMatchError::EmptyCollection)
+        }
+    }
+
+    /// Find up to `k` files captured closest to `target`, closest first, preferring the earlier
+    /// file on a tie.
+    ///
+    /// Like [`get_closest_file_by_datetime`](Self::get_closest_file_by_datetime), this relies
+    /// on the collection being sorted by datetime.
+    pub fn k_nearest(&self, target: &NaiveDateTime, k: usize) -> Vec<&IIQFile> {
+        // Expand outwards from where the target would be inserted, taking the closer of the
+        // files on either side each time
+        let mut after = self.files.partition_point(|f| f.datetime < *target);
+        let mut before = after;
+        let mut nearest = Vec::with_capacity(k.min(self.files.len()));
+        while nearest.len() < k {
+            let take_before = match (before.checked_sub(1), self.files.get(after)) {
+                (Some(b), Some(a)) => self.files[b].diff(target).abs() <= a.diff(target).abs(),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_before {
+                before -= 1;
+                nearest.push(&self.files[before]);
+            } else {
+                nearest.push(&self.files[after]);
+                after += 1;
+            }
         }
+        nearest
+    }
+
+    /// The file captured closest to each of `events`, e.g. the trigger times in a flight log,
+    /// with its signed offset from the event, in the order of `events`. Each event is looked up
+    /// with [`get_closest_file_by_datetime`](Self::get_closest_file_by_datetime), so a file can
+    /// be the closest to more than one event.
+    pub fn closest_to_events(
+        &self,
+        events: &[NaiveDateTime],
+    ) -> Result<Vec<(NaiveDateTime, &IIQFile, TimeDelta)>> {
+        events
+            .iter()
+            .map(|event| {
+                let file = self.get_closest_file_by_datetime(event)?;
+                Ok((*event, file, file.diff(event)))
+            })
+            .collect()
+    }
+}
+
+impl Index<usize> for IIQCollection {
+    type Output = IIQFile;
+
+    fn index(&self, index: usize) -> &IIQFile {
+        &self.files[index]
+    }
+}
+
+impl IntoIterator for IIQCollection {
+    type Item = IIQFile;
+    type IntoIter = std::vec::IntoIter<IIQFile>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a IIQCollection {
+    type Item = &'a IIQFile;
+    type IntoIter = std::slice::Iter<'a, IIQFile>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.iter()
     }
 }
 
+/// Sorts the files by capture time, as the collection has to be.
 impl From<Vec<IIQFile>> for IIQCollection {
-    fn from(files: Vec<IIQFile>) -> Self {
+    fn from(mut files: Vec<IIQFile>) -> Self {
+        sort_by_time(&mut files);
         IIQCollection { files }
     }
 }
@@ -159,37 +846,43 @@ impl From<Vec<IIQFile>> for IIQCollection {
 #[derive(Debug)]
 struct JoinedIIQCollection<'a> {
     joined: Vec<(Option<&'a IIQFile>, Option<&'a IIQFile>, Duration)>,
+    /// Maximum offsets for pairs with the NIR file captured before and after the RGB file, on
+    /// top of the threshold the pairs are filtered with
+    direction_limits: Option<(Duration, Duration)>,
 }
 
 impl<'a> JoinedIIQCollection<'a> {
-    pub fn new(rgb: &'a IIQCollection, nir: &'a IIQCollection) -> Result<Self> {
+    /// Pair each file with the closest file in time in the other collection. The pairs are in
+    /// capture order of the longer collection, and files of the shorter one that lose out to
+    /// a closer file are left out.
+    pub fn new(rgb: &'a IIQCollection, nir: &'a IIQCollection, tie_break: TieBreak) -> Self {
+        debug_assert!(rgb.is_sorted() && nir.is_sorted());
         let rgb_shorter = rgb.len() < nir.len();
         let key_collection = if rgb_shorter { rgb } else { nir };
         let other_collection = if rgb_shorter { nir } else { rgb };
+        let others = &other_collection.files;
 
-        let mut join_hash = other_collection
-            .files
-            .iter()
-            .map(|f| (f, (None, Duration::MAX)))
-            .collect::<HashMap<_, _>>();
+        // Closest key file found so far for each of the other files
+        let mut best: Vec<(Option<&IIQFile>, Duration)> = vec![(None, Duration::MAX); others.len()];
 
-        // Match 1:1 the files.
+        // Both collections are sorted by time, so walk them together
+        let mut next = 0;
         for iiq in key_collection.files.iter() {
-            let closest_other_file =
-                other_collection.get_closest_file_by_datetime(&iiq.datetime)?;
-            let dt = iiq.abs_diff(&closest_other_file.datetime);
+            let closest = closest_index(others, &mut next, iiq, tie_break)
+                .expect("the key collection is never the longer one");
 
-            let v = join_hash.get_mut(&closest_other_file);
-            let (existing_match, existing_dt) = v.unwrap();
+            // Match 1:1 the files, keeping the earliest of equally close key files
+            let dt = iiq.abs_diff(&others[closest].datetime);
+            let (existing_match, existing_dt) = &mut best[closest];
             if dt < *existing_dt {
                 *existing_match = Some(iiq);
                 *existing_dt = dt;
             }
         }
 
-        // Turn the hashmap into a vector
-        let mut joined: Vec<(Option<&IIQFile>, Option<&IIQFile>, Duration)> = join_hash
-            .into_iter()
+        let mut joined: Vec<(Option<&IIQFile>, Option<&IIQFile>, Duration)> = others
+            .iter()
+            .zip(best)
             .map(|(k, (v, dt))| (Some(k), v, dt))
             .collect();
 
@@ -201,41 +894,262 @@ impl<'a> JoinedIIQCollection<'a> {
                 .collect();
         }
 
-        Ok(JoinedIIQCollection { joined })
+        JoinedIIQCollection {
+            joined,
+            direction_limits: None,
+        }
+    }
+
+    /// Pair each NIR file with the RGB file closest in time, if it is within `max_dt`, so an
+    /// RGB file can be paired with several NIR files. The pairs are in NIR capture order.
+    fn many_to_one(
+        rgb: &'a IIQCollection,
+        nir: &'a IIQCollection,
+        max_dt: Duration,
+        tie_break: TieBreak,
+    ) -> Self {
+        let mut claimed = vec![false; rgb.len()];
+        let mut joined = Vec::with_capacity(rgb.len() + nir.len());
+        let mut next = 0;
+        for n in nir.iter() {
+            let closest = closest_index(&rgb.files, &mut next, n, tie_break)
+                .map(|i| (i, n.abs_diff(&rgb.files[i].datetime)))
+                .filter(|(_, dt)| *dt <= max_dt);
+            match closest {
+                Some((i, dt)) => {
+                    claimed[i] = true;
+                    joined.push((Some(&rgb.files[i]), Some(n), dt));
+                }
+                None => joined.push((None, Some(n), Duration::MAX)),
+            }
+        }
+        joined.extend(
+            rgb.iter()
+                .zip(claimed)
+                .filter(|(_, claimed)| !claimed)
+                .map(|(r, _)| (Some(r), None, Duration::MAX)),
+        );
+
+        JoinedIIQCollection {
+            joined,
+            direction_limits: None,
+        }
+    }
+
+    /// Pair files by their position in each sorted collection. Extra files in the longer
+    /// collection are left without a match.
+    fn by_order(
+        rgb: &'a IIQCollection,
+        nir: &'a IIQCollection,
+        validate_monotonic: bool,
+    ) -> Result<Self> {
+        if validate_monotonic {
+            let offsets: Vec<(TimeDelta, &IIQFile, &IIQFile)> = rgb
+                .iter()
+                .zip(nir.iter())
+                .map(|(r, n)| (n.diff(&r.datetime), r, n))
+                .collect();
+            // Direction the offset has been moving in so far
+            let mut direction = Ordering::Equal;
+            for pair in offsets.windows(2) {
+                let (prev_offset, (offset, r, n)) = (pair[0].0, pair[1]);
+                let step = offset.cmp(&prev_offset);
+                if step == Ordering::Equal {
+                    continue;
+                }
+                if direction == Ordering::Equal {
+                    direction = step;
+                } else if step != direction {
+                    return Err(IxMatchError::NonMonotonicOrder {
+                        rgb: r.path.clone(),
+                        nir: n.path.clone(),
+                    });
+                }
+            }
+        }
+
+        let len = rgb.len().max(nir.len());
+        let joined = (0..len)
+            .map(|i| {
+                let r = rgb.files.get(i);
+                let n = nir.files.get(i);
+                let dt = match (r, n) {
+                    (Some(r), Some(n)) => r.abs_diff(&n.datetime),
+                    _ => Duration::MAX,
+                };
+                (r, n, dt)
+            })
+            .collect();
+
+        Ok(JoinedIIQCollection {
+            joined,
+            direction_limits: None,
+        })
+    }
+
+    /// Pair files with the same [`IIQFile::frame_index`], or `None` if a file has no index, an
+    /// index appears twice in one collection, or the collections differ in length.
+    fn by_index(rgb: &'a IIQCollection, nir: &'a IIQCollection) -> Option<Self> {
+        if rgb.len() != nir.len() {
+            return None;
+        }
+        let mut nir_by_index = HashMap::new();
+        for file in nir.iter() {
+            if nir_by_index.insert(file.frame_index()?, file).is_some() {
+                return None;
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut joined = Vec::with_capacity(rgb.len());
+        for r in rgb.iter() {
+            let index = r.frame_index()?;
+            if !seen.insert(index) {
+                return None;
+            }
+            match nir_by_index.remove(&index) {
+                Some(n) => joined.push((Some(r), Some(n), r.abs_diff(&n.datetime))),
+                None => joined.push((Some(r), None, Duration::MAX)),
+            }
+        }
+        joined.extend(
+            nir_by_index
+                .into_values()
+                .map(|n| (None, Some(n), Duration::MAX)),
+        );
+
+        Some(JoinedIIQCollection {
+            joined,
+            direction_limits: None,
+        })
+    }
+
+    /// Only match pairs with the NIR file captured `before` or `after` the RGB file if they
+    /// are at most that far apart.
+    fn with_direction_limits(mut self, before: Duration, after: Duration) -> Self {
+        self.direction_limits = Some((before, after));
+        self
+    }
+
+    /// Whether `rgb` and `nir`, `dt` apart, are close enough to be a match.
+    fn is_match(&self, rgb: &IIQFile, nir: &IIQFile, dt: &Duration, max_dt: &Duration) -> bool {
+        let limit = match self.direction_limits {
+            Some((before, _)) if nir.datetime < rgb.datetime => before,
+            Some((_, after)) => after,
+            None => Duration::MAX,
+        };
+        dt <= max_dt && *dt <= limit
     }
 
     fn get_matched(&self, max_dt: &Duration) -> Vec<(&IIQFile, &IIQFile)> {
+        self.get_matched_with_dt(max_dt)
+            .into_iter()
+            .map(|(rgb, nir, _)| (rgb, nir))
+            .collect()
+    }
+
+    fn get_matched_with_dt(&self, max_dt: &Duration) -> Vec<(&IIQFile, &IIQFile, Duration)> {
         self.joined
             .iter()
-            .filter(|(rgb, nir, dt)| rgb.is_some() && nir.is_some() && dt <= max_dt)
-            .map(|(rgb, nir, _)| (rgb.unwrap(), nir.unwrap()))
+            .filter_map(|(rgb, nir, dt)| match (rgb, nir) {
+                (Some(rgb), Some(nir)) if self.is_match(rgb, nir, dt, max_dt) => {
+                    Some((*rgb, *nir, *dt))
+                }
+                _ => None,
+            })
             .collect()
     }
 
-    fn get_matched_rgb(&self, max_dt: &Duration) -> IIQCollection {
+    /// Matched pairs where one file is more than `max_ratio` times the size of the other.
+    fn get_lopsided(&self, max_dt: &Duration, max_ratio: f64) -> Vec<(&IIQFile, &IIQFile)> {
         self.get_matched(max_dt)
-            .iter()
-            .map(|(rgb, _)| (*rgb).clone())
-            .collect::<Vec<IIQFile>>()
-            .into()
+            .into_iter()
+            .filter(|(rgb, nir)| rgb.size_ratio(nir) > max_ratio)
+            .collect()
     }
 
-    fn get_matched_nir(&self, max_dt: &Duration) -> IIQCollection {
+    /// Matched pairs with a [`match_quality`] below `min_quality`, see
+    /// [`ProcessConfig::min_quality`].
+    fn get_low_quality(
+        &self,
+        max_dt: &Duration,
+        config: &ProcessConfig,
+        min_quality: f64,
+    ) -> Vec<(&IIQFile, &IIQFile)> {
         self.get_matched(max_dt)
-            .iter()
-            .map(|(_, nir)| (*nir).clone())
-            .collect::<Vec<IIQFile>>()
-            .into()
+            .into_iter()
+            .filter(|(rgb, nir)| {
+                let threshold = config.pair_threshold(rgb, nir);
+                match_quality(rgb.abs_diff(&nir.datetime), threshold) < min_quality
+            })
+            .collect()
+    }
+
+    fn get_matched_deltas(&self, max_dt: &Duration) -> Vec<Duration> {
+        self.get_matched_with_dt(max_dt)
+            .into_iter()
+            .map(|(_, _, dt)| dt)
+            .collect()
+    }
+
+    /// Matched pairs with their time offset, sorted by RGB capture time.
+    fn get_matched_pairs(&self, max_dt: &Duration) -> Vec<(IIQFile, IIQFile, Duration)> {
+        let mut pairs: Vec<(IIQFile, IIQFile, Duration)> = self
+            .get_matched_with_dt(max_dt)
+            .into_iter()
+            .map(|(rgb, nir, dt)| (rgb.clone(), nir.clone(), dt))
+            .collect();
+        pairs.sort_by_key(|(rgb, _, _)| rgb.datetime);
+        pairs
+    }
+
+    fn get_matched_rgb(&self, max_dt: &Duration) -> IIQCollection {
+        unique_files(self.get_matched(max_dt).into_iter().map(|(rgb, _)| rgb))
+    }
+
+    fn get_matched_nir(&self, max_dt: &Duration) -> IIQCollection {
+        unique_files(self.get_matched(max_dt).into_iter().map(|(_, nir)| nir))
     }
 
     fn get_unmatched(&self, max_dt: &Duration) -> Vec<(Option<&IIQFile>, Option<&IIQFile>)> {
         self.joined
             .iter()
-            .filter(|(rgb, nir, dt)| (rgb.is_none() || nir.is_none()) || dt > max_dt)
+            .filter(|(rgb, nir, dt)| match (rgb, nir) {
+                (Some(rgb), Some(nir)) => !self.is_match(rgb, nir, dt, max_dt),
+                _ => true,
+            })
             .map(|(rgb, nir, _)| (*rgb, *nir))
             .collect()
     }
 
+    /// Unmatched pairs that were closest to each other, but further apart than `max_dt`, with
+    /// their time offset. Sorted by RGB capture time.
+    fn get_over_threshold(&self, max_dt: &Duration) -> Vec<(IIQFile, IIQFile, Duration)> {
+        let mut pairs: Vec<(IIQFile, IIQFile, Duration)> = self
+            .joined
+            .iter()
+            .filter_map(|(rgb, nir, dt)| match (rgb, nir) {
+                (Some(rgb), Some(nir)) if !self.is_match(rgb, nir, dt, max_dt) => {
+                    Some(((*rgb).clone(), (*nir).clone(), *dt))
+                }
+                _ => None,
+            })
+            .collect();
+        pairs.sort_by_key(|(rgb, _, _)| rgb.datetime);
+        pairs
+    }
+
+    /// Unmatched files that no file of the other band was paired with at all.
+    fn get_no_candidate(&self) -> Vec<IIQFile> {
+        self.joined
+            .iter()
+            .filter_map(|(rgb, nir, _)| match (rgb, nir) {
+                (Some(file), None) | (None, Some(file)) => Some((*file).clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
     fn get_unmatched_rgb(&self, max_dt: &Duration) -> IIQCollection {
         self.get_unmatched(max_dt)
             .iter()
@@ -255,250 +1169,4715 @@ impl<'a> JoinedIIQCollection<'a> {
     }
 }
 
-fn check_rgb_nir_dirs_exist(rgb_dir: &Path, nir_dir: &Path) -> Result<()> {
-    let rgb_exists = rgb_dir.exists();
-    let nir_exists = nir_dir.exists();
-    if !rgb_exists && !nir_exists {
-        Err(anyhow!("RGB and NIR directories do not exist"))
-    } else if !rgb_exists {
-        Err(anyhow!("RGB directory does not exist"))
-    } else if !nir_exists {
-        Err(anyhow!("NIR directory does not exist"))
-    } else {
-        Ok(())
+/// Index of the file in `files`, sorted by time, closest to `target`, using `tie_break` between
+/// two files equally close. `next` is the first file captured at or after the previous target,
+/// so looking up targets in time order walks `files` only once. `None` if `files` is empty.
+fn closest_index(
+    files: &[IIQFile],
+    next: &mut usize,
+    target: &IIQFile,
+    tie_break: TieBreak,
+) -> Option<usize> {
+    while *next < files.len() && files[*next].datetime < target.datetime {
+        *next += 1;
+    }
+    match (
+        (*next).checked_sub(1),
+        (*next < files.len()).then_some(*next),
+    ) {
+        (Some(before), Some(after)) => {
+            let before_dt = target.abs_diff(&files[before].datetime);
+            let after_dt = target.abs_diff(&files[after].datetime);
+            let prefer_before = match tie_break {
+                TieBreak::PreferEarlier => before_dt <= after_dt,
+                TieBreak::PreferLater => before_dt < after_dt,
+            };
+            Some(if prefer_before { before } else { after })
+        }
+        (Some(before), None) => Some(before),
+        (None, Some(after)) => Some(after),
+        (None, None) => None,
     }
 }
 
-pub fn process_images(
-    rgb_dir: &Path,
-    nir_dir: &Path,
-    match_threshold: Duration,
-    keep_empty_files: bool,
-    dry_run: bool,
-    verbose: bool,
-) -> Result<(usize, usize, usize, usize, usize)> {
-    check_rgb_nir_dirs_exist(rgb_dir, nir_dir)?;
+/// How a file was decided on by [`match_stream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchDecision<'a> {
+    /// The files are close enough in time to be a pair, `dt` apart
+    Matched {
+        rgb: &'a IIQFile,
+        nir: &'a IIQFile,
+        dt: Duration,
+    },
+    /// The file, or the closest pair of files, isn't matched. A pair was closest to each
+    /// other, but further apart than the threshold, and a lone file was paired with no file of
+    /// the other band at all.
+    Unmatched {
+        rgb: Option<&'a IIQFile>,
+        nir: Option<&'a IIQFile>,
+    },
+}
 
-    // Find IIQ files
-    let rgb_iiq_files = filesystem::find_files(rgb_dir, "iiq")?;
-    let nir_iiq_files = filesystem::find_files(nir_dir, "iiq")?;
+/// Walks two sorted collections together, joining them as [`JoinedIIQCollection::new`] does,
+/// yielding each file of the longer collection as soon as no later file of the shorter one can
+/// be closer to it.
+struct JoinStream<'a> {
+    keys: &'a [IIQFile],
+    others: &'a [IIQFile],
+    rgb_shorter: bool,
+    tie_break: TieBreak,
+    /// Next key file to look up, and the next other file at or after the last one looked up
+    next_key: usize,
+    next_other: usize,
+    /// Next other file to yield
+    current: usize,
+    /// Key file looked up but not yet applied, with the index of its closest other file
+    peeked: Option<(usize, &'a IIQFile)>,
+    /// Closest key file so far for the other file at the index
+    best: Option<(usize, &'a IIQFile, Duration)>,
+}
+
+impl<'a> JoinStream<'a> {
+    fn new(rgb: &'a IIQCollection, nir: &'a IIQCollection, tie_break: TieBreak) -> Self {
+        debug_assert!(rgb.is_sorted() && nir.is_sorted());
+        let rgb_shorter = rgb.len() < nir.len();
+        let (keys, others) = if rgb_shorter {
+            (&rgb.files, &nir.files)
+        } else {
+            (&nir.files, &rgb.files)
+        };
+        JoinStream {
+            keys,
+            others,
+            rgb_shorter,
+            tie_break,
+            next_key: 0,
+            next_other: 0,
+            current: 0,
+            peeked: None,
+            best: None,
+        }
+    }
+}
+
+impl<'a> Iterator for JoinStream<'a> {
+    type Item = (Option<&'a IIQFile>, Option<&'a IIQFile>, Duration);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.others.get(self.current)?;
+        loop {
+            if self.peeked.is_none() && self.next_key < self.keys.len() {
+                let key = &self.keys[self.next_key];
+                self.next_key += 1;
+                let closest = closest_index(self.others, &mut self.next_other, key, self.tie_break)
+                    .expect("the key collection is never the longer one");
+                self.peeked = Some((closest, key));
+            }
+            // Key files are looked up in time order, so their closest other files never go
+            // back, and the current file is done once a key file is closest to a later one
+            match self.peeked {
+                Some((closest, key)) if closest == self.current => {
+                    let dt = key.abs_diff(&other.datetime);
+                    if !matches!(self.best, Some((i, _, best_dt)) if i == closest && best_dt <= dt)
+                    {
+                        self.best = Some((closest, key, dt));
+                    }
+                    self.peeked = None;
+                }
+                _ => break,
+            }
+        }
+
+        let (key, dt) = match self.best.take() {
+            Some((i, key, dt)) if i == self.current => (Some(key), dt),
+            best => {
+                self.best = best;
+                (None, Duration::MAX)
+            }
+        };
+        self.current += 1;
+        Some(if self.rgb_shorter {
+            (key, Some(other), dt)
+        } else {
+            (Some(other), key, dt)
+        })
+    }
+}
+
+/// Collect the files, skipping any already seen, as an RGB file paired with several NIR files
+/// is in several pairs.
+fn unique_files<'a>(files: impl IntoIterator<Item = &'a IIQFile>) -> IIQCollection {
+    let mut seen = HashSet::new();
+    files
+        .into_iter()
+        .filter(|f| seen.insert(f.path()))
+        .cloned()
+        .collect::<Vec<IIQFile>>()
+        .into()
+}
+
+fn check_rgb_nir_dirs_exist(rgb_dir: &Path, nir_dir: &Path) -> Result<()> {
+    let rgb_exists = rgb_dir.exists();
+    let nir_exists = nir_dir.exists();
+    if !rgb_exists && !nir_exists {
+        Err(IxMatchError::RgbAndNirDirsNotFound)
+    } else if !rgb_exists {
+        Err(IxMatchError::RgbDirNotFound)
+    } else if !nir_exists {
+        Err(IxMatchError::NirDirNotFound)
+    } else {
+        Ok(())
+    }
+}
+
+/// Move or copy `paths`, found in `band_dir`, into `dest`. With
+/// [`ProcessConfig::preserve_structure`], each file goes into the same subdirectory of `dest`
+/// as it was in below `band_dir`, which is created if needed.
+fn transfer_into(
+    paths: Vec<PathBuf>,
+    band_dir: &Path,
+    dest: &Path,
+    config: &ProcessConfig,
+    journal: &mut Journal,
+    on_file: &mut dyn FnMut(),
+) -> Result<()> {
+    let mut options = TransferOptions::new(
+        config.file_op,
+        config.collision_policy,
+        config.verbosity.is_debug(),
+    );
+    if config.parallel_moves {
+        options.threads = config.thread_count();
+    }
+    if !config.preserve_structure {
+        return filesystem::transfer_files(paths, dest, options, journal, on_file);
+    }
+
+    let band_dir = band_dir.canonicalize()?;
+    for path in paths {
+        let relative = path
+            .parent()
+            .and_then(|p| p.strip_prefix(&band_dir).ok())
+            .unwrap_or(Path::new(""));
+        let dir = dest.join(relative);
+        journal.create_dir_all(&dir)?;
+        filesystem::transfer_files(vec![path], &dir, options, journal, on_file)?;
+    }
+    Ok(())
+}
+
+/// Moves files to wherever the [`Classifier`] sends them, keeping a [`Journal`] of what was done.
+struct Mover<'a> {
+    config: &'a ProcessConfig,
+    classifier: &'a dyn Classifier,
+    /// Where the files are written instead of the band directories, if anywhere
+    destination: Option<&'a dyn Destination>,
+    journal: Journal,
+    /// The moves skipped with [`ProcessConfig::dry_run`]
+    plan: Vec<PlannedMove>,
+    /// The files in the plan. A dry run can come across a file twice, as empty files are
+    /// matched too, and the first move planned for it is the one a real run would make.
+    planned: HashSet<PathBuf>,
+}
+
+impl<'a> Mover<'a> {
+    fn new(
+        config: &'a ProcessConfig,
+        classifier: &'a dyn Classifier,
+        destination: Option<&'a dyn Destination>,
+    ) -> Self {
+        Mover {
+            config,
+            classifier,
+            destination,
+            journal: Journal::default(),
+            plan: Vec::new(),
+            planned: HashSet::new(),
+        }
+    }
+
+    /// Move the files in `collection` that are in subdirectories of `band_dir` into it, see
+    /// [`ProcessConfig::flatten`].
+    fn flatten(&mut self, collection: &mut IIQCollection, band_dir: &Path) -> Result<()> {
+        let verbose = self.config.verbosity.is_debug();
+        let band_dir = &band_dir.canonicalize()?;
+        for file in &mut collection.files {
+            if file.path.parent() == Some(band_dir) {
+                continue;
+            }
+            let dest = self.journal.move_renaming(&file.path, band_dir, verbose)?;
+            *file = IIQFile::with_datetime(&dest, file.bytes, file.datetime)?;
+        }
+        Ok(())
+    }
+
+    /// Move or copy `files`, found in `band_dir`, the `band` (`rgb` or `nir`) directory, into
+    /// the directories the classifier picks for them, which are created if there are any files
+    /// to go in them. With [`ProcessConfig::dry_run`] the moves are only added to the plan.
+    fn transfer<'f>(
+        &mut self,
+        files: impl IntoIterator<Item = &'f IIQFile>,
+        band_dir: &Path,
+        band: &str,
+        status: FileStatus,
+        label: &str,
+        on_file: &mut dyn FnMut(),
+    ) -> Result<()> {
+        // The files are found by their canonical paths, which the relative paths are taken from
+        let band_dir = &band_dir.canonicalize()?;
+        if self.config.dry_run {
+            self.plan_moves(files, band_dir, band, status, on_file);
+            return Ok(());
+        }
+        if let Some(destination) = self.destination {
+            return self.put(destination, files, band_dir, status, on_file);
+        }
+        let root = self.config.output_root(band_dir, band);
+        // Group by destination, keeping the order the files came in
+        let mut groups: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+        for file in files {
+            let dest = root.join(self.classifier.destination(file, status, self.config));
+            match groups.iter_mut().find(|(dir, _)| *dir == dest) {
+                Some((_, paths)) => paths.push(file.path.clone()),
+                None => groups.push((dest, vec![file.path.clone()])),
+            }
+        }
+
+        for (dest, paths) in groups {
+            if self.config.verbosity.is_debug() {
+                println!("Moving {} files to {:?}", label, dest);
+            }
+            self.journal.create_dir_all(&dest)?;
+            transfer_into(
+                paths,
+                band_dir,
+                &dest,
+                self.config,
+                &mut self.journal,
+                on_file,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Add where `files`, found in `band_dir`, the `band` directory, would be moved to the plan.
+    /// `band_dir` is canonical, like the paths of the files.
+    fn plan_moves<'f>(
+        &mut self,
+        files: impl IntoIterator<Item = &'f IIQFile>,
+        band_dir: &Path,
+        band: &str,
+        status: FileStatus,
+        on_file: &mut dyn FnMut(),
+    ) {
+        let root = self.config.output_root(band_dir, band);
+        for file in files {
+            let mut to = root.join(self.classifier.destination(file, status, self.config));
+            if self.config.preserve_structure {
+                if let Some(relative) = file
+                    .path
+                    .parent()
+                    .and_then(|p| p.strip_prefix(band_dir).ok())
+                {
+                    to.push(relative);
+                }
+            }
+            to.push(file.name());
+            if to != file.path && self.planned.insert(file.path.clone()) {
+                self.plan.push(PlannedMove {
+                    from: file.path.clone(),
+                    to,
+                    status,
+                });
+            }
+            on_file();
+        }
+    }
+
+    /// Hand `files`, found in `band_dir`, to `destination`, at the path relative to the parent
+    /// of `band_dir` they would be moved to. `band_dir` is canonical, like the paths of the files.
+    fn put<'f>(
+        &self,
+        destination: &dyn Destination,
+        files: impl IntoIterator<Item = &'f IIQFile>,
+        band_dir: &Path,
+        status: FileStatus,
+        on_file: &mut dyn FnMut(),
+    ) -> Result<()> {
+        let band_name = band_dir.file_name().map(Path::new).unwrap_or(Path::new(""));
+        for file in files {
+            let mut rel = band_name.join(self.classifier.destination(file, status, self.config));
+            if self.config.preserve_structure {
+                if let Some(relative) = file
+                    .path
+                    .parent()
+                    .and_then(|p| p.strip_prefix(band_dir).ok())
+                {
+                    rel.push(relative);
+                }
+            }
+            rel.push(file.name());
+            destination.put(&file.path, &rel)?;
+            on_file();
+        }
+        Ok(())
+    }
+}
+
+/// Where the matched files from `band_dir` are moved to, see
+/// [`ProcessConfig::matched_subdir`].
+#[cfg(feature = "watch")]
+fn matched_dir(band_dir: &Path, config: &ProcessConfig) -> PathBuf {
+    match &config.matched_subdir {
+        Some(name) => band_dir.join(name),
+        None => band_dir.to_path_buf(),
+    }
+}
+
+/// Every status files are moved by
+const FILE_STATUSES: [FileStatus; 7] = [
+    FileStatus::Matched,
+    FileStatus::Unmatched,
+    FileStatus::Empty,
+    FileStatus::Corrupt,
+    FileStatus::Suspect,
+    FileStatus::Review,
+    FileStatus::OffHours,
+];
+
+/// Sub-directories that processing with `config` moves files into, see
+/// [`ProcessConfig::subdir_name`]
+fn output_subdirs(config: &ProcessConfig) -> Vec<&Path> {
+    FILE_STATUSES
+        .iter()
+        .filter_map(|status| config.subdir_name(*status))
+        .filter(|name| !name.is_empty())
+        .map(Path::new)
+        .collect()
+}
+
+fn check_not_already_processed(dirs: &[&Path], subdirs: &[&Path]) -> Result<()> {
+    for dir in dirs {
+        for name in subdirs {
+            let found = dir.join(name);
+            if found.is_dir() {
+                return Err(IxMatchError::AlreadyProcessed {
+                    dir: dir.to_path_buf(),
+                    found,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn process_images(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+) -> Result<MatchReport> {
+    process_images_with_progress(rgb_dir, nir_dir, config, |_| {})
+}
+
+/// Like [`process_images`], moving each file to the directory `classifier` picks for it.
+pub fn process_images_with_classifier(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    classifier: &dyn Classifier,
+) -> Result<MatchReport> {
+    run_process_images(rgb_dir, nir_dir, config, classifier, None, |_| {})
+}
+
+/// Like [`process_images`], writing the files to `destination` instead of moving them within
+/// the band directories, e.g. to upload them. Files are left where they are, unless the
+/// destination removes them, and what was already written isn't undone if a later step fails.
+/// [`ProcessConfig::output_dir`] is ignored, as the destination decides where files go.
+pub fn process_images_with_destination(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    destination: &dyn Destination,
+) -> Result<MatchReport> {
+    run_process_images(
+        rgb_dir,
+        nir_dir,
+        config,
+        &DefaultClassifier,
+        Some(destination),
+        |_| {},
+    )
+}
+
+/// Like [`process_images`], calling `progress` as files are scanned, matched and moved.
+pub fn process_images_with_progress(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    progress: impl FnMut(Progress),
+) -> Result<MatchReport> {
+    run_process_images(rgb_dir, nir_dir, config, &DefaultClassifier, None, progress)
+}
+
+/// Split the files of a mixed directory into RGB files, whose name matches `band_regex`, and
+/// NIR files, see [`ProcessConfig::band_regex`].
+fn split_by_band(paths: Vec<PathBuf>, band_regex: &Regex) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    paths.into_iter().partition(|path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| band_regex.is_match(name))
+    })
+}
+
+/// The files of each band that processing `rgb_dir` and `nir_dir` with `config` starts
+/// from: the [`ProcessConfig::rgb_paths`] and [`ProcessConfig::nir_paths`] if they were
+/// listed, or else those found in the directories, or in the one directory holding both
+/// bands, split by [`ProcessConfig::band_regex`].
+pub fn band_files(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    // Find IIQ files, unless they were listed
+    let find_files = |dir, extensions, listed: &Option<Vec<PathBuf>>| match listed {
+        Some(paths) => {
+            if let Some(path) = paths.iter().find(|path| !path.is_file()) {
+                return Err(IxMatchError::ListedFileNotFound { path: path.clone() });
+            }
+            Ok(paths.clone())
+        }
+        None => config.find_files(dir, extensions),
+    };
+    Ok(match config.compiled_band_regex()? {
+        // Both bands in one directory, told apart by name
+        Some(regex) => split_by_band(
+            find_files(rgb_dir, &config.rgb_extensions, &config.rgb_paths)?,
+            &regex,
+        ),
+        None => (
+            find_files(rgb_dir, &config.rgb_extensions, &config.rgb_paths)?,
+            find_files(nir_dir, &config.nir_extensions, &config.nir_paths)?,
+        ),
+    })
+}
+
+fn run_process_images(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    classifier: &dyn Classifier,
+    destination: Option<&dyn Destination>,
+    mut progress: impl FnMut(Progress),
+) -> Result<MatchReport> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!(
+        "process_images",
+        rgb_dir = %rgb_dir.display(),
+        nir_dir = %nir_dir.display(),
+        dry_run = config.dry_run
+    )
+    .entered();
+    let mut tracker = ProgressTracker::new(&mut progress);
+
+    check_dirs(rgb_dir, nir_dir, config)?;
+
+    // Leave the files matched by earlier runs alone
+    let mut manifest = config.manifest.as_deref().map(Manifest::load).transpose()?;
+    let (rgb_collection, nir_collection) =
+        scan_collections(rgb_dir, nir_dir, config, manifest.as_ref(), &mut tracker)?;
+
+    let result = match_and_move(
+        rgb_collection,
+        nir_collection,
+        rgb_dir,
+        nir_dir,
+        config,
+        Mover::new(config, classifier, destination),
+        &mut tracker,
+    );
+    if let Some(manifest) = &mut manifest {
+        let report = match &result {
+            Ok(report) => Some(report),
+            // The files were still moved
+            Err(IxMatchError::Unmatched { report, .. }) => Some(report.as_ref()),
+            Err(_) => None,
+        };
+        if let Some(report) = report.filter(|_| !config.dry_run) {
+            manifest.record_pairs(report.pairs.iter().map(|(rgb, nir, _)| (rgb, nir)));
+            manifest.save()?;
+        }
+    }
+    result
+}
+
+/// Find and read the files of each band as processing with `config` does: the
+/// [`band_files`] without those in the `manifest` or modified before
+/// [`ProcessConfig::modified_since`], read from the file names or headers, through the
+/// [`ProcessConfig::cache`] if there is one.
+pub(crate) fn scan_collections(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    manifest: Option<&Manifest>,
+    tracker: &mut ProgressTracker,
+) -> Result<(IIQCollection, IIQCollection)> {
+    let verbose = config.verbosity.is_debug();
+    let (mut rgb_iiq_files, mut nir_iiq_files) = band_files(rgb_dir, nir_dir, config)?;
+    if let Some(manifest) = manifest {
+        rgb_iiq_files.retain(|path| !manifest.contains_rgb(path));
+        nir_iiq_files.retain(|path| !manifest.contains_nir(path));
+    }
+    if let Some(since) = config.modified_since {
+        filesystem::retain_modified_since(&mut rgb_iiq_files, since)?;
+        filesystem::retain_modified_since(&mut nir_iiq_files, since)?;
+    }
+    tracker.start(Phase::Scanning, rgb_iiq_files.len() + nir_iiq_files.len());
+
+    // Create collections
+    let datetime_regex = config.compiled_datetime_regex()?;
+    let read_file = |path: &PathBuf, format: Option<&str>| {
+        // The name only has to parse if the header has no capture time
+        #[cfg(feature = "exif")]
+        if config.header_datetime {
+            if let Some(datetime) = header::read_capture_time(path) {
+                return IIQFile::with_datetime(path, file_len(path)?, datetime);
+            }
+        }
+        IIQFile::with_name_parsing(path, format, datetime_regex.as_ref())
+    };
+    let cache = config.cache.as_deref().map(ScanCache::load).transpose()?;
+    if let Some(cache) = &cache {
+        cache.scanned(rgb_dir);
+        cache.scanned(nir_dir);
+    }
+    let read_cached = |path: &PathBuf, format: Option<&str>| match &cache {
+        Some(cache) => cache.get_or_read(path, |path| read_file(path, format)),
+        None => read_file(path, format),
+    };
+    let threads = config.thread_count();
+    let rgb_format = config.rgb_datetime_format.as_deref();
+    let rgb_collection = IIQCollection::with_parallel_reader(&rgb_iiq_files, threads, &|path| {
+        read_cached(path, rgb_format)
+    })?;
+    tracker.advance(rgb_collection.len());
+    let nir_format = config.nir_datetime_format.as_deref();
+    let nir_collection = IIQCollection::with_parallel_reader(&nir_iiq_files, threads, &|path| {
+        read_cached(path, nir_format)
+    })?;
+    tracker.advance(nir_collection.len());
+    if let Some(cache) = &cache {
+        if verbose {
+            println!(
+                "Scan cache: {} files cached, {} read",
+                cache.hits(),
+                cache.misses()
+            );
+        }
+        cache.save()?;
+    }
+    Ok((rgb_collection, nir_collection))
+}
+
+/// Like [`process_images`], for collections that were already built, e.g. with
+/// [`IIQCollection::from_dir`], so the directories aren't scanned again.
+///
+/// The collections should hold the files under `rgb_dir` and `nir_dir`, which is where the
+/// matched files are moved to.
+pub fn process_collections(
+    rgb_collection: IIQCollection,
+    nir_collection: IIQCollection,
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+) -> Result<MatchReport> {
+    check_dirs(rgb_dir, nir_dir, config)?;
+    let mut progress = |_| {};
+    let mut tracker = ProgressTracker::new(&mut progress);
+    match_and_move(
+        rgb_collection,
+        nir_collection,
+        rgb_dir,
+        nir_dir,
+        config,
+        Mover::new(config, &DefaultClassifier, None),
+        &mut tracker,
+    )
+}
+
+/// How many pairs match at each of `thresholds`, matching by time as [`process_images`] would
+/// with `config`, without moving anything. The collections are joined once and the pairs
+/// counted for each threshold, which makes this a quick way to pick a
+/// [`ProcessConfig::match_threshold`].
+pub fn count_matches_at(
+    rgb_collection: &IIQCollection,
+    nir_collection: &IIQCollection,
+    thresholds: &[Duration],
+    config: &ProcessConfig,
+) -> Result<Vec<usize>> {
+    let (rgb_collection, nir_collection) =
+        prepare_for_matching(rgb_collection, nir_collection, config);
+    let mut joined = JoinedIIQCollection::new(&rgb_collection, &nir_collection, config.tie_break);
+    // The directional thresholds still hold, whichever threshold is swept
+    if config.match_threshold_before.is_some() || config.match_threshold_after.is_some() {
+        joined = joined.with_direction_limits(
+            config.match_threshold_before.unwrap_or(Duration::MAX),
+            config.match_threshold_after.unwrap_or(Duration::MAX),
+        );
+    }
+    Ok(thresholds
+        .iter()
+        .map(|threshold| joined.get_matched(threshold).len())
+        .collect())
+}
+
+/// Like [`count_matches_at`], for the files [`process_images`] would read from `rgb_dir` and
+/// `nir_dir` with `config`: listed or found the same way, skipping the same files, and through
+/// the same [`ProcessConfig::cache`], so the counts are what a run at each threshold would
+/// match.
+pub fn sweep_thresholds(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    thresholds: &[Duration],
+    config: &ProcessConfig,
+) -> Result<Vec<usize>> {
+    check_rgb_nir_dirs_exist(rgb_dir, nir_dir)?;
+    let manifest = config.manifest.as_deref().map(Manifest::load).transpose()?;
+    let mut progress = |_| {};
+    let mut tracker = ProgressTracker::new(&mut progress);
+    let (rgb_collection, nir_collection) =
+        scan_collections(rgb_dir, nir_dir, config, manifest.as_ref(), &mut tracker)?;
+    count_matches_at(&rgb_collection, &nir_collection, thresholds, config)
+}
+
+/// The pairs [`process_images`] would match by time with `config`, with their time offset,
+/// sorted by RGB capture time, without moving anything. Unlike the pairs borrowed from the
+/// collections while matching, these are owned, so they can be kept or returned on their
+/// own. Capture times are in UTC if the cameras have an offset.
+pub fn matched_pairs(
+    rgb_collection: &IIQCollection,
+    nir_collection: &IIQCollection,
+    config: &ProcessConfig,
+) -> Result<Vec<(IIQFile, IIQFile, Duration)>> {
+    let (rgb_collection, nir_collection) =
+        prepare_for_matching(rgb_collection, nir_collection, config);
+    let (before, after) = config.directional_thresholds();
+    let joined = JoinedIIQCollection::new(&rgb_collection, &nir_collection, config.tie_break)
+        .with_direction_limits(before, after);
+    Ok(joined.get_matched_pairs(&before.max(after)))
+}
+
+/// Match the collections by time as [`matched_pairs`] does, deciding on one file at a time, so
+/// a caller can act on each pair as it goes without the whole join being held in memory.
+///
+/// The collections are used as they are, so they should already be sorted, in UTC and
+/// filtered as they need to be. The decisions are in capture order of the longer collection,
+/// and files of the shorter one that lose out to a closer file are left out.
+pub fn match_stream<'a>(
+    rgb_collection: &'a IIQCollection,
+    nir_collection: &'a IIQCollection,
+    config: &ProcessConfig,
+) -> impl Iterator<Item = MatchDecision<'a>> {
+    let (before, after) = config.directional_thresholds();
+    JoinStream::new(rgb_collection, nir_collection, config.tie_break).map(move |(rgb, nir, dt)| {
+        match (rgb, nir) {
+            (Some(rgb), Some(nir)) => {
+                let limit = if nir.datetime < rgb.datetime {
+                    before
+                } else {
+                    after
+                };
+                if dt <= limit {
+                    MatchDecision::Matched { rgb, nir, dt }
+                } else {
+                    MatchDecision::Unmatched {
+                        rgb: Some(rgb),
+                        nir: Some(nir),
+                    }
+                }
+            }
+            (rgb, nir) => MatchDecision::Unmatched { rgb, nir },
+        }
+    })
+}
+
+/// An (RGB, NIR, time offset) row of [`joined_table`].
+pub type JoinedRow = (Option<IIQFile>, Option<IIQFile>, Duration);
+
+/// The whole join [`process_images`] would match by time with `config`, before any threshold
+/// is applied, for thresholding it yourself: each file of the longer collection with the
+/// closest file of the other band that it was paired with, as (RGB, NIR, time offset). Files
+/// no file of the other band was paired with have `None` for it and an offset of
+/// `Duration::MAX`. In capture order of the longer collection, and files of the shorter one
+/// that lose out to a closer file are left out.
+pub fn joined_table(
+    rgb_collection: &IIQCollection,
+    nir_collection: &IIQCollection,
+    config: &ProcessConfig,
+) -> Result<Vec<JoinedRow>> {
+    let (rgb_collection, nir_collection) =
+        prepare_for_matching(rgb_collection, nir_collection, config);
+    let joined = JoinedIIQCollection::new(&rgb_collection, &nir_collection, config.tie_break);
+    Ok(joined
+        .joined
+        .into_iter()
+        .map(|(rgb, nir, dt)| (rgb.cloned(), nir.cloned(), dt))
+        .collect())
+}
+
+/// Like [`matched_pairs`], for a quick preview from the paths alone. The collections are built
+/// with [`IIQCollection::from_names`], so the files aren't even stat'ed and have 0 bytes.
+///
+/// As the sizes are unknown, empty files aren't detected: they are paired like any other file,
+/// regardless of [`ProcessConfig::keep_empty_files`] and [`ProcessConfig::min_size_bytes`].
+pub fn preview_pairs(
+    rgb_paths: &[PathBuf],
+    nir_paths: &[PathBuf],
+    config: &ProcessConfig,
+) -> Result<Vec<(IIQFile, IIQFile, Duration)>> {
+    let rgb_collection = IIQCollection::from_names(rgb_paths)?;
+    let nir_collection = IIQCollection::from_names(nir_paths)?;
+    let config = ProcessConfig {
+        keep_empty_files: true,
+        ..config.clone()
+    };
+    matched_pairs(&rgb_collection, &nir_collection, &config)
+}
+
+/// The larger of two file counts over the smaller one, which is infinite if only one is 0.
+fn count_skew(rgb_count: usize, nir_count: usize) -> f64 {
+    let (small, large) = (rgb_count.min(nir_count), rgb_count.max(nir_count));
+    if large == 0 {
+        1.0
+    } else {
+        large as f64 / small as f64
+    }
+}
+
+/// Copies of the collections as they are matched by time with `config`: in UTC, within the
+/// time range and time of day and without empty files.
+fn prepare_for_matching(
+    rgb_collection: &IIQCollection,
+    nir_collection: &IIQCollection,
+    config: &ProcessConfig,
+) -> (IIQCollection, IIQCollection) {
+    let mut rgb_collection = rgb_collection.clone();
+    let mut nir_collection = nir_collection.clone();
+    rgb_collection.convert_to_utc(config.rgb_utc_offset);
+    nir_collection.convert_to_utc(config.nir_utc_offset);
+    rgb_collection.retain_in_range(config.start, config.end);
+    nir_collection.retain_in_range(config.start, config.end);
+    if !config.keep_empty_files {
+        rgb_collection.pop_empty_files(config.min_size_bytes);
+        nir_collection.pop_empty_files(config.min_size_bytes);
+    }
+    if let Some(window) = config.time_of_day {
+        rgb_collection.pop_off_hours(window, config.rgb_utc_offset);
+        nir_collection.pop_off_hours(window, config.nir_utc_offset);
+    }
+    if let Some(gap) = config.line_offset_gap {
+        nir_collection.remove_line_offsets(&rgb_collection, gap);
+    }
+    (rgb_collection, nir_collection)
+}
+
+fn check_dirs(rgb_dir: &Path, nir_dir: &Path, config: &ProcessConfig) -> Result<()> {
+    check_rgb_nir_dirs_exist(rgb_dir, nir_dir)?;
+    // Incremental runs are expected to find the output of earlier ones
+    if !config.force && !config.dry_run && config.manifest.is_none() {
+        check_not_already_processed(
+            &[
+                &config.output_root(rgb_dir, "rgb"),
+                &config.output_root(nir_dir, "nir"),
+            ],
+            &output_subdirs(config),
+        )?;
+    }
+    Ok(())
+}
+
+/// Everything from filtering the collections on: moving empty and corrupt files, matching and
+/// moving the results.
+fn match_and_move(
+    rgb_collection: IIQCollection,
+    nir_collection: IIQCollection,
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    mut mover: Mover,
+    tracker: &mut ProgressTracker,
+) -> Result<MatchReport> {
+    let check_locations = config.check_locations && !config.dry_run && mover.destination.is_none();
+    let inputs: HashSet<PathBuf> = if check_locations {
+        rgb_collection
+            .iter()
+            .chain(nir_collection.iter())
+            .map(|file| file.path().to_path_buf())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let count_before = count_run_files(&inputs, &mover.journal);
+
+    // If anything fails part way, put back what was already moved, so the directories are
+    // left as they were
+    let mut report = match try_match_and_move(
+        rgb_collection,
+        nir_collection,
+        rgb_dir,
+        nir_dir,
+        config,
+        tracker,
+        &mut mover,
+    ) {
+        Ok(report) => report,
+        Err(error) => {
+            return Err(match mover.journal.roll_back() {
+                Ok(()) => error,
+                Err(rollback) => IxMatchError::RollbackFailed {
+                    error: Box::new(error),
+                    rollback: Box::new(rollback),
+                },
+            });
+        }
+    };
+    report.timings = tracker.timings();
+    if check_locations {
+        let count_after = count_run_files(&inputs, &mover.journal);
+        report.discrepancies = find_discrepancies(&mover.journal, count_before, count_after);
+    }
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        rgb = report.rgb_count,
+        nir = report.nir_count,
+        matched = report.matched_count,
+        unmatched_rgb = report.unmatched_rgb_count,
+        unmatched_nir = report.unmatched_nir_count,
+        "processed"
+    );
+
+    if config.strict && (report.unmatched_rgb_count > 0 || report.unmatched_nir_count > 0) {
+        return Err(IxMatchError::Unmatched {
+            rgb: report.unmatched_rgb_count,
+            nir: report.unmatched_nir_count,
+            report: Box::new(report),
+        });
+    }
+
+    Ok(report)
+}
+
+/// The `<stem>.match.json` next to the file at `path`, see [`ProcessConfig::write_sidecars`].
+fn sidecar_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.match.json"))
+}
+
+/// The sidecar to write next to where each file of the `pairs` is now, and what goes in it,
+/// see [`ProcessConfig::write_sidecars`].
+fn sidecars(
+    pairs: &[(IIQFile, IIQFile, Duration)],
+    destinations: &HashMap<&Path, &Path>,
+) -> Vec<(PathBuf, String)> {
+    let mut sidecars = Vec::with_capacity(pairs.len() * 2);
+    for (rgb, nir, dt) in pairs {
+        for (file, band, partner) in [(rgb, "rgb", nir), (nir, "nir", rgb)] {
+            let path = destinations
+                .get(file.path())
+                .copied()
+                .unwrap_or(file.path());
+            sidecars.push((
+                sidecar_path(path),
+                report::sidecar_json(file, band, partner, *dt),
+            ));
+        }
+    }
+    sidecars
+}
+
+/// How many of the run's `inputs` and the files the `journal` moved or copied are on disk, for
+/// [`ProcessConfig::check_locations`]. Only the run's own files count, wherever they came from
+/// (e.g. [`ProcessConfig::rgb_paths`]), so moves between directories leave the count unchanged.
+fn count_run_files(inputs: &HashSet<PathBuf>, journal: &Journal) -> usize {
+    let mut files: HashSet<&Path> = inputs.iter().map(PathBuf::as_path).collect();
+    files.extend(journal.transfers().map(|(_, _, to)| to));
+    files.into_iter().filter(|path| path.is_file()).count()
+}
+
+/// Compare what the `journal` says was done with what is on disk, for
+/// [`ProcessConfig::check_locations`]. Copies add to the `count_before` expected after the run.
+fn find_discrepancies(
+    journal: &Journal,
+    count_before: usize,
+    count_after: usize,
+) -> Vec<Discrepancy> {
+    let transfers: Vec<_> = journal.transfers().collect();
+    let froms: HashSet<&Path> = transfers.iter().map(|(_, from, _)| *from).collect();
+    let tos: HashSet<&Path> = transfers.iter().map(|(_, _, to)| *to).collect();
+
+    let mut discrepancies = Vec::new();
+    let mut copies = 0;
+    for (file_op, from, to) in &transfers {
+        // A file moved on again is checked at its last stop
+        if !froms.contains(to) && !to.exists() {
+            discrepancies.push(Discrepancy::Missing {
+                path: to.to_path_buf(),
+            });
+        }
+        if *file_op == FileOp::Move {
+            if !tos.contains(from) && from.exists() {
+                discrepancies.push(Discrepancy::NotRemoved {
+                    path: from.to_path_buf(),
+                });
+            }
+        } else {
+            copies += 1;
+        }
+    }
+
+    let expected = count_before + copies;
+    if count_after != expected {
+        discrepancies.push(Discrepancy::CountChanged {
+            expected,
+            found: count_after,
+        });
+    }
+    discrepancies
+}
+
+fn try_match_and_move(
+    mut rgb_collection: IIQCollection,
+    mut nir_collection: IIQCollection,
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    tracker: &mut ProgressTracker,
+    mover: &mut Mover,
+) -> Result<MatchReport> {
+    let verbose = config.verbosity.is_debug();
+
+    rgb_collection.convert_to_utc(config.rgb_utc_offset);
+    nir_collection.convert_to_utc(config.nir_utc_offset);
+
+    // Leave files outside the requested time range alone
+    if config.start.is_some() || config.end.is_some() {
+        rgb_collection.retain_in_range(config.start, config.end);
+        nir_collection.retain_in_range(config.start, config.end);
+    }
+    let rgb_count = rgb_collection.len();
+    let nir_count = nir_collection.len();
+
+    // The sidecars of an earlier run no longer hold for the files sorted again, whatever this
+    // run decides. Off hours files are only set aside, like those out of range.
+    if !config.dry_run && mover.destination.is_none() {
+        let files = [
+            (&rgb_collection, config.rgb_utc_offset),
+            (&nir_collection, config.nir_utc_offset),
+        ]
+        .into_iter()
+        .flat_map(|(collection, offset)| {
+            collection.iter().filter(move |file| {
+                config
+                    .time_of_day
+                    .is_none_or(|window| file.is_within_hours(window, offset))
+            })
+        });
+        for file in files {
+            mover.journal.remove_file(&sidecar_path(file.path()))?;
+        }
+    }
+
+    // Very different numbers of files are more likely a mistake than dropped frames
+    let skewed = config
+        .max_count_skew
+        .map(|max_skew| (max_skew, count_skew(rgb_count, nir_count)))
+        .filter(|(max_skew, skew)| skew > max_skew);
+    if let Some((max_skew, _)) = skewed.filter(|_| config.strict) {
+        return Err(IxMatchError::CountSkew {
+            rgb: rgb_count,
+            nir: nir_count,
+            max_skew,
+        });
+    }
+
+    // A threshold wider than the gap between frames lets several frames match the same file
+    let threshold = {
+        let (before, after) = config.directional_thresholds();
+        before.max(after)
+    };
+    let crowded = config
+        .max_thresh_interval_ratio
+        .filter(|_| config.match_strategy == MatchStrategy::ByTime)
+        .and_then(|max_ratio| {
+            let interval = [&rgb_collection, &nir_collection]
+                .into_iter()
+                .filter_map(IIQCollection::median_interval)
+                .min()?;
+            (threshold.as_secs_f64() > max_ratio * interval.as_secs_f64())
+                .then_some((max_ratio, interval))
+        });
+    if let Some((max_ratio, interval)) = crowded.filter(|_| config.strict) {
+        return Err(IxMatchError::ThresholdExceedsInterval {
+            threshold,
+            interval,
+            max_ratio,
+        });
+    }
+
+    // Get 0 byte file counts
+    let empty_rgb_files_len = rgb_collection.empty_files_len_below(config.min_size_bytes);
+    let empty_nir_files_len = nir_collection.empty_files_len_below(config.min_size_bytes);
+
+    if !config.keep_empty_files && !config.dry_run {
+        // Move empty files
+        let empty_rgb_files = rgb_collection.pop_empty_files(config.min_size_bytes);
+        let empty_nir_files = nir_collection.pop_empty_files(config.min_size_bytes);
+        tracker.start(
+            Phase::MovingEmpty,
+            empty_rgb_files.len() + empty_nir_files.len(),
+        );
+
+        mover.transfer(
+            &empty_rgb_files,
+            rgb_dir,
+            "rgb",
+            FileStatus::Empty,
+            "empty RGB",
+            &mut || tracker.advance(1),
+        )?;
+
+        mover.transfer(
+            &empty_nir_files,
+            nir_dir,
+            "nir",
+            FileStatus::Empty,
+            "empty NIR",
+            &mut || tracker.advance(1),
+        )?;
+    } else if !config.keep_empty_files {
+        // A dry run leaves the empty files in to be matched, and only plans where they'd go
+        tracker.start(Phase::MovingEmpty, 0);
+        for (collection, band_dir, band) in [
+            (&rgb_collection, rgb_dir, "rgb"),
+            (&nir_collection, nir_dir, "nir"),
+        ] {
+            mover.plan_moves(
+                collection
+                    .iter()
+                    .filter(|file| file.is_empty_below(config.min_size_bytes)),
+                &band_dir.canonicalize()?,
+                band,
+                FileStatus::Empty,
+                &mut || {},
+            );
+        }
+    } else {
+        tracker.start(Phase::MovingEmpty, 0);
+    }
+
+    let (corrupt_rgb_count, corrupt_nir_count) = if config.validate {
+        let corrupt_rgb_files = rgb_collection.pop_corrupt_files()?;
+        let corrupt_nir_files = nir_collection.pop_corrupt_files()?;
+        tracker.start(
+            Phase::MovingCorrupt,
+            corrupt_rgb_files.len() + corrupt_nir_files.len(),
+        );
+        mover.transfer(
+            &corrupt_rgb_files,
+            rgb_dir,
+            "rgb",
+            FileStatus::Corrupt,
+            "corrupt RGB",
+            &mut || tracker.advance(1),
+        )?;
+        mover.transfer(
+            &corrupt_nir_files,
+            nir_dir,
+            "nir",
+            FileStatus::Corrupt,
+            "corrupt NIR",
+            &mut || tracker.advance(1),
+        )?;
+        (corrupt_rgb_files.len(), corrupt_nir_files.len())
+    } else {
+        tracker.start(Phase::MovingCorrupt, 0);
+        (0, 0)
+    };
+
+    let (off_hours_rgb_count, off_hours_nir_count) = if let Some(window) = config.time_of_day {
+        let off_hours_rgb_files = rgb_collection.pop_off_hours(window, config.rgb_utc_offset);
+        let off_hours_nir_files = nir_collection.pop_off_hours(window, config.nir_utc_offset);
+        tracker.start(
+            Phase::MovingOffHours,
+            off_hours_rgb_files.len() + off_hours_nir_files.len(),
+        );
+        mover.transfer(
+            &off_hours_rgb_files,
+            rgb_dir,
+            "rgb",
+            FileStatus::OffHours,
+            "off hours RGB",
+            &mut || tracker.advance(1),
+        )?;
+        mover.transfer(
+            &off_hours_nir_files,
+            nir_dir,
+            "nir",
+            FileStatus::OffHours,
+            "off hours NIR",
+            &mut || tracker.advance(1),
+        )?;
+        (off_hours_rgb_files.len(), off_hours_nir_files.len())
+    } else {
+        tracker.start(Phase::MovingOffHours, 0);
+        (0, 0)
+    };
+
+    // Only the files left to match, those filtered out above have been moved or left alone
+    if config.flatten && !config.dry_run {
+        mover.flatten(&mut rgb_collection, rgb_dir)?;
+        mover.flatten(&mut nir_collection, nir_dir)?;
+    }
+
+    // Take out the clock drift of each flight line
+    if let Some(gap) = config.line_offset_gap {
+        let offsets = nir_collection.remove_line_offsets(&rgb_collection, gap);
+        if config.verbosity.is_debug() {
+            for (i, offset) in offsets.iter().enumerate() {
+                println!(
+                    "Line {}: NIR clock {} ms off",
+                    i + 1,
+                    offset.num_milliseconds()
+                );
+            }
+        }
+    }
+
+    // Do the join
+    tracker.start(Phase::Matching, rgb_collection.len() + nir_collection.len());
+    // Matches by time can allow a different offset on each side
+    let (before, after) = config.directional_thresholds();
+    let time_threshold = before.max(after);
+    let (joined, match_threshold) = match config.match_strategy {
+        MatchStrategy::ByTime => (
+            JoinedIIQCollection::new(&rgb_collection, &nir_collection, config.tie_break)
+                .with_direction_limits(before, after),
+            time_threshold,
+        ),
+        MatchStrategy::ByOrder { validate_monotonic } => (
+            JoinedIIQCollection::by_order(&rgb_collection, &nir_collection, validate_monotonic)?,
+            // Positional pairs are matched regardless of their offset
+            Duration::MAX,
+        ),
+        MatchStrategy::ManyToOne => (
+            JoinedIIQCollection::many_to_one(
+                &rgb_collection,
+                &nir_collection,
+                time_threshold,
+                config.tie_break,
+            )
+            .with_direction_limits(before, after),
+            time_threshold,
+        ),
+        MatchStrategy::ByIndex => {
+            match JoinedIIQCollection::by_index(&rgb_collection, &nir_collection) {
+                // As are pairs with the same frame index
+                Some(joined) => (joined, Duration::MAX),
+                None => {
+                    if verbose {
+                        println!("Frame indices missing or mismatched, matching by time");
+                    }
+                    (
+                        JoinedIIQCollection::new(
+                            &rgb_collection,
+                            &nir_collection,
+                            config.tie_break,
+                        )
+                        .with_direction_limits(before, after),
+                        time_threshold,
+                    )
+                }
+            }
+        }
+    };
+    tracker.advance(rgb_collection.len() + nir_collection.len());
+
+    let matched_rgb = joined.get_matched_rgb(&match_threshold);
+    let matched_nir = joined.get_matched_nir(&match_threshold);
+    let pairs = joined.get_matched_pairs(&match_threshold);
+    let no_candidate = joined.get_no_candidate();
+
+    // Near misses within the looser threshold are set aside to be looked at, not unmatched
+    let (review, over_threshold): (Vec<_>, Vec<_>) = joined
+        .get_over_threshold(&match_threshold)
+        .into_iter()
+        .partition(|(_, _, dt)| config.review_threshold.is_some_and(|review| *dt <= review));
+    let review_rgb = unique_files(review.iter().map(|(rgb, _, _)| rgb));
+    let review_nir = unique_files(review.iter().map(|(_, nir, _)| nir));
+    let review_paths: HashSet<&Path> = review
+        .iter()
+        .flat_map(|(rgb, nir, _)| [rgb.path(), nir.path()])
+        .collect();
+    let unmatched_rgb = joined
+        .get_unmatched_rgb(&match_threshold)
+        .filter(|f| !review_paths.contains(f.path()));
+    let unmatched_nir = joined
+        .get_unmatched_nir(&match_threshold)
+        .filter(|f| !review_paths.contains(f.path()));
+
+    // Almost nothing matching is more likely a mistake than a bad flight
+    let possible = rgb_collection.len().min(nir_collection.len());
+    if let Some(min_rate) = config.min_match_rate {
+        if possible > 0 && (matched_rgb.len() as f64) < min_rate * possible as f64 {
+            return Err(IxMatchError::LowMatchRate {
+                matched: matched_rgb.len(),
+                possible,
+                min_rate,
+            });
+        }
+    }
+    let qualities = pairs
+        .iter()
+        .map(|(rgb, nir, dt)| match_quality(*dt, config.pair_threshold(rgb, nir)))
+        .collect();
+
+    // Read positions while the files are still where they were found
+    #[cfg(feature = "exif")]
+    let positions = if config.read_gps {
+        pairs
+            .iter()
+            .map(|(rgb, nir, _)| {
+                header::read_gps_position(rgb.path())
+                    .or_else(|| header::read_gps_position(nir.path()))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(feature = "exif"))]
+    let positions = Vec::new();
+
+    // Pairs with very different file sizes are usually a bad capture, and pairs close to the
+    // threshold may be the wrong frames, so set them aside
+    let lopsided = config
+        .size_ratio_max
+        .map(|max_ratio| joined.get_lopsided(&match_threshold, max_ratio))
+        .unwrap_or_default();
+    let low_quality = config
+        .min_quality
+        .map(|min_quality| joined.get_low_quality(&match_threshold, config, min_quality))
+        .unwrap_or_default();
+    if verbose {
+        for (rgb, nir) in &lopsided {
+            println!(
+                "Sizes of {} ({} bytes) and {} ({} bytes) differ too much",
+                rgb.name, rgb.bytes, nir.name, nir.bytes
+            );
+        }
+        for (rgb, nir) in &low_quality {
+            println!(
+                "{} and {} are {} ms apart, too far to be sure of the match",
+                rgb.name,
+                nir.name,
+                rgb.abs_diff(&nir.datetime).as_millis()
+            );
+        }
+    }
+    let mut suspect = lopsided;
+    for pair in low_quality {
+        if !suspect.contains(&pair) {
+            suspect.push(pair);
+        }
+    }
+    let suspect_rgb = unique_files(suspect.iter().map(|(rgb, _)| *rgb));
+    let suspect_nir = unique_files(suspect.iter().map(|(_, nir)| *nir));
+
+    // Move all matched iiq files to camera dirs root, or the matched subdir
+    tracker.start(Phase::MovingMatched, matched_rgb.len() + matched_nir.len());
+    let suspect_paths: HashSet<&Path> = suspect
+        .iter()
+        .flat_map(|(rgb, nir)| [rgb.path(), nir.path()])
+        .collect();
+    mover.transfer(
+        matched_rgb
+            .iter()
+            .filter(|f| !suspect_paths.contains(f.path())),
+        rgb_dir,
+        "rgb",
+        FileStatus::Matched,
+        "matched RGB",
+        &mut || tracker.advance(1),
+    )?;
+    mover.transfer(
+        matched_nir
+            .iter()
+            .filter(|f| !suspect_paths.contains(f.path())),
+        nir_dir,
+        "nir",
+        FileStatus::Matched,
+        "matched NIR",
+        &mut || tracker.advance(1),
+    )?;
+    mover.transfer(
+        &suspect_rgb,
+        rgb_dir,
+        "rgb",
+        FileStatus::Suspect,
+        "suspect RGB",
+        &mut || tracker.advance(1),
+    )?;
+    mover.transfer(
+        &suspect_nir,
+        nir_dir,
+        "nir",
+        FileStatus::Suspect,
+        "suspect NIR",
+        &mut || tracker.advance(1),
+    )?;
+
+    // Move unmatched files, and the near misses to review
+    let (unmatched_rgb_moved, unmatched_nir_moved): (&[IIQFile], &[IIQFile]) =
+        if config.keep_unmatched_in_place {
+            (&[], &[])
+        } else {
+            (&unmatched_rgb.files, &unmatched_nir.files)
+        };
+    tracker.start(
+        Phase::MovingUnmatched,
+        review_rgb.len() + review_nir.len() + unmatched_rgb_moved.len() + unmatched_nir_moved.len(),
+    );
+    mover.transfer(
+        &review_rgb,
+        rgb_dir,
+        "rgb",
+        FileStatus::Review,
+        "review RGB",
+        &mut || tracker.advance(1),
+    )?;
+    mover.transfer(
+        &review_nir,
+        nir_dir,
+        "nir",
+        FileStatus::Review,
+        "review NIR",
+        &mut || tracker.advance(1),
+    )?;
+    mover.transfer(
+        unmatched_rgb_moved,
+        rgb_dir,
+        "rgb",
+        FileStatus::Unmatched,
+        "unmatched RGB",
+        &mut || tracker.advance(1),
+    )?;
+    mover.transfer(
+        unmatched_nir_moved,
+        nir_dir,
+        "nir",
+        FileStatus::Unmatched,
+        "unmatched NIR",
+        &mut || tracker.advance(1),
+    )?;
+
+    // Where the unmatched files are now, to feed them to a later run
+    let destinations = mover.journal.destinations();
+    let current_paths = |files: &IIQCollection| -> Vec<PathBuf> {
+        files
+            .files
+            .iter()
+            .map(|f| {
+                destinations
+                    .get(f.path())
+                    .unwrap_or(&f.path())
+                    .to_path_buf()
+            })
+            .collect()
+    };
+    let unmatched_rgb_paths = current_paths(&unmatched_rgb);
+    let unmatched_nir_paths = current_paths(&unmatched_nir);
+
+    let sidecars = if config.write_sidecars && !config.dry_run && mover.destination.is_none() {
+        sidecars(&pairs, &destinations)
+    } else {
+        Vec::new()
+    };
+    // In the journal, so they are removed again if anything fails
+    for (path, contents) in &sidecars {
+        mover.journal.write_file(path, contents)?;
+    }
+
+    let report = MatchReport {
+        rgb_count,
+        nir_count,
+        matched_count: matched_rgb.len(),
+        suspect_count: suspect.len(),
+        review_count: review.len(),
+        unmatched_rgb_count: unmatched_rgb.len(),
+        unmatched_nir_count: unmatched_nir.len(),
+        empty_rgb_count: empty_rgb_files_len,
+        empty_nir_count: empty_nir_files_len,
+        corrupt_rgb_count,
+        corrupt_nir_count,
+        off_hours_rgb_count,
+        off_hours_nir_count,
+        matched_bytes: matched_rgb.total_bytes() + matched_nir.total_bytes(),
+        unmatched_bytes: unmatched_rgb.total_bytes() + unmatched_nir.total_bytes(),
+        unmatched_rgb_paths,
+        unmatched_nir_paths,
+        deltas: joined.get_matched_deltas(&match_threshold),
+        pairs,
+        positions,
+        qualities,
+        over_threshold,
+        no_candidate,
+        discrepancies: Vec::new(),
+        count_skew: skewed.map(|(_, skew)| skew),
+        frame_interval: crowded.map(|(_, interval)| interval),
+        // Filled in by match_and_move, once the last phase is done
+        timings: Timings::default(),
+        plan: MovePlan {
+            moves: std::mem::take(&mut mover.plan),
+        },
+    };
+
+    Ok(report)
+}
+
+/// Run [`process_images`] on every RGB/NIR directory pair found under `root`.
+///
+/// Returns the session directory (the parent of each pair) along with its report. Use
+/// [`MatchReport::merge`] to total them.
+pub fn process_sessions(
+    root: &Path,
+    rgb_pattern: &str,
+    nir_pattern: &str,
+    case_sensitive: bool,
+    config: &ProcessConfig,
+) -> Result<Vec<(PathBuf, MatchReport)>> {
+    find_session_dirs(
+        root,
+        rgb_pattern,
+        nir_pattern,
+        case_sensitive,
+        config.follow_links,
+    )
+    .into_iter()
+    .map(|(rgb_dir, nir_dir)| {
+        let session_dir = rgb_dir.parent().unwrap_or(root).to_path_buf();
+        if config.verbosity.is_debug() {
+            println!("Processing session {}", session_dir.display());
+        }
+        let report = process_images(&rgb_dir, &nir_dir, config)?;
+        Ok((session_dir, report))
+    })
+    .collect()
+}
+
+fn remove_dir_if_empty(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        let is_empty = dir.read_dir()?.next().is_none();
+        if is_empty {
+            fs::remove_dir(dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the directories the files in `collection` were moved out of, such as a
+/// [`ProcessConfig::matched_subdir`], and their parents below `band_dir`, if nothing else is
+/// left in them.
+fn remove_left_dirs(collection: &IIQCollection, band_dir: &Path) -> Result<()> {
+    let band_dir = band_dir.canonicalize()?;
+    let dirs: HashSet<&Path> = collection
+        .iter()
+        .filter_map(|f| f.path().parent())
+        .collect();
+    for dir in dirs {
+        for dir in dir
+            .ancestors()
+            .take_while(|d| *d != band_dir && d.starts_with(&band_dir))
+        {
+            remove_dir_if_empty(dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// The directory `file` was originally in, or `None` if it is already there.
+///
+/// Files moved to an output directory with [`ProcessConfig::preserve_structure`] go back to the
+/// same place below `band_dir` as they are below the output directory. Otherwise, files go back
+/// to the directory named after their capture time, directly in `band_dir`, unless they are
+/// already in a directory with that name. If there is no such directory, files are taken to
+/// have been found in `band_dir` itself, so those in an output directory go back there.
+fn original_dir(file: &IIQFile, band_dir: &Path, subdirs: &[&Path]) -> Result<Option<PathBuf>> {
+    let band_dir = band_dir.canonicalize()?;
+    let parent = file.path().parent().unwrap_or(&band_dir);
+    let mut in_output_dir = false;
+    if let Ok(relative) = parent.strip_prefix(&band_dir) {
+        if let Some(rest) = subdirs
+            .iter()
+            .find_map(|dir| relative.strip_prefix(dir).ok())
+        {
+            in_output_dir = true;
+            if rest.components().next().is_some() {
+                return Ok(Some(band_dir.join(rest)));
+            }
+        }
+    }
+    if parent.file_name() == Some(file.original_parent_dir_name().as_ref()) {
+        return Ok(None);
+    }
+    let dated_dir = band_dir.join(file.original_parent_dir_name());
+    if !dated_dir.is_dir() {
+        return Ok(in_output_dir.then_some(band_dir));
+    }
+    Ok(Some(dated_dir))
+}
+
+/// Move the files of `collection` back to their [`original_dir`] and remove the directories
+/// left empty, returning how many files were moved.
+fn revert_band(
+    collection: &IIQCollection,
+    band_dir: &Path,
+    subdirs: &[&Path],
+    verbose: bool,
+) -> Result<usize> {
+    let mut reverted = 0;
+    for file in collection.iter() {
+        let sidecar = sidecar_path(file.path());
+        if sidecar.is_file() {
+            fs::remove_file(&sidecar)?;
+        }
+        let Some(dest) = original_dir(file, band_dir, subdirs)? else {
+            continue;
+        };
+        // A subdirectory of a file moved with `preserve_structure` may have been removed since
+        fs::create_dir_all(&dest)?;
+        filesystem::move_files(
+            vec![file.path.clone()],
+            &dest,
+            CollisionPolicy::Error,
+            verbose,
+        )?;
+        reverted += 1;
+    }
+    remove_left_dirs(collection, band_dir)?;
+    for name in subdirs {
+        remove_dir_if_empty(&band_dir.join(name))?;
+    }
+    Ok(reverted)
+}
+
+/// Move the files in `rgb_dir` and `nir_dir` back to where they were before processing,
+/// returning how many RGB and NIR files were, or with [`ProcessConfig::dry_run`] would be,
+/// moved. The file names are read as `config` says, so pass the one the files were processed
+/// with.
+///
+/// Files that are already in place are left alone, so reverting twice, or reverting
+/// directories that were never processed, moves nothing.
+pub fn revert_changes(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+) -> Result<(usize, usize)> {
+    check_rgb_nir_dirs_exist(rgb_dir, nir_dir)?;
+    let verbose = config.verbosity.is_debug();
+    let subdirs = output_subdirs(config);
+
+    // Find IIQ files, splitting a mixed directory the way it was split for processing
+    let (rgb_iiq_files, nir_iiq_files) = match config.compiled_band_regex()? {
+        Some(regex) => split_by_band(
+            config.find_moved_files(rgb_dir, &config.rgb_extensions)?,
+            &regex,
+        ),
+        None => (
+            config.find_moved_files(rgb_dir, &config.rgb_extensions)?,
+            config.find_moved_files(nir_dir, &config.nir_extensions)?,
+        ),
+    };
+
+    // Create collections, reading the names as they were read for processing
+    let datetime_regex = config.compiled_datetime_regex()?;
+    let read = |paths: &[PathBuf], format: &Option<String>| {
+        IIQCollection::with_name_parsing(paths, format.as_deref(), datetime_regex.as_ref())
+    };
+    let rgb_collection = read(&rgb_iiq_files, &config.rgb_datetime_format)?;
+    let nir_collection = read(&nir_iiq_files, &config.nir_datetime_format)?;
+
+    if config.dry_run {
+        let to_revert = |collection: &IIQCollection, band_dir: &Path| -> Result<usize> {
+            let mut count = 0;
+            for file in collection.iter() {
+                if original_dir(file, band_dir, &subdirs)?.is_some() {
+                    count += 1;
+                }
+            }
+            Ok(count)
+        };
+        return Ok((
+            to_revert(&rgb_collection, rgb_dir)?,
+            to_revert(&nir_collection, nir_dir)?,
+        ));
+    }
+
+    Ok((
+        revert_band(&rgb_collection, rgb_dir, &subdirs, verbose)?,
+        revert_band(&nir_collection, nir_dir, &subdirs, verbose)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+    use tempfile::TempDir;
+
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_iiq_file_new() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("210101_120000000.iiq");
+        fs::write(&path, "content").unwrap();
+
+        let file = IIQFile::new(&path).unwrap();
+        assert_eq!(file.stem, "210101_120000000");
+        let date = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+        let time = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        let dt = NaiveDateTime::new(date, time);
+        assert_eq!(file.datetime, dt);
+        assert_eq!(file.bytes, 7);
+        assert_eq!(file.path, path);
+        assert_eq!(file.name, "210101_120000000.iiq");
+    }
+
+    #[test]
+    fn test_iiq_file_new_without_millis() {
+        let temp_dir = TempDir::new().unwrap();
+        let parse = |s| NaiveDateTime::parse_from_str(s, "%y%m%d_%H%M%S%3f").unwrap();
+        for (name, expected) in [
+            ("210101_120000250.iiq", "210101_120000250"),
+            ("210101_120000.iiq", "210101_120000000"),
+            ("210101_120000_0042.iiq", "210101_120000000"),
+            ("210101_1200_0042.iiq", "210101_120000000"),
+        ] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, "content").unwrap();
+            assert_eq!(
+                IIQFile::new(&path).unwrap().datetime,
+                parse(expected),
+                "{name}"
+            );
+        }
+        let file = IIQFile::new(&temp_dir.path().join("210101_120000_0042.iiq")).unwrap();
+        assert_eq!(file.frame_index(), Some(42));
+
+        // Neither with nor without milliseconds
+        let path = temp_dir.path().join("210101_12000012.iiq");
+        fs::write(&path, "content").unwrap();
+        assert!(matches!(
+            IIQFile::new(&path),
+            Err(IxMatchError::InvalidFileName { .. })
+        ));
+    }
+
+    #[test]
+    fn test_iiq_file_new_short_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("IMG_01.iiq");
+        fs::write(&path, "content").unwrap();
+        let result = IIQFile::new(&path);
+        assert!(matches!(result, Err(IxMatchError::InvalidFileName { .. })));
+    }
+
+    #[test]
+    fn test_iiq_file_new_bad_datetime() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("210199_120000000.iiq");
+        fs::write(&path, "content").unwrap();
+
+        let result = IIQFile::new(&path);
+        assert!(matches!(result, Err(IxMatchError::ParseDatetime { .. })));
+    }
+
+    #[cfg(feature = "exif")]
+    #[test]
+    fn test_iiq_file_from_header() {
+        let temp_dir = TempDir::new().unwrap();
+        // Renamed file, its name is a second off the real capture time
+        let renamed = temp_dir.path().join("210101_120001000.iiq");
+        header::tests::write_tiff_with_capture_time(&renamed, "2021:01:01 12:00:00", "100");
+        let no_exif = temp_dir.path().join("210101_120002000.iiq");
+        fs::write(&no_exif, "content").unwrap();
+
+        let file = IIQFile::from_header(&renamed).unwrap();
+        assert_eq!(
+            file.datetime(),
+            NaiveDateTime::parse_from_str("210101_120000100", "%y%m%d_%H%M%S%3f").unwrap()
+        );
+
+        let file = IIQFile::from_header(&no_exif).unwrap();
+        assert_eq!(
+            file.datetime(),
+            NaiveDateTime::parse_from_str("210101_120002000", "%y%m%d_%H%M%S%3f").unwrap()
+        );
+
+        // Renamed to something that isn't a PhaseOne name at all
+        let site_name = temp_dir.path().join("site12_rgb_0042.iiq");
+        header::tests::write_tiff_with_capture_time(&site_name, "2021:01:01 12:00:00", "100");
+        let file = IIQFile::from_header(&site_name).unwrap();
+        assert_eq!(
+            file.datetime(),
+            NaiveDateTime::parse_from_str("210101_120000100", "%y%m%d_%H%M%S%3f").unwrap()
+        );
+        assert_eq!(file.name(), "site12_rgb_0042.iiq");
+        let no_exif = temp_dir.path().join("site12_rgb_0043.iiq");
+        fs::write(&no_exif, "content").unwrap();
+        assert!(matches!(
+            IIQFile::from_header(&no_exif),
+            Err(IxMatchError::InvalidFileName { .. })
+        ));
+    }
+
+    #[cfg(feature = "exif")]
+    #[test]
+    fn test_process_images_read_gps() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+
+        header::tests::write_tiff_with_gps_position(
+            &rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            50.1154,
+            -125.2226,
+        );
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+        // A pair without GPS in either file
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120001000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120001100.iiq"),
+            "content",
+        )
+        .unwrap();
+
+        let config = ProcessConfig::builder().read_gps(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.missing_positions(), 1);
+        let position = report.positions[0].unwrap();
+        assert!((position.latitude - 50.1154).abs() < 1e-6);
+        assert!((position.longitude + 125.2226).abs() < 1e-6);
+        assert!(report
+            .geojson()
+            .contains(r#""rgb_name":"210101_120000000.iiq""#));
+        assert!(!report.geojson().contains("210101_120001000.iiq"));
+    }
+
+    #[test]
+    fn test_make_iiq_collection() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        let files = vec![
+            base_path.join("210101_120000000.iiq"),
+            base_path.join("210101_120001000.iiq"),
+        ];
+
+        files.iter().for_each(|file| {
+            fs::write(file, "content").unwrap();
+        });
+
+        let collection = IIQCollection::new(&files).unwrap();
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection.paths(), files);
+    }
+
+    #[test]
+    fn test_collection_from_vec_is_sorted() {
+        let file = |name: &str| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap();
+        let files = vec![
+            file("210101_120002000.iiq"),
+            file("210101_120000000.iiq"),
+            file("210101_120001000.iiq"),
+        ];
+
+        let collection = IIQCollection::from(files.clone());
+        assert!(collection.is_sorted());
+        let names: Vec<&str> = collection.iter().map(|f| f.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "210101_120000000.iiq",
+                "210101_120001000.iiq",
+                "210101_120002000.iiq"
+            ]
+        );
+        assert!(!IIQCollection { files }.is_sorted());
+    }
+
+    #[test]
+    fn test_collection_from_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+        fs::create_dir(base_path.join("210101_1200")).unwrap();
+
+        fs::write(base_path.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(
+            base_path.join("210101_1200/210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(base_path.join("210101_120002000.IIQ"), "content").unwrap();
+        fs::write(base_path.join("notes.txt"), "content").unwrap();
+
+        let collection = IIQCollection::from_dir(base_path, &["iiq"]).unwrap();
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection.files[0].stem, "210101_120000000");
+        assert_eq!(collection.files[1].stem, "210101_120001000");
+
+        let collection = IIQCollection::from_dir(base_path, &["iiq", "IIQ"]).unwrap();
+        assert_eq!(collection.len(), 3);
+        assert_eq!(collection.files[2].stem, "210101_120002000");
+    }
+
+    #[test]
+    fn test_merge_collections() {
+        let temp_dir = TempDir::new().unwrap();
+        let card_a = temp_dir.path().join("a");
+        let card_b = temp_dir.path().join("b");
+        fs::create_dir_all(&card_a).unwrap();
+        fs::create_dir_all(&card_b).unwrap();
+
+        fs::write(card_a.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(card_a.join("210101_120002000.iiq"), "content").unwrap();
+        fs::write(card_b.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(card_b.join("210101_120003000.iiq"), "content").unwrap();
+
+        let a = IIQCollection::from_dir(&card_a, &["iiq"]).unwrap();
+        let b = IIQCollection::from_dir(&card_b, &["iiq"]).unwrap();
+        let b_again = b.clone();
+
+        let merged = a.merge(b).merge(b_again);
+        let stems: Vec<&str> = merged.files.iter().map(|f| f.stem.as_str()).collect();
+        assert_eq!(
+            stems,
+            vec![
+                "210101_120000000",
+                "210101_120001000",
+                "210101_120002000",
+                "210101_120003000"
+            ]
+        );
+        assert_eq!(
+            merged.files[1].path.parent().unwrap(),
+            card_b.canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_split_into_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        let files = vec![
+            base_path.join("210101_120000000.iiq"),
+            base_path.join("210101_120002000.iiq"),
+            base_path.join("210101_120004000.iiq"),
+            base_path.join("210101_120100000.iiq"),
+            base_path.join("210101_120102000.iiq"),
+        ];
+        files.iter().for_each(|file| {
+            fs::write(file, "content").unwrap();
+        });
+        let collection = IIQCollection::new(&files).unwrap();
+
+        let lines = collection.split_into_lines(Duration::from_secs(30));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].paths(), files[..3]);
+        assert_eq!(lines[1].paths(), files[3..]);
+
+        let lines = collection.split_into_lines(Duration::from_secs(1));
+        assert_eq!(lines.len(), 5);
+
+        assert!(IIQCollection::from(vec![])
+            .split_into_lines(Duration::from_secs(30))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_detect_gaps() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        // One frame every 2 s, with the one at 12:00:06 missing
+        let files = vec![
+            base_path.join("210101_120000000.iiq"),
+            base_path.join("210101_120002100.iiq"),
+            base_path.join("210101_120003900.iiq"),
+            base_path.join("210101_120008000.iiq"),
+            base_path.join("210101_120010000.iiq"),
+        ];
+        files.iter().for_each(|file| {
+            fs::write(file, "content").unwrap();
+        });
+        let collection = IIQCollection::new(&files).unwrap();
+
+        let gaps = collection.detect_gaps(Duration::from_secs(2), Duration::from_millis(200));
+        assert_eq!(gaps.len(), 1);
+        let (before, after, interval) = &gaps[0];
+        assert_eq!(before.path(), files[2]);
+        assert_eq!(after.path(), files[3]);
+        assert_eq!(*interval, Duration::from_millis(4100));
+
+        assert!(collection
+            .detect_gaps(Duration::from_secs(2), Duration::from_secs(3))
+            .is_empty());
+        assert!(IIQCollection::from(vec![])
+            .detect_gaps(Duration::from_secs(2), Duration::ZERO)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_filter_collection() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        let files = vec![
+            base_path.join("210101_050000000.iiq"),
+            base_path.join("210101_120000000.iiq"),
+            base_path.join("210101_153000000.iiq"),
+            base_path.join("210101_230000000.iiq"),
+        ];
+        files.iter().for_each(|file| {
+            fs::write(file, "content").unwrap();
+        });
+        let collection = IIQCollection::new(&files).unwrap();
+
+        // Leave out the nighttime captures
+        let morning = NaiveTime::from_hms_opt(6, 0, 0).unwrap();
+        let evening = NaiveTime::from_hms_opt(20, 0, 0).unwrap();
+        let daytime = collection.filter(|f| (morning..evening).contains(&f.datetime().time()));
+        assert_eq!(daytime.paths(), files[1..3]);
+        assert_eq!(collection.len(), 4);
+
+        assert!(collection.filter(|_| false).is_empty());
+    }
+
+    #[test]
+    fn test_collection_byte_totals() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+
+        fs::write(base_path.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(base_path.join("210101_120001000.iiq"), "").unwrap();
+        fs::write(base_path.join("210101_120002000.iiq"), "more content").unwrap();
+        fs::write(base_path.join("210101_120003000.iiq"), "").unwrap();
+
+        let collection = IIQCollection::from_dir(base_path, &["iiq"]).unwrap();
+        assert_eq!(collection.len(), 4);
+        assert_eq!(collection.empty_files_len(), 2);
+        assert_eq!(collection.non_empty_len(), 2);
+        assert_eq!(collection.total_bytes(), 19);
+    }
+
+    #[test]
+    fn test_join_collections() {
+        let temp_dir_rgb = TempDir::new().unwrap();
+        let rgb_files = vec![
+            temp_dir_rgb.path().join("210101_120000000.iiq"),
+            temp_dir_rgb.path().join("210101_120001000.iiq"),
+        ];
+        for file in &rgb_files {
+            fs::write(file, "content").unwrap();
+        }
+        let rgb_collection = IIQCollection::new(&rgb_files).unwrap();
+
+        let temp_dir_nir = TempDir::new().unwrap();
+        let nir_files = vec![
+            temp_dir_nir.path().join("210101_120000100.iiq"),
+            temp_dir_nir.path().join("210101_120001100.iiq"),
+        ];
+        for file in &nir_files {
+            fs::write(file, "content").unwrap();
+        }
+        let nir_collection = IIQCollection::new(&nir_files).unwrap();
+
+        let result =
+            JoinedIIQCollection::new(&rgb_collection, &nir_collection, TieBreak::default());
+
+        assert_eq!(
+            result.joined,
+            vec![
+                (
+                    Some(&rgb_collection.files[0]),
+                    Some(&nir_collection.files[0]),
+                    Duration::from_millis(100)
+                ),
+                (
+                    Some(&rgb_collection.files[1]),
+                    Some(&nir_collection.files[1]),
+                    Duration::from_millis(100)
+                ),
+            ]
+        );
+
+        let result =
+            JoinedIIQCollection::new(&rgb_collection, &nir_collection, TieBreak::default());
+        let pairs = result.get_matched_pairs(&Duration::from_millis(500));
+        assert_eq!(pairs.len(), 2);
+        for (i, (rgb, nir, dt)) in pairs.iter().enumerate() {
+            assert_eq!(rgb, &rgb_collection.files[i]);
+            assert_eq!(nir, &nir_collection.files[i]);
+            assert_eq!(*dt, Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_process_images_by_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // NIR clock is ~1 hour behind
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120002000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120004000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_110000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_110002150.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_110004200.iiq"), "content").unwrap();
+
+        let mut config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 0);
+
+        config.match_strategy = MatchStrategy::ByOrder {
+            validate_monotonic: true,
+        };
+        config.dry_run = false;
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 3);
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_abs_diff() {
+        let mut file = IIQFile::new_unchecked(&PathBuf::from("210101_120000000.iiq"), 1).unwrap();
+        let later = file.datetime + TimeDelta::milliseconds(1500);
+        assert_eq!(file.abs_diff(&later), Duration::from_millis(1500));
+        assert_eq!(file.abs_diff(&file.datetime), Duration::ZERO);
+
+        // As far apart as datetimes go, in both directions
+        file.datetime = NaiveDateTime::MIN;
+        let far = file.abs_diff(&NaiveDateTime::MAX);
+        assert!(far > Duration::from_secs(500_000 * 365 * 24 * 3600));
+        file.datetime = NaiveDateTime::MAX;
+        assert_eq!(file.abs_diff(&NaiveDateTime::MIN), far);
+    }
+
+    #[test]
+    fn test_process_images_many_to_one() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+
+        // The NIR camera fires twice for each RGB frame
+        let rgb_files = ["210101_120000000.iiq", "210101_120001000.iiq"];
+        let nir_files = [
+            "210101_120000050.iiq",
+            "210101_120000300.iiq",
+            "210101_120001050.iiq",
+            "210101_120001300.iiq",
+        ];
+        for name in rgb_files {
+            fs::write(rgb_dir.join("210101_1200").join(name), "content").unwrap();
+        }
+        for name in nir_files {
+            fs::write(nir_dir.join("210101_1200").join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.unmatched_nir_count, 2);
+
+        let config = ProcessConfig::builder()
+            .match_strategy(MatchStrategy::ManyToOne)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.unmatched_rgb_count, 0);
+        assert_eq!(report.unmatched_nir_count, 0);
+        assert_eq!(report.pairs.len(), 4);
+        let groups: Vec<(&str, Vec<&str>)> = report
+            .groups()
+            .into_iter()
+            .map(|(rgb, nirs)| (rgb.name(), nirs.iter().map(|n| n.name()).collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (rgb_files[0], vec![nir_files[0], nir_files[1]]),
+                (rgb_files[1], vec![nir_files[2], nir_files[3]]),
+            ]
+        );
+        for name in rgb_files {
+            assert!(rgb_dir.join(name).exists());
+        }
+        for name in nir_files {
+            assert!(nir_dir.join(name).exists());
+        }
+    }
+
+    #[test]
+    fn test_process_config_builder() {
+        assert_eq!(ProcessConfig::builder().build(), ProcessConfig::default());
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(100))
+            .exclude("**/thumbnails")
+            .exclude("**/*.tmp")
+            .min_quality(0.5)
+            .build();
+        assert_eq!(
+            config,
+            ProcessConfig {
+                match_threshold: Duration::from_millis(100),
+                exclude: vec!["**/thumbnails".to_string(), "**/*.tmp".to_string()],
+                min_quality: Some(0.5),
+                ..Default::default()
+            }
+        );
+
+        // Changing one option of an existing config
+        let dry_run = ProcessConfigBuilder::from(config.clone())
+            .dry_run(true)
+            .build();
+        assert!(dry_run.dry_run);
+        assert_eq!(dry_run.exclude, config.exclude);
+    }
+
+    #[test]
+    fn test_process_images_with_classifier() {
+        use chrono::Timelike;
+
+        /// Sends matched frames taken on an odd second to their own directory
+        struct OddSeconds;
+        impl Classifier for OddSeconds {
+            fn destination(
+                &self,
+                file: &IIQFile,
+                status: FileStatus,
+                config: &ProcessConfig,
+            ) -> PathBuf {
+                if status == FileStatus::Matched && file.datetime().second() % 2 == 1 {
+                    PathBuf::from("odd")
+                } else {
+                    DefaultClassifier.destination(file, status, config)
+                }
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        for name in ["210101_120000000.iiq", "210101_120001000.iiq"] {
+            fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        for name in [
+            "210101_120000100.iiq",
+            "210101_120001100.iiq",
+            "210101_120005000.iiq",
+        ] {
+            fs::write(nir_dir.join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig::default();
+        let report =
+            process_images_with_classifier(&rgb_dir, &nir_dir, &config, &OddSeconds).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(rgb_dir.join("odd").join("210101_120001000.iiq").exists());
+        assert!(nir_dir.join("odd").join("210101_120001100.iiq").exists());
+        // Falls back to the default for the rest
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.iiq")
+            .exists());
+    }
+
+    #[test]
+    fn test_process_images_datetime_formats() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // PhaseOne names on the RGB camera, whole seconds and a frame number on the NIR one
+        for name in ["210101_120000000.iiq", "210101_120005100.iiq"] {
+            fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        for name in [
+            "2021-01-01_12-00-00_0001.iiq",
+            "2021-01-01_12-00-05_0002.iiq",
+        ] {
+            fs::write(nir_dir.join(name), "content").unwrap();
+        }
+        fs::write(nir_dir.join("2021-01-01_12-00-30_0003.iiq"), "content").unwrap();
+
+        // Both bands are parsed the same way by default
+        let result = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default());
+        assert!(matches!(result, Err(IxMatchError::ParseDatetime { .. })));
+
+        let config = ProcessConfig::builder()
+            .nir_datetime_format("%Y-%m-%d_%H-%M-%S")
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.pairs[0].1.name(), "2021-01-01_12-00-00_0001.iiq");
+        assert_eq!(report.pairs[1].2, Duration::from_millis(100));
+        assert!(nir_dir
+            .join("unmatched/2021-01-01_12-00-30_0003.iiq")
+            .exists());
+    }
+
+    #[test]
+    fn test_process_images_band_regex() {
+        let temp_dir = TempDir::new().unwrap();
+        let mixed_dir = temp_dir.path().join("mixed");
+        fs::create_dir_all(&mixed_dir).unwrap();
+        for name in [
+            "210101_120000000_RGB.iiq",
+            "210101_120000100_NIR.iiq",
+            "210101_120010000_RGB.iiq",
+            "210101_120010050_NIR.iiq",
+            "210101_120020000_RGB.iiq",
+        ] {
+            fs::write(mixed_dir.join(name), "content").unwrap();
+        }
+
+        let paths = find_files(&mixed_dir, "iiq", None, &[], true).unwrap();
+        let (rgb, nir) = split_by_band(paths, &Regex::new(r"_RGB\.iiq$").unwrap());
+        assert_eq!(rgb.len(), 3);
+        assert_eq!(nir.len(), 2);
+        assert!(nir
+            .iter()
+            .all(|path| path.to_string_lossy().ends_with("_NIR.iiq")));
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .band_regex(r"_RGB\.iiq$")
+            .datetime_regex(r"^(\d{6}_\d{9})_")
+            .matched_subdir("matched")
+            .build();
+        let report = process_images(&mixed_dir, &mixed_dir, &config).unwrap();
+        assert_eq!(report.rgb_count, 3);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.unmatched_rgb_count, 1);
+        assert_eq!(report.unmatched_nir_count, 0);
+        assert_eq!(
+            report.pairs[0].0.path().file_name().unwrap(),
+            "210101_120000000_RGB.iiq"
+        );
+        assert_eq!(
+            report.pairs[0].1.path().file_name().unwrap(),
+            "210101_120000100_NIR.iiq"
+        );
+        assert!(mixed_dir
+            .join("matched")
+            .join("210101_120010050_NIR.iiq")
+            .exists());
+        assert!(mixed_dir
+            .join("unmatched")
+            .join("210101_120020000_RGB.iiq")
+            .exists());
+
+        // Each file is reverted once, with the band it was processed as
+        assert_eq!(
+            revert_changes(&mixed_dir, &mixed_dir, &config).unwrap(),
+            (3, 2)
+        );
+        for name in [
+            "210101_120000000_RGB.iiq",
+            "210101_120010050_NIR.iiq",
+            "210101_120020000_RGB.iiq",
+        ] {
+            assert!(mixed_dir.join(name).exists());
+        }
+        assert!(!mixed_dir.join("matched").exists());
+        assert!(!mixed_dir.join("unmatched").exists());
+
+        // The bands still go to their own output directories
+        let out_dir = temp_dir.path().join("out");
+        let config = ProcessConfigBuilder::from(config)
+            .output_dir(&out_dir)
+            .build();
+        process_images(&mixed_dir, &mixed_dir, &config).unwrap();
+        assert!(out_dir
+            .join("rgb")
+            .join("matched")
+            .join("210101_120010000_RGB.iiq")
+            .exists());
+        assert!(out_dir
+            .join("nir")
+            .join("matched")
+            .join("210101_120010050_NIR.iiq")
+            .exists());
+        assert!(out_dir
+            .join("rgb")
+            .join("unmatched")
+            .join("210101_120020000_RGB.iiq")
+            .exists());
+        assert!(!out_dir
+            .join("rgb")
+            .join("matched")
+            .join("210101_120010050_NIR.iiq")
+            .exists());
+
+        let config = ProcessConfig::builder()
+            .band_regex("(")
+            .dry_run(true)
+            .build();
+        assert!(matches!(
+            process_images(&mixed_dir, &mixed_dir, &config),
+            Err(IxMatchError::InvalidBandRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn test_band_files_band_regex() {
+        let temp_dir = TempDir::new().unwrap();
+        let mixed_dir = temp_dir.path().join("mixed");
+        fs::create_dir_all(&mixed_dir).unwrap();
+        for name in [
+            "210101_120000000_RGB.iiq",
+            "210101_120000100_NIR.iiq",
+            "210101_120010000_RGB.iiq",
+        ] {
+            fs::write(mixed_dir.join(name), "content").unwrap();
+        }
+        let config = ProcessConfig::builder().band_regex(r"_RGB\.iiq$").build();
+
+        // What --list shows for each band
+        let (rgb_paths, nir_paths) = band_files(&mixed_dir, &mixed_dir, &config).unwrap();
+        assert_eq!(rgb_paths.len(), 2);
+        assert_eq!(nir_paths.len(), 1);
+        assert!(nir_paths[0].ends_with("210101_120000100_NIR.iiq"));
+
+        // And what --sweep counts, rather than each file matching itself
+        let rgb = IIQCollection::new(&rgb_paths).unwrap();
+        let nir = IIQCollection::new(&nir_paths).unwrap();
+        let thresholds = [10, 200].map(Duration::from_millis);
+        assert_eq!(
+            count_matches_at(&rgb, &nir, &thresholds, &config).unwrap(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_process_images_datetime_regex() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("SITE12_210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("SITE12_210101_120000100.iiq"), "content").unwrap();
+
+        let result = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default());
+        assert!(matches!(result, Err(IxMatchError::ParseDatetime { .. })));
+
+        let config = ProcessConfig::builder()
+            .datetime_regex(r"_(\d{6}_\d{9})$")
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(
+            report.pairs[0].0.datetime(),
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        );
+        assert_eq!(report.pairs[0].2, Duration::from_millis(100));
+
+        // Without a capture group, and with a band's own format
+        let regex = Regex::new(r"\d{4}-\d{2}-\d{2}T\d{6}").unwrap();
+        let path = PathBuf::from("SITE12_2021-01-01T120000_0042.iiq");
+        fs::write(temp_dir.path().join(&path), "content").unwrap();
+        let file = IIQFile::with_datetime_regex(
+            &temp_dir.path().join(&path),
+            &regex,
+            Some("%Y-%m-%dT%H%M%S"),
+        )
+        .unwrap();
+        assert_eq!(file.datetime().to_string(), "2021-01-01 12:00:00");
+        let no_match = temp_dir.path().join("SITE12.iiq");
+        fs::write(&no_match, "content").unwrap();
+        assert!(matches!(
+            IIQFile::with_datetime_regex(&no_match, &regex, None),
+            Err(IxMatchError::InvalidFileName { .. })
+        ));
+
+        let config = ProcessConfig::builder().datetime_regex("(").build();
+        assert!(matches!(
+            process_images(&rgb_dir, &nir_dir, &config),
+            Err(IxMatchError::InvalidDatetimeRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn test_process_images_modified_since() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        let old_rgb = rgb_dir.join("210101_120000000.iiq");
+        let old_nir = nir_dir.join("210101_120000100.iiq");
+        fs::write(&old_rgb, "content").unwrap();
+        fs::write(&old_nir, "content").unwrap();
+        fs::write(rgb_dir.join("210101_130000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_130000100.iiq"), "content").unwrap();
+
+        // The first pair was copied from an earlier card dump
+        let a_day_ago = SystemTime::now() - Duration::from_secs(86_400);
+        for path in [&old_rgb, &old_nir] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(a_day_ago)
+                .unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .modified_since(SystemTime::now() - Duration::from_secs(3600))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.rgb_count, 1);
+        assert_eq!(report.nir_count, 1);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.pairs[0].0.name(), "210101_130000000.iiq");
+        // The older files are left where they were
+        assert!(old_rgb.exists());
+        assert!(old_nir.exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_process_images_time_of_day() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        // A daytime pair, and one triggered by accident at night
+        for (dir, name) in [
+            (&rgb_dir, "210101_120000000.iiq"),
+            (&rgb_dir, "210101_230000000.iiq"),
+            (&nir_dir, "210101_120000100.iiq"),
+            (&nir_dir, "210101_230000100.iiq"),
+        ] {
+            let dir = dir.join(&name[..11]);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .time_of_day(
+                NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            )
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.off_hours_rgb_count, 1);
+        assert_eq!(report.off_hours_nir_count, 1);
+        assert_eq!(report.unmatched_rgb_count, 0);
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(rgb_dir
+            .join("offhours")
+            .join("210101_230000000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("offhours")
+            .join("210101_230000100.iiq")
+            .exists());
+
+        // Reverting puts them back
+        revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        assert!(rgb_dir
+            .join("210101_2300")
+            .join("210101_230000000.iiq")
+            .exists());
+        assert!(!rgb_dir.join("offhours").exists());
+
+        // A window spanning midnight keeps the night pair instead
+        let config = ProcessConfig::builder()
+            .time_of_day(
+                NaiveTime::from_hms_opt(20, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(4, 0, 0).unwrap(),
+            )
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.pairs[0].0.name(), "210101_230000000.iiq");
+        assert_eq!(report.off_hours_rgb_count, 1);
+    }
+
+    #[test]
+    fn test_process_images_directional_thresholds() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        for name in ["210101_120000000.iiq", "210101_120010000.iiq"] {
+            fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        // The first NIR frame lags by 300 ms as expected, the second leads by 100 ms
+        for name in ["210101_120000300.iiq", "210101_120009900.iiq"] {
+            fs::write(nir_dir.join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .match_threshold_before(Duration::from_millis(50))
+            .match_threshold_after(Duration::from_millis(500))
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.pairs[0].1.name(), "210101_120000300.iiq");
+        assert_eq!(report.unmatched_rgb_count, 1);
+        assert_eq!(report.unmatched_nir_count, 1);
+        assert_eq!(report.over_threshold.len(), 1);
+        assert_eq!(report.over_threshold[0].1.name(), "210101_120009900.iiq");
+
+        // Either way is fine with the same threshold on both sides
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+    }
+
+    #[test]
+    fn test_process_images_check_locations() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120020000.iiq"), "").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .matched_subdir("matched")
+            .check_locations(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.discrepancies, vec![]);
+
+        // Copies add to the count expected afterwards
+        let copy_dir = temp_dir.path().join("copy");
+        let (copy_rgb, copy_nir) = (copy_dir.join("rgb"), copy_dir.join("nir"));
+        fs::create_dir_all(&copy_rgb).unwrap();
+        fs::create_dir_all(&copy_nir).unwrap();
+        fs::write(copy_rgb.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(copy_nir.join("210101_120000100.iiq"), "content").unwrap();
+        let config = ProcessConfigBuilder::from(config)
+            .file_op(FileOp::Copy)
+            .build();
+        let report = process_images(&copy_rgb, &copy_nir, &config).unwrap();
+        assert_eq!(report.discrepancies, vec![]);
+
+        // A file lost after it was moved is reported, along with the count
+        let src = temp_dir.path().join("src");
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("210101_130000000.iiq"), "content").unwrap();
+        fs::write(src.join("210101_130001000.iiq"), "content").unwrap();
+        let mut journal = Journal::default();
+        filesystem::transfer_files(
+            vec![
+                src.join("210101_130000000.iiq"),
+                src.join("210101_130001000.iiq"),
+            ],
+            &dest,
+            TransferOptions::new(FileOp::Move, CollisionPolicy::Error, false),
+            &mut journal,
+            &mut || {},
+        )
+        .unwrap();
+        assert_eq!(find_discrepancies(&journal, 2, 2), vec![]);
+
+        fs::remove_file(dest.join("210101_130000000.iiq")).unwrap();
+        fs::write(src.join("210101_130001000.iiq"), "content").unwrap();
+        assert_eq!(
+            find_discrepancies(&journal, 2, 1),
+            vec![
+                Discrepancy::Missing {
+                    path: dest.join("210101_130000000.iiq")
+                },
+                Discrepancy::NotRemoved {
+                    path: src.join("210101_130001000.iiq")
+                },
+                Discrepancy::CountChanged {
+                    expected: 2,
+                    found: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_images_sidecars_filtered() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // Sidecars from an earlier run, next to a file sorted again, one off hours and one out
+        // of range
+        for name in ["210101_120000000", "210101_230000000", "210102_120000000"] {
+            fs::write(rgb_dir.join(format!("{name}.iiq")), "content").unwrap();
+            fs::write(rgb_dir.join(format!("{name}.match.json")), "{}").unwrap();
+        }
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+
+        let parse = |s| NaiveDateTime::parse_from_str(s, "%y%m%d_%H%M%S%3f").unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(50))
+            .end(parse("210101_235959000"))
+            .time_of_day(
+                NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            )
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 0);
+
+        assert!(!rgb_dir.join("210101_120000000.match.json").exists());
+        assert!(rgb_dir.join("210101_230000000.match.json").exists());
+        assert!(rgb_dir.join("210102_120000000.match.json").exists());
+    }
+
+    #[test]
+    fn test_process_images_write_sidecars() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000150.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .matched_subdir("matched")
+            .write_sidecars(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        assert_eq!(
+            fs::read_to_string(rgb_dir.join("matched").join("210101_120000000.match.json")).unwrap(),
+            "{\"file\":\"210101_120000000.iiq\",\"band\":\"rgb\",\"partner\":\"210101_120000150.iiq\",\"delta_ms\":150}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(nir_dir.join("matched").join("210101_120000150.match.json")).unwrap(),
+            "{\"file\":\"210101_120000150.iiq\",\"band\":\"nir\",\"partner\":\"210101_120000000.iiq\",\"delta_ms\":150}\n"
+        );
+        // Only matched files get one
+        assert!(!rgb_dir
+            .join("unmatched")
+            .join("210101_120010000.match.json")
+            .exists());
+
+        // A file no longer matched loses its sidecar when run again
+        let rerun = ProcessConfigBuilder::from(config.clone())
+            .match_threshold(Duration::from_millis(50))
+            .force(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &rerun).unwrap();
+        assert_eq!(report.matched_count, 0);
+        assert!(rgb_dir
+            .join("unmatched")
+            .join("210101_120000000.iiq")
+            .exists());
+        assert!(!rgb_dir
+            .join("matched")
+            .join("210101_120000000.match.json")
+            .exists());
+        assert!(!rgb_dir
+            .join("unmatched")
+            .join("210101_120000000.match.json")
+            .exists());
+
+        // Sidecars are removed again when reverting
+        let config = ProcessConfigBuilder::from(config).force(true).build();
+        process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert!(rgb_dir
+            .join("matched")
+            .join("210101_120000000.match.json")
+            .exists());
+        revert_changes(&rgb_dir, &nir_dir, &config).unwrap();
+        for dir in [&rgb_dir, &nir_dir] {
+            let sidecars = fs::read_dir(dir)
+                .unwrap()
+                .filter(|entry| {
+                    entry
+                        .as_ref()
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .ends_with(".match.json")
+                })
+                .count();
+            assert_eq!(sidecars, 0);
+        }
+        assert!(!rgb_dir.join("matched").exists());
+        assert!(!nir_dir.join("matched").exists());
+    }
+
+    #[test]
+    fn test_process_images_keep_unmatched_in_place() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120020000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .matched_subdir("matched")
+            .keep_unmatched_in_place(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.unmatched_rgb_count, 1);
+        assert_eq!(report.unmatched_nir_count, 1);
+        let (rgb_dir, nir_dir) = (
+            rgb_dir.canonicalize().unwrap(),
+            nir_dir.canonicalize().unwrap(),
+        );
+        assert_eq!(
+            report.unmatched_rgb_paths,
+            vec![rgb_dir.join("210101_120010000.iiq")]
+        );
+        assert_eq!(
+            report.unmatched_nir_paths,
+            vec![nir_dir.join("210101_120020000.iiq")]
+        );
+        assert!(rgb_dir.join("210101_120010000.iiq").exists());
+        assert!(nir_dir.join("210101_120020000.iiq").exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+        // Matched files are still moved
+        assert!(rgb_dir
+            .join("matched")
+            .join("210101_120000000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("matched")
+            .join("210101_120000100.iiq")
+            .exists());
+    }
+
+    #[test]
+    fn test_process_images_unmatched_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        let rgb_dir = rgb_dir.canonicalize().unwrap();
+        assert_eq!(
+            report.unmatched_rgb_paths,
+            vec![rgb_dir.join("210101_120010000.iiq")]
+        );
+        assert!(report.unmatched_nir_paths.is_empty());
+
+        // The moved files are listed where they were moved to
+        let config = ProcessConfigBuilder::from(config).dry_run(false).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        let unmatched = rgb_dir.join("unmatched").join("210101_120010000.iiq");
+        assert_eq!(report.unmatched_rgb_paths, vec![unmatched.clone()]);
+        assert!(unmatched.exists());
+
+        // Matching the listed file again once its partner has turned up
+        fs::write(nir_dir.join("210101_120010100.iiq"), "content").unwrap();
+        let config = ProcessConfigBuilder::from(config)
+            .rgb_paths(report.unmatched_rgb_paths)
+            .force(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert!(rgb_dir.join("210101_120010000.iiq").exists());
+    }
+
+    #[test]
+    fn test_process_images_line_offsets() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // Two lines a minute apart, frames every 2s, with the NIR clock 300 ms ahead on the
+        // first and 250 ms behind on the second
+        let name = |s: u64, ms: u64| format!("210101_12{:02}{:02}{:03}.iiq", s / 60, s % 60, ms);
+        for i in 0..5 {
+            fs::write(rgb_dir.join(name(i * 2, 0)), "content").unwrap();
+            fs::write(nir_dir.join(name(i * 2, 300)), "content").unwrap();
+            fs::write(rgb_dir.join(name(70 + i * 2, 500)), "content").unwrap();
+            fs::write(nir_dir.join(name(70 + i * 2, 250)), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(100))
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 0);
+
+        let config = ProcessConfigBuilder::from(config)
+            .line_offset_gap(Duration::from_secs(30))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 10);
+        assert!(report.deltas.iter().all(|dt| dt.is_zero()));
+        let pairs = matched_pairs(
+            &IIQCollection::from_dir(&rgb_dir, &["iiq"]).unwrap(),
+            &IIQCollection::from_dir(&nir_dir, &["iiq"]).unwrap(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(pairs.len(), 10);
+        for (rgb, nir, _) in &pairs {
+            assert_eq!(rgb.name()[..13], nir.name()[..13]);
+        }
+    }
+
+    #[test]
+    fn test_process_images_max_thresh_interval_ratio() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // Both cameras fire every 400 ms
+        let name = |ms: u64| format!("210101_1200{:02}{:03}.iiq", ms / 1000, ms % 1000);
+        for i in 0..5 {
+            fs::write(rgb_dir.join(name(i * 400)), "content").unwrap();
+            fs::write(nir_dir.join(name(i * 400 + 10)), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(300))
+            .max_thresh_interval_ratio(0.5)
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.frame_interval, Some(Duration::from_millis(400)));
+        assert_eq!(report.matched_count, 5);
+
+        let config = ProcessConfigBuilder::from(config)
+            .match_threshold(Duration::from_millis(100))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.frame_interval, None);
+
+        let config = ProcessConfigBuilder::from(config)
+            .match_threshold(Duration::from_millis(300))
+            .dry_run(false)
+            .strict(true)
+            .build();
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::ThresholdExceedsInterval { interval, .. })
+                if interval == Duration::from_millis(400)
+        ));
+        assert!(!rgb_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_process_images_max_count_skew() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // The NIR camera stopped after the first frame
+        for i in 0..10 {
+            let name = format!("210101_1200{:02}000.iiq", i * 2);
+            fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .max_count_skew(3.0)
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.count_skew, Some(10.0));
+        assert_eq!(report.matched_count, 1);
+
+        let config = ProcessConfig::builder().max_count_skew(20.0).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.count_skew, None);
+        revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+
+        let config = ProcessConfig::builder()
+            .max_count_skew(3.0)
+            .strict(true)
+            .build();
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::CountSkew {
+                rgb: 10,
+                nir: 1,
+                ..
+            })
+        ));
+        assert!(!rgb_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_process_images_min_match_rate() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // Only the first of 10 NIR frames is close to its RGB frame
+        for i in 0..10 {
+            let secs = i * 5;
+            fs::write(
+                rgb_dir.join(format!("210101_1200{secs:02}000.iiq")),
+                "content",
+            )
+            .unwrap();
+            let offset = if i == 0 { 100 } else { 900 };
+            fs::write(
+                nir_dir.join(format!("210101_1200{secs:02}{offset:03}.iiq")),
+                "content",
+            )
+            .unwrap();
+        }
+        fs::write(rgb_dir.join("210101_130000000.iiq"), "").unwrap();
+
+        let config = ProcessConfig::builder().min_match_rate(0.9).build();
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::LowMatchRate {
+                matched: 1,
+                possible: 10,
+                ..
+            })
+        ));
+        // Nothing was left moved
+        assert!(rgb_dir.join("210101_130000000.iiq").exists());
+        assert!(!rgb_dir.join("empty").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+
+        let config = ProcessConfig::builder().min_match_rate(0.1).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+    }
+
+    #[test]
+    fn test_sweep_thresholds() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        for (rgb, nir, content) in [
+            ("210101_120000000.iiq", "210101_120000100.iiq", "content"),
+            // Empty
+            ("210101_120010000.iiq", "210101_120010100.iiq", ""),
+            ("210101_120020000.iiq", "210101_120020100.iiq", "content"),
+            // Outside the time of day
+            ("210101_130000000.iiq", "210101_130000100.iiq", "content"),
+            // Matched by an earlier run
+            ("210101_120030000.iiq", "210101_120030100.iiq", "content"),
+        ] {
+            fs::write(rgb_dir.join(rgb), "content").unwrap();
+            fs::write(nir_dir.join(nir), content).unwrap();
+        }
+        let manifest = temp_dir.path().join("manifest.txt");
+        fs::write(
+            &manifest,
+            "ix-match manifest v1\nrgb\t210101_120030000.iiq\nnir\t210101_120030100.iiq\n",
+        )
+        .unwrap();
+        let cache = temp_dir.path().join("scan.cache");
+        let config = ProcessConfig::builder()
+            .time_of_day(
+                NaiveTime::from_hms_opt(11, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(12, 30, 0).unwrap(),
+            )
+            .manifest(&manifest)
+            .cache(&cache)
+            .build();
+
+        let thresholds = [10, 200].map(Duration::from_millis);
+        let counts = sweep_thresholds(&rgb_dir, &nir_dir, &thresholds, &config).unwrap();
+        assert_eq!(counts, vec![0, 2]);
+        // The files were read through the cache, as a run reads them
+        assert!(cache.exists());
+
+        // Which is what a run at that threshold matches
+        let config = ProcessConfigBuilder::from(config)
+            .match_threshold(Duration::from_millis(200))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, counts[1]);
+    }
+
+    #[test]
+    fn test_count_matches_at() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // Offsets of 50, 150, 250 and 450 ms
+        for name in [
+            "210101_120000000.iiq",
+            "210101_120010000.iiq",
+            "210101_120020000.iiq",
+            "210101_120030000.iiq",
+        ] {
+            fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        for name in [
+            "210101_120000050.iiq",
+            "210101_120010150.iiq",
+            "210101_120020250.iiq",
+            "210101_120030450.iiq",
+        ] {
+            fs::write(nir_dir.join(name), "content").unwrap();
+        }
+        let rgb = IIQCollection::from_dir(&rgb_dir, &["iiq"]).unwrap();
+        let nir = IIQCollection::from_dir(&nir_dir, &["iiq"]).unwrap();
+
+        let thresholds = [10, 100, 200, 300, 500].map(Duration::from_millis);
+        let counts = count_matches_at(&rgb, &nir, &thresholds, &ProcessConfig::default()).unwrap();
+        assert_eq!(counts, vec![0, 1, 2, 3, 4]);
+        assert!(counts.windows(2).all(|w| w[0] <= w[1]));
+
+        // Nothing was moved
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+
+        // The directional thresholds cap the counts like they cap a run's matches
+        let nir_early = IIQCollection::from(vec![
+            IIQFile::new_unchecked(&PathBuf::from("210101_115959950.iiq"), 1).unwrap(),
+            IIQFile::new_unchecked(&PathBuf::from("210101_120009850.iiq"), 1).unwrap(),
+            IIQFile::new_unchecked(&PathBuf::from("210101_120020250.iiq"), 1).unwrap(),
+            IIQFile::new_unchecked(&PathBuf::from("210101_120030450.iiq"), 1).unwrap(),
+        ]);
+        let config = ProcessConfig::builder()
+            .match_threshold_before(Duration::from_millis(100))
+            .match_threshold_after(Duration::from_millis(300))
+            .build();
+        let counts = count_matches_at(&rgb, &nir_early, &thresholds, &config).unwrap();
+        // 50 ms before, 150 ms before, 250 ms after and 450 ms after
+        assert_eq!(counts, vec![0, 1, 1, 2, 2]);
+        let config = ProcessConfigBuilder::from(config)
+            .match_threshold(Duration::from_millis(500))
+            .build();
+        assert_eq!(matched_pairs(&rgb, &nir_early, &config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_matched_pairs() {
+        let collection = |names: &[&str]| -> IIQCollection {
+            names
+                .iter()
+                .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+                .collect::<Vec<_>>()
+                .into()
+        };
+        let rgb = collection(&[
+            "210101_120000000.iiq",
+            "210101_120010000.iiq",
+            "210101_120020000.iiq",
+        ]);
+        let nir = collection(&[
+            "210101_115959900.iiq",
+            "210101_120010150.iiq",
+            "210101_120020450.iiq",
+        ]);
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .build();
+
+        let joined = JoinedIIQCollection::new(&rgb, &nir, config.tie_break);
+        let borrowed: Vec<(IIQFile, IIQFile, Duration)> = joined
+            .get_matched_with_dt(&config.match_threshold)
+            .into_iter()
+            .map(|(rgb, nir, dt)| (rgb.clone(), nir.clone(), dt))
+            .collect();
+        let owned = matched_pairs(&rgb, &nir, &config).unwrap();
+        assert_eq!(owned.len(), 2);
+        assert_eq!(owned, borrowed);
+
+        // They outlive the collections
+        drop(joined);
+        drop((rgb, nir));
+        assert_eq!(owned[1].1.name(), "210101_120010150.iiq");
+        assert_eq!(owned[1].2, Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_joined_table() {
+        let collection = |names: &[&str]| -> IIQCollection {
+            names
+                .iter()
+                .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+                .collect::<Vec<_>>()
+                .into()
+        };
+        let rgb = collection(&[
+            "210101_120000000.iiq",
+            "210101_120010000.iiq",
+            "210101_120020000.iiq",
+            "210101_120030000.iiq",
+        ]);
+        let nir = collection(&[
+            "210101_120000100.iiq",
+            "210101_120012000.iiq",
+            "210101_120030050.iiq",
+        ]);
+        let config = ProcessConfig::default();
+
+        let table = joined_table(&rgb, &nir, &config).unwrap();
+        let rows: Vec<(Option<&str>, Option<&str>, Duration)> = table
+            .iter()
+            .map(|(rgb, nir, dt)| {
+                (
+                    rgb.as_ref().map(|f| f.name()),
+                    nir.as_ref().map(|f| f.name()),
+                    *dt,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (
+                    Some("210101_120000000.iiq"),
+                    Some("210101_120000100.iiq"),
+                    Duration::from_millis(100)
+                ),
+                // Over the threshold, but still in the table
+                (
+                    Some("210101_120010000.iiq"),
+                    Some("210101_120012000.iiq"),
+                    Duration::from_millis(2000)
+                ),
+                (Some("210101_120020000.iiq"), None, Duration::MAX),
+                (
+                    Some("210101_120030000.iiq"),
+                    Some("210101_120030050.iiq"),
+                    Duration::from_millis(50)
+                ),
+            ]
+        );
+        assert_eq!(matched_pairs(&rgb, &nir, &config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_preview_pairs() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |names: &[&str]| -> Vec<PathBuf> {
+            names
+                .iter()
+                .map(|name| {
+                    let path = temp_dir.path().join(name);
+                    fs::write(&path, "content").unwrap();
+                    path
+                })
+                .collect()
+        };
+        let rgb_paths = write(&[
+            "210101_120000000.iiq",
+            "210101_120010000.iiq",
+            "210101_120020000.iiq",
+        ]);
+        fs::create_dir(temp_dir.path().join("nir")).unwrap();
+        let nir_paths = write(&[
+            "nir/210101_120000100.iiq",
+            "nir/210101_120011000.iiq",
+            "nir/210101_120020300.iiq",
+        ]);
+        let config = ProcessConfig::default();
+
+        let full = matched_pairs(
+            &IIQCollection::new(&rgb_paths).unwrap(),
+            &IIQCollection::new(&nir_paths).unwrap(),
+            &config,
+        )
+        .unwrap();
+        let preview = preview_pairs(&rgb_paths, &nir_paths, &config).unwrap();
+        let paths = |pairs: &[(IIQFile, IIQFile, Duration)]| -> Vec<(PathBuf, PathBuf, Duration)> {
+            pairs
+                .iter()
+                .map(|(rgb, nir, dt)| (rgb.path().to_path_buf(), nir.path().to_path_buf(), *dt))
+                .collect()
+        };
+        assert_eq!(full.len(), 2);
+        assert_eq!(paths(&preview), paths(&full));
+        assert!(preview
+            .iter()
+            .all(|(rgb, nir, _)| rgb.bytes() == 0 && nir.bytes() == 0));
+
+        // The files are never looked at
+        fs::remove_dir_all(temp_dir.path()).unwrap();
+        let preview = preview_pairs(&rgb_paths, &nir_paths, &config).unwrap();
+        assert_eq!(paths(&preview), paths(&full));
+    }
+
+    #[test]
+    fn test_match_stream_equals_batch() {
+        // Capture times with irregular gaps, some close enough to tie
+        let collection = |count: usize, seed: u64| -> IIQCollection {
+            let start = NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap();
+            let mut state = seed;
+            let mut millis = 0;
+            (0..count)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    millis += (state >> 33) % 4 * 250;
+                    let datetime = start + TimeDelta::milliseconds(millis as i64);
+                    let name = format!("{}.iiq", datetime.format("%y%m%d_%H%M%S%3f"));
+                    IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap()
+                })
+                .collect::<Vec<_>>()
+                .into()
+        };
+
+        for (rgb_count, nir_count) in [(40, 40), (30, 45), (45, 30), (0, 5), (5, 0), (0, 0)] {
+            let rgb = collection(rgb_count, 1);
+            let nir = collection(nir_count, 2);
+            for tie_break in [TieBreak::PreferEarlier, TieBreak::PreferLater] {
+                let config = ProcessConfig::builder()
+                    .match_threshold(Duration::from_millis(300))
+                    .match_threshold_before(Duration::from_millis(200))
+                    .tie_break(tie_break)
+                    .build();
+                let (before, after) = config.directional_thresholds();
+                let joined = JoinedIIQCollection::new(&rgb, &nir, tie_break)
+                    .with_direction_limits(before, after);
+                let max_dt = before.max(after);
+
+                let decisions: Vec<MatchDecision> = match_stream(&rgb, &nir, &config).collect();
+                let matched: Vec<(&IIQFile, &IIQFile, Duration)> = decisions
+                    .iter()
+                    .filter_map(|decision| match *decision {
+                        MatchDecision::Matched { rgb, nir, dt } => Some((rgb, nir, dt)),
+                        MatchDecision::Unmatched { .. } => None,
+                    })
+                    .collect();
+                let unmatched: Vec<(Option<&IIQFile>, Option<&IIQFile>)> = decisions
+                    .iter()
+                    .filter_map(|decision| match *decision {
+                        MatchDecision::Unmatched { rgb, nir } => Some((rgb, nir)),
+                        MatchDecision::Matched { .. } => None,
+                    })
+                    .collect();
+                assert_eq!(matched, joined.get_matched_with_dt(&max_dt));
+                assert_eq!(unmatched, joined.get_unmatched(&max_dt));
+                assert_eq!(decisions.len(), rgb_count.max(nir_count));
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_index() {
+        let file = |name: &str| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap();
+        assert_eq!(file("240101_120000000_0042.iiq").frame_index(), Some(42));
+        assert_eq!(file("240101_1200_0042.iiq").frame_index(), Some(42));
+        assert_eq!(file("240101_1200.iiq").frame_index(), None);
+        assert_eq!(
+            file("240101_120000000_Camera_RGB_0007.iiq").frame_index(),
+            Some(7)
+        );
+        assert_eq!(file("240101_120000000.iiq").frame_index(), None);
+        assert_eq!(file("240101_120000000_Camera_RGB.iiq").frame_index(), None);
+    }
+
+    #[test]
+    fn test_process_images_by_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("240101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("240101_1200")).unwrap();
+
+        // The NIR clock is well off, so these would not match by time
+        let rgb_files = ["240101_120000000_0041.iiq", "240101_120001000_0042.iiq"];
+        let nir_files = ["240101_120010500_0041.iiq", "240101_120011500_0042.iiq"];
+        for name in rgb_files {
+            fs::write(rgb_dir.join("240101_1200").join(name), "content").unwrap();
+        }
+        for name in nir_files {
+            fs::write(nir_dir.join("240101_1200").join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .match_strategy(MatchStrategy::ByIndex)
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        let pairs: Vec<(&str, &str)> = report
+            .pairs
+            .iter()
+            .map(|(rgb, nir, _)| (rgb.name(), nir.name()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [(rgb_files[0], nir_files[0]), (rgb_files[1], nir_files[1])]
+        );
+
+        // Without an index on every file, this falls back to matching by time
+        fs::write(
+            nir_dir.join("240101_1200").join("240101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            rgb_dir
+                .join("240101_1200")
+                .join("240101_120020000_0043.iiq"),
+            "content",
+        )
+        .unwrap();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.pairs[0].1.name(), "240101_120000100.iiq");
+
+        // Names only to the minute, where the index is all that tells the files apart
+        let rgb_dir = temp_dir.path().join("minutes/rgb");
+        let nir_dir = temp_dir.path().join("minutes/nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        for name in ["240101_1200_0041.iiq", "240101_1200_0042.iiq"] {
+            fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        for name in ["240101_1200_0042.iiq", "240101_1200_0041.iiq"] {
+            fs::write(nir_dir.join(name), "content").unwrap();
+        }
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        for (rgb, nir, _) in &report.pairs {
+            assert_eq!(rgb.name(), nir.name());
+        }
+        assert!(report
+            .pairs
+            .iter()
+            .any(|(rgb, _, _)| rgb.name() == "240101_1200_0042.iiq"));
+    }
+
+    #[test]
+    fn test_join_by_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_path = temp_dir.path();
+        let make = |names: &[&str]| {
+            let paths: Vec<PathBuf> = names.iter().map(|n| base_path.join(n)).collect();
+            paths.iter().for_each(|p| fs::write(p, "content").unwrap());
+            IIQCollection::new(&paths).unwrap()
+        };
+        let rgb = make(&["210101_120000000.iiq", "210101_120002000.iiq"]);
+        let nir = make(&[
+            "210101_110000100.iiq",
+            "210101_110002100.iiq",
+            "210101_110004100.iiq",
+        ]);
+
+        let joined = JoinedIIQCollection::by_order(&rgb, &nir, true).unwrap();
+        assert_eq!(joined.joined.len(), 3);
+        assert_eq!(joined.joined[0].0, Some(&rgb.files[0]));
+        assert_eq!(joined.joined[0].1, Some(&nir.files[0]));
+        assert_eq!(joined.joined[2].0, None);
+        assert_eq!(joined.joined[2].1, Some(&nir.files[2]));
+
+        // Offset grows then shrinks, as if a frame was dropped
+        let rgb = make(&[
+            "210101_130000000.iiq",
+            "210101_130002000.iiq",
+            "210101_130004000.iiq",
+        ]);
+        let nir = make(&[
+            "210101_130000100.iiq",
+            "210101_130002300.iiq",
+            "210101_130004100.iiq",
+        ]);
+        assert!(JoinedIIQCollection::by_order(&rgb, &nir, false).is_ok());
+        assert!(matches!(
+            JoinedIIQCollection::by_order(&rgb, &nir, true),
+            Err(IxMatchError::NonMonotonicOrder { .. })
+        ));
+    }
+
+    #[test]
+    fn test_collection() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Create test files
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120001100.iiq"), "content").unwrap();
+
+        let rgb_files = filesystem::find_files(&rgb_dir, "iiq", None, &[], true).unwrap();
+        let nir_files = filesystem::find_files(&nir_dir, "iiq", None, &[], true).unwrap();
+
+        let rgb_collection = IIQCollection::new(&rgb_files).unwrap();
+        let nir_collection = IIQCollection::new(&nir_files).unwrap();
+
+        assert_eq!(rgb_collection.len(), 2);
+        assert_eq!(nir_collection.len(), 2);
+    }
+
+    #[test]
+    fn test_process_images() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Create test files
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120001100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.empty_rgb_count, 0);
+        assert_eq!(report.empty_nir_count, 0);
+        let stats = report.delta_stats().unwrap();
+        assert_eq!(stats.median, Duration::from_millis(100));
+        assert_eq!(stats.max, Duration::from_millis(100));
+
+        // Check if files are in their original locations
+        // (process_images doesn't move matched files in this case)
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(rgb_dir.join("210101_120001000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(nir_dir.join("210101_120001100.iiq").exists());
+
+        // Unmatched directories should not be created in this case
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_process_images_dry_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Create test files
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap(); // This one won't match
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .keep_empty_files(true)
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.empty_rgb_count, 0);
+        assert_eq!(report.empty_nir_count, 0);
+
+        // Check if all files are in their original locations (dry run)
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(rgb_dir.join("210101_120001000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(nir_dir.join("210101_120005000.iiq").exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_process_images_band_extensions() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.IIQ"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120001100.IIQ"), "content").unwrap();
+
+        // Only the RGB files are found with the default extension
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!((report.rgb_count, report.nir_count), (2, 0));
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .nir_extensions(vec!["IIQ".to_string()])
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!((report.rgb_count, report.nir_count), (2, 2));
+        assert_eq!(report.matched_count, 2);
+        assert!(nir_dir.join("210101_120000100.IIQ").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+
+        // An extension listed twice still finds each file once
+        let config = ProcessConfig::builder()
+            .rgb_extensions(vec!["iiq".to_string(), "iiq".to_string()])
+            .dry_run(true)
+            .build();
+        assert_eq!(
+            config
+                .find_files(&rgb_dir, &config.rgb_extensions)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Reverting finds the files with the same extensions
+        fs::write(nir_dir.join("210101_120005000.IIQ"), "content").unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .nir_extensions(vec!["IIQ".to_string()])
+            .force(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.unmatched_nir_count, 1);
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.IIQ")
+            .exists());
+        assert_eq!(revert_changes(&rgb_dir, &nir_dir, &config).unwrap(), (0, 1));
+        assert!(nir_dir.join("210101_120005000.IIQ").exists());
+    }
+
+    #[test]
+    fn test_process_images_non_canonical_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        for dir in ["rgb/download", "nir/download", "other"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("rgb/download/210101_120000000.iiq"), "content").unwrap();
+        fs::write(root.join("rgb/download/210101_120002000.iiq"), "").unwrap();
+        fs::write(root.join("nir/download/210101_120000100.iiq"), "content").unwrap();
+        // The same directories as given, but not as found
+        let rgb_dir = root.join("other/../rgb");
+        let nir_dir = root.join("other/../nir");
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .matched_subdir("matched")
+            .preserve_structure(true)
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        let targets: Vec<_> = report.plan.moves.iter().map(|m| m.to.clone()).collect();
+        assert_eq!(
+            targets,
+            vec![
+                root.join("rgb/empty/download/210101_120002000.iiq"),
+                root.join("rgb/matched/download/210101_120000000.iiq"),
+                root.join("nir/matched/download/210101_120000100.iiq"),
+            ]
+        );
+
+        let config = ProcessConfigBuilder::from(config).dry_run(false).build();
+        process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert!(root
+            .join("rgb/matched/download/210101_120000000.iiq")
+            .exists());
+        assert!(root
+            .join("rgb/empty/download/210101_120002000.iiq")
+            .exists());
+        assert!(root
+            .join("nir/matched/download/210101_120000100.iiq")
+            .exists());
+    }
+
+    #[test]
+    fn test_process_images_dry_run_plan() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120002000.iiq"), "").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .matched_subdir("matched")
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        let planned = |band: &Path, name: &str, dir: &str, status| PlannedMove {
+            from: band.join(name),
+            to: band.join(dir).join(name),
+            status,
+        };
+        assert_eq!(
+            report.plan.moves,
+            vec![
+                planned(&rgb_dir, "210101_120002000.iiq", "empty", FileStatus::Empty),
+                planned(
+                    &rgb_dir,
+                    "210101_120000000.iiq",
+                    "matched",
+                    FileStatus::Matched
+                ),
+                planned(
+                    &nir_dir,
+                    "210101_120000100.iiq",
+                    "matched",
+                    FileStatus::Matched
+                ),
+                planned(
+                    &nir_dir,
+                    "210101_120005000.iiq",
+                    "unmatched",
+                    FileStatus::Unmatched
+                ),
+            ]
+        );
+
+        // Nothing was moved
+        for planned in &report.plan.moves {
+            assert!(planned.from.exists());
+            assert!(!planned.to.exists());
+        }
+        assert!(!rgb_dir.join("empty").exists());
+        assert!(!nir_dir.join("matched").exists());
+        // Dry runs leave the empty files in to be matched, so the empty file counts as
+        // unmatched too, but is only planned once
+        assert_eq!(report.empty_rgb_count, 1);
+        assert_eq!(report.unmatched_rgb_count, 1);
+
+        // Nothing is planned for real runs
+        let config = ProcessConfigBuilder::from(config).dry_run(false).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert!(report.plan.is_empty());
+        assert_eq!(report.empty_rgb_count, 1);
+        assert_eq!(report.unmatched_rgb_count, 0);
+    }
+
+    #[test]
+    fn test_process_images_with_unmatched() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Create test files
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // These won't match
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .keep_empty_files(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.empty_rgb_count, 0);
+        assert_eq!(report.empty_nir_count, 0);
+        assert_eq!(report.matched_bytes, 14);
+        assert_eq!(report.unmatched_bytes, 14);
+
+        // Check if matched files are in their original locations
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+
+        // Check if unmatched files are moved to the unmatched directory
+        assert!(rgb_dir
+            .join("unmatched")
+            .join("210101_120001000.iiq")
+            .exists());
+        assert!(!rgb_dir.join("210101_120001000.iiq").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.iiq")
+            .exists());
+        assert!(!nir_dir.join("210101_120005000.iiq").exists());
+    }
+
+    #[test]
+    fn test_process_images_with_uneven_numbers() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Create test files
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // These won't match
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .keep_empty_files(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.rgb_count, 1);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.empty_rgb_count, 0);
+        assert_eq!(report.empty_nir_count, 0);
+
+        // Check if matched files are in their original locations
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+
+        // Check if unmatched files are moved to the unmatched directory
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.iiq")
+            .exists());
+        assert!(!nir_dir.join("210101_120005000.iiq").exists());
+    }
+
+    #[test]
+    fn test_process_images_strict() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // This won't match
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        let config = ProcessConfigBuilder::from(config).strict(true).build();
+        match process_images(&rgb_dir, &nir_dir, &config) {
+            Err(IxMatchError::Unmatched { rgb, nir, report }) => {
+                assert_eq!((rgb, nir), (0, 1));
+                assert_eq!(report.matched_count, 1);
+            }
+            other => panic!("Expected Unmatched error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_process_images_validate() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "II*\0content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "II*\0content").unwrap();
+        // A truncated transfer, which would otherwise match
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120001100.iiq"), "II*\0content").unwrap();
+
+        let config = ProcessConfig::builder().validate(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.corrupt_rgb_count, 1);
+        assert_eq!(report.corrupt_nir_count, 0);
+        assert_eq!(report.matched_count, 1);
+        assert!(rgb_dir
+            .join("corrupt")
+            .join("210101_120001000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120001100.iiq")
+            .exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_process_images_size_ratio() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Comparable sizes
+        fs::write(rgb_dir.join("210101_120000000.iiq"), vec![1; 100]).unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), vec![1; 120]).unwrap();
+        // The NIR file is a fraction of the RGB one
+        fs::write(rgb_dir.join("210101_120001000.iiq"), vec![1; 100]).unwrap();
+        fs::write(nir_dir.join("210101_120001100.iiq"), vec![1; 10]).unwrap();
+
+        let config = ProcessConfig::builder().size_ratio_max(1.5).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.suspect_count, 1);
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(rgb_dir
+            .join("suspect")
+            .join("210101_120001000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("suspect")
+            .join("210101_120001100.iiq")
+            .exists());
+    }
+
+    #[test]
+    fn test_process_images_min_quality() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // 10 ms apart, quality 0.98
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000010.iiq"), "content").unwrap();
+        // 400 ms apart, quality 0.2
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120001400.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder().min_quality(0.5).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.suspect_count, 1);
+        assert_eq!(report.qualities.len(), 2);
+        assert!((report.qualities[0] - 0.98).abs() < 1e-9);
+        assert!((report.qualities[1] - 0.2).abs() < 1e-9);
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000010.iiq").exists());
+        assert!(rgb_dir
+            .join("suspect")
+            .join("210101_120001000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("suspect")
+            .join("210101_120001400.iiq")
+            .exists());
+
+        // Qualities are scaled by the threshold on the side the NIR file is on
+        let (rgb_dir, nir_dir) = (temp_dir.path().join("rgb2"), temp_dir.path().join("nir2"));
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        // 700 ms after of 1 s, quality 0.3
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000700.iiq"), "content").unwrap();
+        // 400 ms before of 500 ms, quality 0.2
+        fs::write(rgb_dir.join("210101_120005000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120004600.iiq"), "content").unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold_after(Duration::from_secs(1))
+            .min_quality(0.25)
+            .dry_run(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert!((report.qualities[0] - 0.3).abs() < 1e-9);
+        assert!((report.qualities[1] - 0.2).abs() < 1e-9);
+        assert_eq!(report.suspect_count, 1);
+    }
+
+    #[test]
+    fn test_process_images_with_destination() {
+        use std::cell::RefCell;
+
+        /// Records the writes instead of doing them
+        #[derive(Default)]
+        struct Recorder {
+            puts: RefCell<Vec<(PathBuf, PathBuf)>>,
+        }
+
+        impl Destination for Recorder {
+            fn put(&self, src: &Path, rel: &Path) -> Result<()> {
+                self.puts
+                    .borrow_mut()
+                    .push((src.to_path_buf(), rel.to_path_buf()));
+                Ok(())
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("CAMERA_RGB");
+        let nir_dir = temp_dir.path().join("CAMERA_NIR");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120020000.iiq"), "").unwrap();
+
+        let recorder = Recorder::default();
+        let report = process_images_with_destination(
+            &rgb_dir,
+            &nir_dir,
+            &ProcessConfig::default(),
+            &recorder,
+        )
+        .unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        let mut puts = recorder.puts.into_inner();
+        puts.sort();
+        let expected = [
+            (
+                &nir_dir,
+                "210101_120000100.iiq",
+                "CAMERA_NIR/210101_120000100.iiq",
+            ),
+            (
+                &nir_dir,
+                "210101_120020000.iiq",
+                "CAMERA_NIR/empty/210101_120020000.iiq",
+            ),
+            (
+                &rgb_dir,
+                "210101_120000000.iiq",
+                "CAMERA_RGB/210101_120000000.iiq",
+            ),
+            (
+                &rgb_dir,
+                "210101_120010000.iiq",
+                "CAMERA_RGB/unmatched/210101_120010000.iiq",
+            ),
+        ]
+        .map(|(dir, name, rel)| (dir.join(name), PathBuf::from(rel)));
+        assert_eq!(puts, expected);
+
+        // Nothing was moved
+        assert!(nir_dir.join("210101_120020000.iiq").exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("empty").exists());
+    }
+
+    #[test]
+    fn test_process_images_review_threshold() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+
+        // Within the threshold
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // Between the threshold and the review threshold
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120010800.iiq"), "content").unwrap();
+        // Beyond both
+        fs::write(rgb_dir.join("210101_120020000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120022000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(500))
+            .review_threshold(Duration::from_millis(1000))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.review_count, 1);
+        assert_eq!(report.unmatched_rgb_count, 1);
+        assert_eq!(report.unmatched_nir_count, 1);
+        assert_eq!(report.over_threshold.len(), 1);
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(rgb_dir.join("review").join("210101_120010000.iiq").exists());
+        assert!(nir_dir.join("review").join("210101_120010800.iiq").exists());
+        assert!(rgb_dir
+            .join("unmatched")
+            .join("210101_120020000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120022000.iiq")
+            .exists());
+
+        // Reverting puts the files to review back too
+        revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        assert!(rgb_dir.join("210101_120010000.iiq").exists());
+        assert!(!rgb_dir.join("review").exists());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+
+        let collection = IIQCollection::from_dir(&nir_dir, &["iiq"]).unwrap();
+        let json = serde_json::to_string(&collection).unwrap();
+        assert!(json.contains("\"2021-01-01T12:00:00.100\""));
+        assert!(json.contains(&format!("{:?}", collection[0].path())));
+        let loaded: IIQCollection = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.files, collection.files);
+
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        let loaded: MatchReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, report);
+        assert_eq!(loaded.pairs.len(), 1);
+    }
+
+    #[test]
+    fn test_process_collections() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120005000.iiq"),
+            "content",
+        )
+        .unwrap();
+
+        let rgb = IIQCollection::from_dir(&rgb_dir, &["iiq"]).unwrap();
+        let nir = IIQCollection::from_dir(&nir_dir, &["iiq"]).unwrap();
+        let report =
+            process_collections(rgb, nir, &rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.unmatched_nir_count, 1);
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.iiq")
+            .exists());
+    }
+
+    #[test]
+    fn test_process_images_matched_subdir() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+
+        let rgb_file = rgb_dir.join("210101_1200").join("210101_120000000.iiq");
+        let nir_file = nir_dir.join("210101_1200").join("210101_120000100.iiq");
+        fs::write(&rgb_file, "content").unwrap();
+        fs::write(&nir_file, "content").unwrap();
+        // This one won't match
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120005000.iiq"),
+            "content",
+        )
+        .unwrap();
+
+        let config = ProcessConfig::builder().matched_subdir("matched").build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.matched_count, 1);
+        assert!(rgb_dir
+            .join("matched")
+            .join("210101_120000000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("matched")
+            .join("210101_120000100.iiq")
+            .exists());
+        assert!(!rgb_file.exists());
+        assert!(!nir_file.exists());
+        assert!(!rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.iiq")
+            .exists());
+
+        // Reverting puts the files back and removes the emptied subdirectory
+        revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        assert!(rgb_file.exists());
+        assert!(nir_file.exists());
+        assert!(!rgb_dir.join("matched").exists());
+        assert!(!nir_dir.join("matched").exists());
+
+        // Files found in the band directory itself go back there, given the subdirectory
+        let (rgb_dir, nir_dir) = (temp_dir.path().join("rgb2"), temp_dir.path().join("nir2"));
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert!(rgb_dir
+            .join("matched")
+            .join("210101_120000000.iiq")
+            .exists());
 
-    // Create collections
-    let mut rgb_collection = IIQCollection::new(&rgb_iiq_files)?;
-    let mut nir_collection = IIQCollection::new(&nir_iiq_files)?;
+        // The subdirectory is output of an earlier run, like unmatched/
+        assert!(matches!(
+            process_images(&rgb_dir, &nir_dir, &config),
+            Err(IxMatchError::AlreadyProcessed { .. })
+        ));
 
-    // Get 0 byte file counts
-    let empty_rgb_files_len = rgb_collection.empty_files_len();
-    let empty_nir_files_len = nir_collection.empty_files_len();
+        assert_eq!(revert_changes(&rgb_dir, &nir_dir, &config).unwrap(), (1, 1));
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(!rgb_dir.join("matched").exists());
+        assert!(!nir_dir.join("matched").exists());
+    }
 
-    if !keep_empty_files && !dry_run {
-        // Move empty files
-        let empty_rgb_files = rgb_collection.pop_empty_files();
-        let empty_nir_files = nir_collection.pop_empty_files();
+    #[test]
+    fn test_process_images_subdir_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
 
-        if empty_rgb_files.len() > 0 {
-            let empty_rgb_dir = rgb_dir.join("empty");
-            if verbose {
-                println!("Moving empty RGB files to {:?}", empty_rgb_dir);
-            }
-            fs::create_dir_all(&empty_rgb_dir)?;
-            filesystem::move_files(empty_rgb_files.paths(), &empty_rgb_dir, verbose)?;
-        }
+        let config = ProcessConfig::builder()
+            .subdir_name(FileStatus::Unmatched, "no_match")
+            .subdir_name(FileStatus::Empty, "zero")
+            .build();
+        assert_eq!(config.subdir_name(FileStatus::Unmatched), Some("no_match"));
+        assert_eq!(config.subdir_name(FileStatus::Corrupt), Some("corrupt"));
+        assert_eq!(config.subdir_name(FileStatus::Matched), None);
+
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert!(nir_dir.join("no_match/210101_120005000.iiq").exists());
+        assert!(rgb_dir.join("zero/210101_120010000.iiq").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+        assert!(!rgb_dir.join("empty").exists());
 
-        if empty_nir_files.len() > 0 {
-            let empty_nir_dir = nir_dir.join("empty");
-            if verbose {
-                println!("Moving empty NIR files to {:?}", empty_nir_dir);
-            }
-            fs::create_dir_all(&empty_nir_dir)?;
-            filesystem::move_files(empty_nir_files.paths(), &empty_nir_dir, verbose)?;
-        }
-    }
+        // The renamed subdirectories are output of an earlier run too
+        assert!(matches!(
+            process_images(&rgb_dir, &nir_dir, &config),
+            Err(IxMatchError::AlreadyProcessed { .. })
+        ));
 
-    // Do the join
-    let joined = JoinedIIQCollection::new(&rgb_collection, &nir_collection)?;
+        assert_eq!(revert_changes(&rgb_dir, &nir_dir, &config).unwrap(), (1, 1));
+        assert!(nir_dir.join("210101_120005000.iiq").exists());
+        assert!(rgb_dir.join("210101_120010000.iiq").exists());
+        assert!(!nir_dir.join("no_match").exists());
+        assert!(!rgb_dir.join("zero").exists());
+    }
 
-    let matched_rgb = joined.get_matched_rgb(&match_threshold);
-    let matched_nir = joined.get_matched_nir(&match_threshold);
-    let unmatched_rgb = joined.get_unmatched_rgb(&match_threshold);
-    let unmatched_nir = joined.get_unmatched_nir(&match_threshold);
+    #[test]
+    fn test_process_images_preserve_structure() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        for line in ["lineA", "lineB"] {
+            fs::create_dir_all(rgb_dir.join(line).join("210101_1200")).unwrap();
+            fs::create_dir_all(nir_dir.join(line).join("210101_1200")).unwrap();
+        }
 
-    if !dry_run {
-        // Move all matched iiq files to camera dirs root
-        filesystem::move_files(matched_rgb.paths(), rgb_dir, verbose)?;
-        filesystem::move_files(matched_nir.paths(), nir_dir, verbose)?;
+        let matched_rgb = rgb_dir
+            .join("lineA")
+            .join("210101_1200")
+            .join("210101_120000000.iiq");
+        fs::write(&matched_rgb, "content").unwrap();
+        fs::write(
+            nir_dir
+                .join("lineA")
+                .join("210101_1200")
+                .join("210101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+        // These won't match
+        let unmatched_rgb = rgb_dir
+            .join("lineA")
+            .join("210101_1200")
+            .join("210101_120010000.iiq");
+        let unmatched_nir = nir_dir
+            .join("lineB")
+            .join("210101_1200")
+            .join("210101_120020000.iiq");
+        fs::write(&unmatched_rgb, "content").unwrap();
+        fs::write(&unmatched_nir, "content").unwrap();
+
+        let config = ProcessConfig::builder().preserve_structure(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        // Matched files stay where they are, unmatched ones keep their subdirectories
+        assert!(matched_rgb.exists());
+        assert!(!unmatched_rgb.exists());
+        assert!(!unmatched_nir.exists());
+        assert!(rgb_dir
+            .join("unmatched")
+            .join("lineA")
+            .join("210101_1200")
+            .join("210101_120010000.iiq")
+            .exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("lineB")
+            .join("210101_1200")
+            .join("210101_120020000.iiq")
+            .exists());
 
-        // Move unmatched files
-        if unmatched_rgb.len() > 0 {
-            let unmatched_rgb_dir = rgb_dir.join("unmatched");
-            if verbose {
-                println!("Moving unmatched RGB files to {:?}", unmatched_rgb_dir);
-            }
-            fs::create_dir_all(&unmatched_rgb_dir)?;
-            filesystem::move_files(unmatched_rgb.paths(), &unmatched_rgb_dir, verbose)?;
-        }
-        if unmatched_nir.len() > 0 {
-            let unmatched_nir_dir = nir_dir.join("unmatched");
-            if verbose {
-                println!("Moving unmatched NIR files to {:?}", unmatched_nir_dir);
-            }
-            fs::create_dir_all(&unmatched_nir_dir)?;
-            filesystem::move_files(unmatched_nir.paths(), &unmatched_nir_dir, verbose)?;
-        }
+        revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        assert!(matched_rgb.exists());
+        assert!(unmatched_rgb.exists());
+        assert!(unmatched_nir.exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("unmatched").exists());
     }
 
-    Ok((
-        rgb_iiq_files.len(),
-        nir_iiq_files.len(),
-        matched_rgb.len(),
-        empty_rgb_files_len,
-        empty_nir_files_len,
-    ))
-}
+    #[test]
+    fn test_process_images_twice() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
 
-fn remove_dir_if_empty(dir: &Path) -> Result<()> {
-    if dir.exists() {
-        let is_empty = dir.read_dir()?.next().is_none();
-        if is_empty {
-            fs::remove_dir(dir)?;
-        }
+        // Create test files
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // This one won't match
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let mut config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .build();
+        process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert!(nir_dir.join("unmatched").exists());
+
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::AlreadyProcessed { dir, .. }) if dir == nir_dir
+        ));
+
+        // Dry runs don't move anything so are allowed
+        config.dry_run = true;
+        process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        config.dry_run = false;
+        config.force = true;
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
     }
-    Ok(())
-}
 
-pub fn revert_changes(
-    rgb_dir: &Path,
-    nir_dir: &Path,
-    dry_run: bool,
-    verbose: bool,
-) -> Result<(usize, usize)> {
-    check_rgb_nir_dirs_exist(rgb_dir, nir_dir)?;
+    #[test]
+    fn test_process_images_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
 
-    // Find IIQ files
-    let rgb_iiq_files = filesystem::find_files(rgb_dir, "iiq")?;
-    let nir_iiq_files = filesystem::find_files(nir_dir, "iiq")?;
+        // Create test files
+        fs::write(rgb_dir.join("210101_1200/210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_1200/210101_120000100.iiq"), "content").unwrap();
+        // This one won't match
+        fs::write(nir_dir.join("210101_1200/210101_120005000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .file_op(FileOp::Copy)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        // Originals are untouched
+        assert!(rgb_dir.join("210101_1200/210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_1200/210101_120000100.iiq").exists());
+        assert!(nir_dir.join("210101_1200/210101_120005000.iiq").exists());
+
+        // And copies are where the files would have been moved to
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(nir_dir.join("unmatched/210101_120005000.iiq").exists());
+    }
 
-    // Create collections
-    let rgb_collection = IIQCollection::new(&rgb_iiq_files)?;
-    let nir_collection = IIQCollection::new(&nir_iiq_files)?;
-
-    if !dry_run {
-        for file in rgb_collection.iter() {
-            let dest = &rgb_dir.join(file.original_parent_dir_name());
-            if dest.exists() {
-                filesystem::move_files(vec![file.path.clone()], dest, verbose)?;
-            } else {
-                eprintln!("Parent directory does not exist for file {}", file.name);
-            }
+    #[test]
+    fn test_process_images_listed_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        for name in ["210101_120000000.iiq", "210101_120010000.iiq"] {
+            fs::write(rgb_dir.join(name), "content").unwrap();
         }
-        remove_dir_if_empty(&rgb_dir.join("empty"))?;
-        remove_dir_if_empty(&rgb_dir.join("unmatched"))?;
+        for name in ["210101_120000100.iiq", "210101_120010100.iiq"] {
+            fs::write(nir_dir.join(name), "content").unwrap();
+        }
+        // Picked out elsewhere, not in the NIR directory
+        let selected = temp_dir.path().join("selected");
+        fs::create_dir_all(&selected).unwrap();
+        fs::write(selected.join("210101_120020100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .rgb_paths(vec![rgb_dir.join("210101_120010000.iiq")])
+            .nir_paths(vec![
+                nir_dir.join("210101_120010100.iiq"),
+                selected.join("210101_120020100.iiq"),
+            ])
+            .check_locations(true)
+            .build();
+        // --list and --sweep start from the listed files too
+        let (rgb_paths, nir_paths) = band_files(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(rgb_paths, [rgb_dir.join("210101_120010000.iiq")]);
+        assert_eq!(nir_paths.len(), 2);
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        // The listed file from outside the directories counts before and after the run
+        assert_eq!(report.discrepancies, vec![]);
+        assert_eq!(report.rgb_count, 1);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.pairs[0].0.name(), "210101_120010000.iiq");
+        // The files that weren't listed are left alone
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(nir_dir.join("unmatched/210101_120020100.iiq").exists());
+
+        let missing = nir_dir.join("210101_120030100.iiq");
+        let config = ProcessConfig::builder()
+            .nir_paths(vec![missing.clone()])
+            .force(true)
+            .build();
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::ListedFileNotFound { path }) if path == missing
+        ));
+    }
 
-        for file in nir_collection.iter() {
-            let dest = &nir_dir.join(file.original_parent_dir_name());
-            if dest.exists() {
-                filesystem::move_files(vec![file.path.clone()], dest, verbose)?;
-            } else {
-                eprintln!("Parent directory does not exist for file {}", file.name);
+    #[test]
+    fn test_process_images_flatten() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        for dir in [&rgb_dir, &nir_dir] {
+            fs::create_dir_all(dir.join("download1")).unwrap();
+            fs::create_dir_all(dir.join("download2")).unwrap();
+        }
+        fs::write(rgb_dir.join("download1/210101_120000000.iiq"), "first").unwrap();
+        fs::write(rgb_dir.join("download2/210101_120005000.iiq"), "content").unwrap();
+        // The same name as a file in the other download
+        fs::write(rgb_dir.join("download2/210101_120000000.iiq"), "second").unwrap();
+        fs::write(nir_dir.join("download1/210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("download2/210101_120005100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .flatten(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.unmatched_rgb_count, 1);
+
+        assert!(rgb_dir.join("210101_120005000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(nir_dir.join("210101_120005100.iiq").exists());
+        // Both files with the same name were kept. The one found first, by path, keeps its
+        // name, and the tie between them for the NIR file goes the same way whatever order the
+        // file system lists them in
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(
+            read(rgb_dir.join("unmatched/210101_120000000.iiq")),
+            "first"
+        );
+        assert_eq!(read(rgb_dir.join("210101_120000000-1.iiq")), "second");
+        assert_eq!(report.pairs[0].0.name(), "210101_120000000-1.iiq");
+        // The suffix isn't taken for a frame index
+        assert_eq!(
+            IIQFile::new(&rgb_dir.join("210101_120000000-1.iiq"))
+                .unwrap()
+                .frame_index(),
+            None
+        );
+        for dir in [&rgb_dir, &nir_dir] {
+            for download in ["download1", "download2"] {
+                assert_eq!(fs::read_dir(dir.join(download)).unwrap().count(), 0);
             }
         }
-        remove_dir_if_empty(&nir_dir.join("empty"))?;
-        remove_dir_if_empty(&nir_dir.join("unmatched"))?;
     }
 
-    Ok((rgb_iiq_files.len(), nir_iiq_files.len()))
-}
+    #[test]
+    fn test_process_images_flatten_filtered() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        for (dir, name) in [
+            (&rgb_dir, "210101_120000000.iiq"),
+            (&nir_dir, "210101_120000100.iiq"),
+            // Off hours
+            (&rgb_dir, "210101_230000000.iiq"),
+            // Out of range
+            (&nir_dir, "210102_120000000.iiq"),
+        ] {
+            fs::create_dir_all(dir.join("download")).unwrap();
+            fs::write(dir.join("download").join(name), "content").unwrap();
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
-    use tempfile::TempDir;
+        let parse = |s| NaiveDateTime::parse_from_str(s, "%y%m%d_%H%M%S%3f").unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .end(parse("210101_235959000"))
+            .time_of_day(
+                NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            )
+            .preserve_structure(true)
+            .flatten(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
 
-    use std::fs;
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        // Moved from where it was found, not from where flattening would have put it
+        assert!(rgb_dir
+            .join("offhours/download/210101_230000000.iiq")
+            .exists());
+        assert!(nir_dir.join("download/210102_120000000.iiq").exists());
+        assert!(!nir_dir.join("210102_120000000.iiq").exists());
+    }
 
     #[test]
-    fn test_iiq_file_new() {
+    fn test_process_images_output_dir() {
         let temp_dir = TempDir::new().unwrap();
-        let path = temp_dir.path().join("210101_120000000.iiq");
-        fs::write(&path, "content").unwrap();
+        let rgb_dir = temp_dir.path().join("source/rgb");
+        let nir_dir = temp_dir.path().join("source/nir");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+        fs::write(rgb_dir.join("210101_1200/210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_1200/210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_1200/210101_120005000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_1200/210101_120010000.iiq"), "").unwrap();
+        let source_files = |dir: &Path| {
+            let mut paths = filesystem::find_files(dir, "iiq", None, &[], true).unwrap();
+            paths.sort();
+            paths
+        };
+        let (rgb_before, nir_before) = (source_files(&rgb_dir), source_files(&nir_dir));
+
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .file_op(FileOp::Copy)
+            .output_dir(&output_dir)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        // The source tree is untouched
+        assert_eq!(source_files(&rgb_dir), rgb_before);
+        assert_eq!(source_files(&nir_dir), nir_before);
+        assert_eq!(fs::read_dir(&rgb_dir).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(&nir_dir).unwrap().count(), 1);
+
+        assert!(output_dir.join("rgb/210101_120000000.iiq").exists());
+        assert!(output_dir.join("nir/210101_120000100.iiq").exists());
+        assert!(output_dir
+            .join("nir/unmatched/210101_120005000.iiq")
+            .exists());
+        assert!(output_dir.join("rgb/empty/210101_120010000.iiq").exists());
 
-        let file = IIQFile::new(&path).unwrap();
-        assert_eq!(file.stem, "210101_120000000");
-        let date = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
-        let time = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
-        let dt = NaiveDateTime::new(date, time);
-        assert_eq!(file.datetime, dt);
-        assert_eq!(file.bytes, 7);
-        assert_eq!(file.path, path);
-        assert_eq!(file.name, "210101_120000000.iiq");
+        // The output of that run is found on the next one
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(result, Err(IxMatchError::AlreadyProcessed { .. })));
     }
 
     #[test]
-    fn test_make_iiq_collection() {
+    fn test_process_images_in_range() {
         let temp_dir = TempDir::new().unwrap();
-        let base_path = temp_dir.path();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
 
-        let files = vec![
-            base_path.join("210101_120000000.iiq"),
-            base_path.join("210101_120001000.iiq"),
-        ];
+        // Create test files
+        fs::write(rgb_dir.join("210101_110000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "").unwrap();
+        fs::write(rgb_dir.join("210101_130000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // Out of range and unmatched, but shouldn't be touched
+        fs::write(nir_dir.join("210101_140000000.iiq"), "").unwrap();
+
+        let parse = |s| NaiveDateTime::parse_from_str(s, "%y%m%d_%H%M%S%3f").unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .start(parse("210101_115959000"))
+            .end(parse("210101_125959000"))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 1);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.empty_rgb_count, 1);
+        assert_eq!(report.empty_nir_count, 0);
+
+        assert!(rgb_dir.join("210101_110000000.iiq").exists());
+        assert!(rgb_dir.join("210101_130000000.iiq").exists());
+        assert!(rgb_dir.join("empty/210101_120001000.iiq").exists());
+        assert!(nir_dir.join("210101_140000000.iiq").exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+        assert!(!nir_dir.join("empty").exists());
+    }
 
-        files.iter().for_each(|file| {
-            fs::write(file, "content").unwrap();
-        });
+    #[test]
+    fn test_process_images_with_no_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
 
-        let collection = IIQCollection::new(&files).unwrap();
-        assert_eq!(collection.len(), 2);
-        assert_eq!(collection.paths(), files);
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .keep_empty_files(true)
+            .build();
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(result, Err(IxMatchError::RgbAndNirDirsNotFound)));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "RGB and NIR directories do not exist"
+        );
     }
 
     #[test]
-    fn test_join_collections() {
-        let temp_dir_rgb = TempDir::new().unwrap();
-        let rgb_files = vec![
-            temp_dir_rgb.path().join("210101_120000000.iiq"),
-            temp_dir_rgb.path().join("210101_120001000.iiq"),
-        ];
-        for file in &rgb_files {
-            fs::write(file, "content").unwrap();
-        }
-        let rgb_collection = IIQCollection::new(&rgb_files).unwrap();
-
-        let temp_dir_nir = TempDir::new().unwrap();
-        let nir_files = vec![
-            temp_dir_nir.path().join("210101_120000100.iiq"),
-            temp_dir_nir.path().join("210101_120001100.iiq"),
-        ];
-        for file in &nir_files {
-            fs::write(file, "content").unwrap();
-        }
-        let nir_collection = IIQCollection::new(&nir_files).unwrap();
+    fn test_process_images_with_one_dir_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
 
-        let result = JoinedIIQCollection::new(&rgb_collection, &nir_collection).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        let result = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default());
+        assert!(matches!(result, Err(IxMatchError::RgbDirNotFound)));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "RGB directory does not exist"
+        );
 
-        assert_eq!(result.joined.len(), 2);
-        let mut joined = result.joined;
-        joined.sort();
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::remove_dir(&nir_dir).unwrap();
+        let result = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default());
+        assert!(matches!(result, Err(IxMatchError::NirDirNotFound)));
         assert_eq!(
-            joined,
-            vec![
-                (
-                    Some(&rgb_collection.files[0]),
-                    Some(&nir_collection.files[0]),
-                    Duration::from_millis(100)
-                ),
-                (
-                    Some(&rgb_collection.files[1]),
-                    Some(&nir_collection.files[1]),
-                    Duration::from_millis(100)
-                ),
-            ]
+            result.unwrap_err().to_string(),
+            "NIR directory does not exist"
         );
     }
 
     #[test]
-    fn test_collection() {
+    fn test_process_images_with_keep_empty() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
@@ -507,181 +5886,294 @@ mod tests {
 
         // Create test files
         fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_130000000.iiq"), "").unwrap();
         fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
-        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_120001100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_130000100.iiq"), "").unwrap();
 
-        let rgb_files = filesystem::find_files(&rgb_dir, "iiq").unwrap();
-        let nir_files = filesystem::find_files(&nir_dir, "iiq").unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .keep_empty_files(true)
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
 
-        let rgb_collection = IIQCollection::new(&rgb_files).unwrap();
-        let nir_collection = IIQCollection::new(&nir_files).unwrap();
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.empty_rgb_count, 1);
+        assert_eq!(report.empty_nir_count, 1);
 
-        assert_eq!(rgb_collection.len(), 2);
-        assert_eq!(nir_collection.len(), 2);
+        // Check if matched files are in their original locations
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        assert!(rgb_dir.join("210101_130000000.iiq").exists());
+        assert!(nir_dir.join("210101_130000100.iiq").exists());
+
+        // Check that no empty directories were created
+        assert!(!rgb_dir.join("empty").exists());
+        assert!(!nir_dir.join("empty").exists());
     }
 
     #[test]
-    fn test_process_images() {
+    fn test_process_images_min_size_bytes() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
         fs::create_dir_all(&rgb_dir).unwrap();
         fs::create_dir_all(&nir_dir).unwrap();
 
-        // Create test files
-        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
-        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_120001100.iiq"), "content").unwrap();
-
-        let threshold = Duration::from_millis(200);
-        let (rgb_count, nir_count, matched_count, empty_rgb_count, empty_nir_count) =
-            process_images(&rgb_dir, &nir_dir, threshold, false, false, false).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), vec![1; 2000]).unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), vec![1; 2000]).unwrap();
+        // A transfer that stopped after the first few bytes
+        fs::write(rgb_dir.join("210101_120001000.iiq"), vec![1; 10]).unwrap();
+        fs::write(nir_dir.join("210101_120001100.iiq"), vec![1; 2000]).unwrap();
+
+        // By default only 0 byte files are empty
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.empty_rgb_count, 0);
+        assert_eq!(report.matched_count, 2);
+
+        let config = ProcessConfig::builder().min_size_bytes(1000).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.empty_rgb_count, 1);
+        assert_eq!(report.empty_nir_count, 0);
+        assert_eq!(report.matched_count, 1);
+        assert!(rgb_dir.join("empty").join("210101_120001000.iiq").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120001100.iiq")
+            .exists());
+    }
 
-        assert_eq!(rgb_count, 2);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 2);
-        assert_eq!(empty_rgb_count, 0);
-        assert_eq!(empty_nir_count, 0);
+    #[test]
+    fn test_process_images_utc_offset() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
 
-        // Check if files are in their original locations
-        // (process_images doesn't move matched files in this case)
-        assert!(rgb_dir.join("210101_120000000.iiq").exists());
-        assert!(rgb_dir.join("210101_120001000.iiq").exists());
-        assert!(nir_dir.join("210101_120000100.iiq").exists());
-        assert!(nir_dir.join("210101_120001100.iiq").exists());
+        // The RGB camera was set to UTC+3, the NIR camera to UTC
+        fs::write(rgb_dir.join("210101_150000000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_150001000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120001100.iiq"), "content").unwrap();
 
-        // Unmatched directories should not be created in this case
-        assert!(!rgb_dir.join("unmatched").exists());
-        assert!(!nir_dir.join("unmatched").exists());
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 0);
+
+        let config = ProcessConfig::builder()
+            .dry_run(true)
+            .rgb_utc_offset(FixedOffset::east_opt(3 * 3600).unwrap())
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.deltas, vec![Duration::from_millis(100); 2]);
     }
 
     #[test]
-    fn test_process_images_dry_run() {
+    fn test_process_images_unmatched_reasons() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
         fs::create_dir_all(&rgb_dir).unwrap();
         fs::create_dir_all(&nir_dir).unwrap();
 
-        // Create test files
         fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
-        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
         fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap(); // This one won't match
-
-        let threshold = Duration::from_millis(200);
-        let (rgb_count, nir_count, matched_count, empty_rgb_count, empty_nir_count) =
-            process_images(&rgb_dir, &nir_dir, threshold, true, true, false).unwrap();
-
-        assert_eq!(rgb_count, 2);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 1);
-        assert_eq!(empty_rgb_count, 0);
-        assert_eq!(empty_nir_count, 0);
+        // Just too far apart
+        fs::write(rgb_dir.join("210101_120010000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120010600.iiq"), "content").unwrap();
+        // Frames the NIR camera missed
+        fs::write(rgb_dir.join("210101_120020000.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120020100.iiq"), "content").unwrap();
+
+        let config = ProcessConfig::builder().dry_run(true).build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.unmatched_rgb_count, 3);
+        assert_eq!(report.unmatched_nir_count, 1);
+
+        assert_eq!(report.over_threshold.len(), 1);
+        let (rgb, nir, dt) = &report.over_threshold[0];
+        assert_eq!(rgb.name(), "210101_120010000.iiq");
+        assert_eq!(nir.name(), "210101_120010600.iiq");
+        assert_eq!(*dt, Duration::from_millis(600));
+
+        let no_candidate: Vec<&str> = report.no_candidate.iter().map(|f| f.name()).collect();
+        assert_eq!(
+            no_candidate,
+            vec!["210101_120020000.iiq", "210101_120020100.iiq"]
+        );
+    }
 
-        // Check if all files are in their original locations (dry run)
+    #[test]
+    fn test_process_images_incremental() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+        let config = ProcessConfig::builder()
+            .manifest(temp_dir.path().join("manifest.txt"))
+            .build();
+
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+
+        // A NIR file closer to the first RGB file arrives, along with a new pair
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120000050.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120001000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120001100.iiq"),
+            "content",
+        )
+        .unwrap();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        // Only the new files are considered, so the first pair stays as it was
+        assert_eq!(report.rgb_count, 1);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.pairs[0].0.name(), "210101_120001000.iiq");
         assert!(rgb_dir.join("210101_120000000.iiq").exists());
-        assert!(rgb_dir.join("210101_120001000.iiq").exists());
         assert!(nir_dir.join("210101_120000100.iiq").exists());
-        assert!(nir_dir.join("210101_120005000.iiq").exists());
-        assert!(!rgb_dir.join("unmatched").exists());
-        assert!(!nir_dir.join("unmatched").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120000050.iiq")
+            .exists());
     }
 
     #[test]
-    fn test_process_images_with_unmatched() {
+    fn test_process_images_threads() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
         fs::create_dir_all(&rgb_dir).unwrap();
         fs::create_dir_all(&nir_dir).unwrap();
 
-        // Create test files
-        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
-        // These won't match
-        fs::write(rgb_dir.join("210101_120001000.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
-
-        let threshold = Duration::from_millis(200);
-        let (rgb_count, nir_count, matched_count, empty_rgb_count, empty_nir_count) =
-            process_images(&rgb_dir, &nir_dir, threshold, true, false, false).unwrap();
-
-        assert_eq!(rgb_count, 2);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 1);
-        assert_eq!(empty_rgb_count, 0);
-        assert_eq!(empty_nir_count, 0);
+        for second in 0..20 {
+            fs::write(
+                rgb_dir.join(format!("210101_1200{second:02}000.iiq")),
+                "content",
+            )
+            .unwrap();
+            // Every fifth NIR frame is missing
+            if second % 5 != 4 {
+                fs::write(
+                    nir_dir.join(format!("210101_1200{second:02}100.iiq")),
+                    "content",
+                )
+                .unwrap();
+            }
+        }
 
-        // Check if matched files are in their original locations
-        assert!(rgb_dir.join("210101_120000000.iiq").exists());
-        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        let run = |threads| {
+            let config = ProcessConfig::builder()
+                .dry_run(true)
+                .threads(NonZeroUsize::new(threads).unwrap())
+                .build();
+            process_images(&rgb_dir, &nir_dir, &config).unwrap()
+        };
+        let sequential = run(1);
+        assert_eq!(sequential.matched_count, 16);
+        assert_eq!(sequential.unmatched_rgb_count, 4);
+        for (i, (rgb, nir, _)) in sequential.pairs.iter().enumerate() {
+            let second = i + i / 4;
+            assert_eq!(rgb.name(), format!("210101_1200{second:02}000.iiq"));
+            assert_eq!(nir.name(), format!("210101_1200{second:02}100.iiq"));
+        }
 
-        // Check if unmatched files are moved to the unmatched directory
-        assert!(rgb_dir
-            .join("unmatched")
-            .join("210101_120001000.iiq")
-            .exists());
-        assert!(!rgb_dir.join("210101_120001000.iiq").exists());
-        assert!(nir_dir
-            .join("unmatched")
-            .join("210101_120005000.iiq")
-            .exists());
-        assert!(!nir_dir.join("210101_120005000.iiq").exists());
+        let parallel = run(3);
+        assert_eq!(parallel.pairs, sequential.pairs);
+        assert_eq!(parallel.no_candidate, sequential.no_candidate);
     }
 
     #[test]
-    fn test_process_images_with_uneven_numbers() {
+    fn test_process_images_timings() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
         fs::create_dir_all(&rgb_dir).unwrap();
         fs::create_dir_all(&nir_dir).unwrap();
 
-        // Create test files
         fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
         fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
-        // These won't match
-        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
-
-        let threshold = Duration::from_millis(200);
-        let (rgb_count, nir_count, matched_count, empty_rgb_count, empty_nir_count) =
-            process_images(&rgb_dir, &nir_dir, threshold, true, false, false).unwrap();
-
-        assert_eq!(rgb_count, 1);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 1);
-        assert_eq!(empty_rgb_count, 0);
-        assert_eq!(empty_nir_count, 0);
 
-        // Check if matched files are in their original locations
-        assert!(rgb_dir.join("210101_120000000.iiq").exists());
-        assert!(nir_dir.join("210101_120000100.iiq").exists());
-
-        // Check if unmatched files are moved to the unmatched directory
-        assert!(!rgb_dir.join("unmatched").exists());
-        assert!(nir_dir
-            .join("unmatched")
-            .join("210101_120005000.iiq")
-            .exists());
-        assert!(!nir_dir.join("210101_120005000.iiq").exists());
+        let report = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        let timings = report.timings;
+        assert!(timings.scanning > Duration::ZERO);
+        assert!(timings.reading > Duration::ZERO);
+        assert_eq!(
+            timings.total(),
+            timings.scanning + timings.reading + timings.matching + timings.moving
+        );
     }
 
     #[test]
-    fn test_process_images_with_no_dirs() {
+    fn test_process_images_rolls_back_on_error() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
 
-        let threshold = Duration::from_millis(200);
-        let result = process_images(&rgb_dir, &nir_dir, threshold, true, false, false);
-        assert!(result.is_err());
+        let rgb_files = ["210101_120000000.iiq", "210101_130000000.iiq"];
+        let nir_files = ["210101_120000100.iiq", "210101_140000000.iiq"];
+        for name in rgb_files {
+            fs::write(rgb_dir.join("210101_1200").join(name), "content").unwrap();
+        }
+        for name in nir_files {
+            fs::write(nir_dir.join("210101_1200").join(name), "content").unwrap();
+        }
+        // The unmatched NIR file is moved last, and clashes with a file left from another run
+        fs::create_dir_all(nir_dir.join("unmatched")).unwrap();
+        fs::write(nir_dir.join("unmatched").join(nir_files[1]), "other").unwrap();
+
+        let config = ProcessConfig::builder().force(true).build();
+        let result = process_images(&rgb_dir, &nir_dir, &config);
+        assert!(matches!(
+            result,
+            Err(IxMatchError::DestinationExists { .. })
+        ));
+
+        // Everything moved before the failure is back where it was
+        for name in rgb_files {
+            assert!(rgb_dir.join("210101_1200").join(name).exists());
+        }
+        for name in nir_files {
+            assert!(nir_dir.join("210101_1200").join(name).exists());
+        }
+        assert!(!rgb_dir.join(rgb_files[0]).exists());
+        assert!(!nir_dir.join(nir_files[0]).exists());
+        assert!(!rgb_dir.join("unmatched").exists());
+        assert_eq!(
+            fs::read_to_string(nir_dir.join("unmatched").join(nir_files[1])).unwrap(),
+            "other"
+        );
     }
 
     #[test]
-    fn test_process_images_with_keep_empty() {
+    fn test_process_images_with_no_keep_empty() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
@@ -694,29 +6186,78 @@ mod tests {
         fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
         fs::write(nir_dir.join("210101_130000100.iiq"), "").unwrap();
 
-        let threshold = Duration::from_millis(200);
-        let (rgb_count, nir_count, matched_count, empty_rgb_count, empty_nir_count) =
-            process_images(&rgb_dir, &nir_dir, threshold, true, false, false).unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
 
-        assert_eq!(rgb_count, 2);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 2);
-        assert_eq!(empty_rgb_count, 1);
-        assert_eq!(empty_nir_count, 1);
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.empty_rgb_count, 1);
+        assert_eq!(report.empty_nir_count, 1);
 
         // Check if matched files are in their original locations
         assert!(rgb_dir.join("210101_120000000.iiq").exists());
         assert!(nir_dir.join("210101_120000100.iiq").exists());
-        assert!(rgb_dir.join("210101_130000000.iiq").exists());
-        assert!(nir_dir.join("210101_130000100.iiq").exists());
 
-        // Check that no empty directories were created
-        assert!(!rgb_dir.join("empty").exists());
-        assert!(!nir_dir.join("empty").exists());
+        // Check if empty files are moved to the empty directory
+        assert!(rgb_dir.join("empty").join("210101_130000000.iiq").exists());
+        assert!(!rgb_dir.join("210101_130000000.iiq").exists());
+        assert!(nir_dir.join("empty").join("210101_130000100.iiq").exists());
+        assert!(!nir_dir.join("210101_130000100.iiq").exists());
     }
 
+    #[cfg(feature = "tracing")]
     #[test]
-    fn test_process_images_with_no_keep_empty() {
+    fn test_process_images_tracing_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Records the names of the spans opened
+        struct SpanRecorder(Arc<Mutex<Vec<&'static str>>>);
+
+        impl Subscriber for SpanRecorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name());
+                Id::from_u64(names.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let report = tracing::subscriber::with_default(SpanRecorder(names.clone()), || {
+            process_images(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap()
+        });
+        assert_eq!(report.matched_count, 1);
+
+        let names = names.lock().unwrap();
+        assert_eq!(names[0], "process_images");
+        for name in ["scanning", "matching", "moving"] {
+            assert!(names.contains(&name), "no {name} span in {names:?}");
+        }
+    }
+
+    #[test]
+    fn test_process_images_with_progress() {
         let temp_dir = TempDir::new().unwrap();
         let rgb_dir = temp_dir.path().join("rgb");
         let nir_dir = temp_dir.path().join("nir");
@@ -727,27 +6268,83 @@ mod tests {
         fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
         fs::write(rgb_dir.join("210101_130000000.iiq"), "").unwrap();
         fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
-        fs::write(nir_dir.join("210101_130000100.iiq"), "").unwrap();
+        // This one won't match
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
 
-        let threshold = Duration::from_millis(200);
-        let (rgb_count, nir_count, matched_count, empty_rgb_count, empty_nir_count) =
-            process_images(&rgb_dir, &nir_dir, threshold, false, false, false).unwrap();
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .build();
+        let mut updates = Vec::new();
+        process_images_with_progress(&rgb_dir, &nir_dir, &config, |p| updates.push(p)).unwrap();
+
+        let last_of = |phase: Phase| {
+            updates
+                .iter()
+                .rev()
+                .find(|p| p.phase == phase)
+                .map(|p| (p.current, p.total))
+        };
+        assert_eq!(last_of(Phase::Scanning), Some((4, 4)));
+        assert_eq!(last_of(Phase::MovingEmpty), Some((1, 1)));
+        assert_eq!(last_of(Phase::Matching), Some((3, 3)));
+        assert_eq!(last_of(Phase::MovingMatched), Some((2, 2)));
+        assert_eq!(last_of(Phase::MovingUnmatched), Some((1, 1)));
+
+        // Phases are reported in order
+        let mut phases: Vec<Phase> = updates.iter().map(|p| p.phase).collect();
+        phases.dedup();
+        assert_eq!(
+            phases,
+            vec![
+                Phase::Scanning,
+                Phase::MovingEmpty,
+                Phase::MovingCorrupt,
+                Phase::MovingOffHours,
+                Phase::Matching,
+                Phase::MovingMatched,
+                Phase::MovingUnmatched
+            ]
+        );
+    }
 
-        assert_eq!(rgb_count, 2);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 1);
-        assert_eq!(empty_rgb_count, 1);
-        assert_eq!(empty_nir_count, 1);
+    #[test]
+    fn test_process_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+
+        for (session, nir_file) in [
+            ("240101", "240101_120000100.iiq"),
+            ("240102", "240102_120005000.iiq"),
+        ] {
+            let rgb_dir = root.join(session).join("CAMERA_RGB");
+            let nir_dir = root.join(session).join("CAMERA_NIR");
+            fs::create_dir_all(&rgb_dir).unwrap();
+            fs::create_dir_all(&nir_dir).unwrap();
+            fs::write(rgb_dir.join(format!("{session}_120000000.iiq")), "content").unwrap();
+            fs::write(nir_dir.join(nir_file), "content").unwrap();
+        }
 
-        // Check if matched files are in their original locations
-        assert!(rgb_dir.join("210101_120000000.iiq").exists());
-        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        let config = ProcessConfig::builder()
+            .match_threshold(Duration::from_millis(200))
+            .build();
+        let reports = process_sessions(root, "CAMERA_RGB", "CAMERA_NIR", true, &config).unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].0, root.join("240101"));
+        assert_eq!(reports[0].1.matched_count, 1);
+        assert_eq!(reports[1].0, root.join("240102"));
+        assert_eq!(reports[1].1.matched_count, 0);
+        assert!(root
+            .join("240102/CAMERA_NIR/unmatched/240102_120005000.iiq")
+            .exists());
 
-        // Check if empty files are moved to the empty directory
-        assert!(rgb_dir.join("empty").join("210101_130000000.iiq").exists());
-        assert!(!rgb_dir.join("210101_130000000.iiq").exists());
-        assert!(nir_dir.join("empty").join("210101_130000100.iiq").exists());
-        assert!(!nir_dir.join("210101_130000100.iiq").exists());
+        let mut total = MatchReport::default();
+        for (_, report) in reports {
+            total.merge(report);
+        }
+        assert_eq!(total.rgb_count, 2);
+        assert_eq!(total.nir_count, 2);
+        assert_eq!(total.matched_count, 1);
     }
 
     #[test]
@@ -789,13 +6386,152 @@ mod tests {
         assert_eq!(closest_file.path, files[2]);
     }
 
+    #[test]
+    fn test_get_closest_file_with_tie_break() {
+        let collection: IIQCollection = ["210101_120000000.iiq", "210101_120001000.iiq"]
+            .iter()
+            .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+            .collect::<Vec<_>>()
+            .into();
+        // Exactly halfway between the two files
+        let target = NaiveDateTime::parse_from_str("210101_120000500", "%y%m%d_%H%M%S%3f").unwrap();
+
+        let earlier = collection
+            .get_closest_file_with_tie_break(&target, TieBreak::PreferEarlier)
+            .unwrap();
+        assert_eq!(earlier.stem(), "210101_120000000");
+        let later = collection
+            .get_closest_file_with_tie_break(&target, TieBreak::PreferLater)
+            .unwrap();
+        assert_eq!(later.stem(), "210101_120001000");
+        assert_eq!(
+            collection.get_closest_file_by_datetime(&target).unwrap(),
+            earlier
+        );
+    }
+
+    #[test]
+    fn test_join_collections_tie_break() {
+        let rgb: IIQCollection = ["210101_120000500.iiq"]
+            .iter()
+            .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+            .collect::<Vec<_>>()
+            .into();
+        let nir: IIQCollection = ["210101_120000000.iiq", "210101_120001000.iiq"]
+            .iter()
+            .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+            .collect::<Vec<_>>()
+            .into();
+        let threshold = Duration::from_millis(500);
+
+        let joined = JoinedIIQCollection::new(&rgb, &nir, TieBreak::PreferEarlier);
+        assert_eq!(
+            joined.get_matched_nir(&threshold)[0].stem(),
+            "210101_120000000"
+        );
+        let joined = JoinedIIQCollection::new(&rgb, &nir, TieBreak::PreferLater);
+        assert_eq!(
+            joined.get_matched_nir(&threshold)[0].stem(),
+            "210101_120001000"
+        );
+    }
+
     #[test]
     fn test_get_closest_file_by_datetime_empty_collection() {
         let collection = IIQCollection { files: vec![] };
         let target_datetime =
             NaiveDateTime::parse_from_str("210101_120000500", "%y%m%d_%H%M%S%3f").unwrap();
         let result = collection.get_closest_file_by_datetime(&target_datetime);
-        assert!(result.is_err());
+        assert!(matches!(result, Err(IxMatchError::EmptyCollection)));
+    }
+
+    #[test]
+    fn test_closest_to_events() {
+        let collection: IIQCollection = [
+            "210101_120000000.iiq",
+            "210101_120002000.iiq",
+            "210101_120004000.iiq",
+        ]
+        .iter()
+        .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+        .collect::<Vec<_>>()
+        .into();
+        let parse = |s| NaiveDateTime::parse_from_str(s, "%y%m%d_%H%M%S%3f").unwrap();
+        let events = [
+            parse("210101_120002300"),
+            parse("210101_115959000"),
+            parse("210101_120003800"),
+        ];
+
+        let closest = collection.closest_to_events(&events).unwrap();
+        let found: Vec<(NaiveDateTime, &str, i64)> = closest
+            .iter()
+            .map(|(event, file, delta)| (*event, file.stem(), delta.num_milliseconds()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (events[0], "210101_120002000", -300),
+                (events[1], "210101_120000000", 1000),
+                (events[2], "210101_120004000", 200),
+            ]
+        );
+
+        assert!(collection.closest_to_events(&[]).unwrap().is_empty());
+        assert!(matches!(
+            IIQCollection::from(vec![]).closest_to_events(&events),
+            Err(IxMatchError::EmptyCollection)
+        ));
+    }
+
+    #[test]
+    fn test_k_nearest() {
+        let collection: IIQCollection = [
+            "210101_120000000.iiq",
+            "210101_120001000.iiq",
+            "210101_120002000.iiq",
+            "210101_120004000.iiq",
+        ]
+        .iter()
+        .map(|name| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap())
+        .collect::<Vec<_>>()
+        .into();
+        let names = |files: Vec<&IIQFile>| -> Vec<String> {
+            files.iter().map(|f| f.stem().to_string()).collect()
+        };
+        let target = NaiveDateTime::parse_from_str("210101_120002200", "%y%m%d_%H%M%S%3f").unwrap();
+
+        assert_eq!(
+            names(collection.k_nearest(&target, 3)),
+            ["210101_120002000", "210101_120001000", "210101_120004000"]
+        );
+        assert!(collection.k_nearest(&target, 0).is_empty());
+
+        // Asking for more files than there are returns all of them
+        assert_eq!(
+            names(collection.k_nearest(&target, 10)),
+            [
+                "210101_120002000",
+                "210101_120001000",
+                "210101_120004000",
+                "210101_120000000"
+            ]
+        );
+
+        // Equally close files are returned earlier first
+        let target = NaiveDateTime::parse_from_str("210101_120003000", "%y%m%d_%H%M%S%3f").unwrap();
+        assert_eq!(
+            names(collection.k_nearest(&target, 2)),
+            ["210101_120002000", "210101_120004000"]
+        );
+        let target = NaiveDateTime::parse_from_str("210101_120001000", "%y%m%d_%H%M%S%3f").unwrap();
+        assert_eq!(
+            names(collection.k_nearest(&target, 3)),
+            ["210101_120001000", "210101_120000000", "210101_120002000"]
+        );
+
+        let empty = IIQCollection { files: vec![] };
+        assert!(empty.k_nearest(&target, 3).is_empty());
     }
 
     #[test]
@@ -821,14 +6557,15 @@ mod tests {
         fs::write(nir_unmatched_dir.join("210101_140000100.iiq"), "").unwrap();
 
         // Create original directories
-        fs::create_dir_all(&rgb_dir.join("210101_1201")).unwrap();
-        fs::create_dir_all(&nir_dir.join("210101_1201")).unwrap();
-        fs::create_dir_all(&rgb_dir.join("210101_1300")).unwrap();
-        fs::create_dir_all(&nir_dir.join("210101_1300")).unwrap();
-        fs::create_dir_all(&rgb_dir.join("210101_1400")).unwrap();
-        fs::create_dir_all(&nir_dir.join("210101_1400")).unwrap();
+        fs::create_dir_all(rgb_dir.join("210101_1201")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1201")).unwrap();
+        fs::create_dir_all(rgb_dir.join("210101_1300")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1300")).unwrap();
+        fs::create_dir_all(rgb_dir.join("210101_1400")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1400")).unwrap();
 
-        let (rgb_count, nir_count) = revert_changes(&rgb_dir, &nir_dir, false, false).unwrap();
+        let (rgb_count, nir_count) =
+            revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
 
         assert_eq!(rgb_count, 3);
         assert_eq!(nir_count, 3);
@@ -865,15 +6602,17 @@ mod tests {
 
         // Create original directories
         // --Removed fs::create_dir_all(&rgb_dir.join("210101_1201")).unwrap();
-        fs::create_dir_all(&nir_dir.join("210101_1201")).unwrap();
-        fs::create_dir_all(&rgb_dir.join("210101_1300")).unwrap();
-        fs::create_dir_all(&nir_dir.join("210101_1300")).unwrap();
-        fs::create_dir_all(&rgb_dir.join("210101_1400")).unwrap();
-        fs::create_dir_all(&nir_dir.join("210101_1400")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1201")).unwrap();
+        fs::create_dir_all(rgb_dir.join("210101_1300")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1300")).unwrap();
+        fs::create_dir_all(rgb_dir.join("210101_1400")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1400")).unwrap();
 
-        let (rgb_count, nir_count) = revert_changes(&rgb_dir, &nir_dir, false, false).unwrap();
+        let (rgb_count, nir_count) =
+            revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
 
-        assert_eq!(rgb_count, 3);
+        // The RGB file without a directory to go back to stays where it is
+        assert_eq!(rgb_count, 2);
         assert_eq!(nir_count, 3);
 
         assert!(rgb_dir.join("210101_120100000.iiq").exists());
@@ -883,4 +6622,183 @@ mod tests {
         assert!(rgb_dir.join("210101_1400/210101_140000000.iiq").exists());
         assert!(nir_dir.join("210101_1400/210101_140000100.iiq").exists());
     }
+
+    #[test]
+    fn test_revert_changes_restores_empty_files_to_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(&rgb_dir).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        fs::write(rgb_dir.join("210101_120001000.iiq"), "").unwrap();
+        fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let report = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        assert_eq!(report.empty_rgb_count, 1);
+        assert!(rgb_dir.join("empty").join("210101_120001000.iiq").exists());
+        assert!(nir_dir
+            .join("unmatched")
+            .join("210101_120005000.iiq")
+            .exists());
+
+        revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        // The files were found in the band directories, so that's where they go back to
+        assert!(rgb_dir.join("210101_120001000.iiq").exists());
+        assert!(nir_dir.join("210101_120005000.iiq").exists());
+        assert!(rgb_dir.join("210101_120000000.iiq").exists());
+        assert!(!rgb_dir.join("empty").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_revert_changes_pristine_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+
+        assert_eq!(
+            revert_changes(
+                &rgb_dir,
+                &nir_dir,
+                &ProcessConfig::builder().dry_run(true).build()
+            )
+            .unwrap(),
+            (0, 0)
+        );
+        assert_eq!(
+            revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap(),
+            (0, 0)
+        );
+        assert!(rgb_dir
+            .join("210101_1200")
+            .join("210101_120000000.iiq")
+            .exists());
+        assert!(nir_dir.join("210101_120000100.iiq").exists());
+        for name in output_subdirs(&ProcessConfig::default()) {
+            assert!(!rgb_dir.join(name).exists());
+            assert!(!nir_dir.join(name).exists());
+        }
+        assert!(!nir_dir.join("210101_1200").exists());
+    }
+
+    #[test]
+    fn test_revert_changes_datetime_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        for name in [
+            "2021-01-01_12-00-00_0001.iiq",
+            "2021-01-01_12-00-30_0002.iiq",
+        ] {
+            fs::write(nir_dir.join("210101_1200").join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig::builder()
+            .nir_datetime_format("%Y-%m-%d_%H-%M-%S")
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.matched_count, 1);
+        assert!(nir_dir
+            .join("unmatched")
+            .join("2021-01-01_12-00-30_0002.iiq")
+            .exists());
+
+        // The dated directories are named after the capture time, not the start of the name
+        assert_eq!(revert_changes(&rgb_dir, &nir_dir, &config).unwrap(), (1, 2));
+        for name in [
+            "2021-01-01_12-00-00_0001.iiq",
+            "2021-01-01_12-00-30_0002.iiq",
+        ] {
+            assert!(nir_dir.join("210101_1200").join(name).exists());
+        }
+        assert!(rgb_dir
+            .join("210101_1200")
+            .join("210101_120000000.iiq")
+            .exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_revert_changes_datetime_regex() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+        let rgb_file = rgb_dir
+            .join("210101_1200")
+            .join("SITE12_210101_120000000.iiq");
+        let nir_file = nir_dir
+            .join("210101_1200")
+            .join("SITE12_210101_120005000.iiq");
+        fs::write(&rgb_file, "content").unwrap();
+        fs::write(&nir_file, "content").unwrap();
+
+        let config = ProcessConfig::builder()
+            .datetime_regex(r"_(\d{6}_\d{9})$")
+            .build();
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(report.unmatched_rgb_count, 1);
+        assert!(!rgb_file.exists());
+
+        assert_eq!(revert_changes(&rgb_dir, &nir_dir, &config).unwrap(), (1, 1));
+        assert!(rgb_file.exists());
+        assert!(nir_file.exists());
+    }
+
+    #[test]
+    fn test_revert_changes_twice() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120005000.iiq"),
+            "content",
+        )
+        .unwrap();
+        process_images(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+
+        assert_eq!(
+            revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap(),
+            (1, 2)
+        );
+        assert_eq!(
+            revert_changes(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap(),
+            (0, 0)
+        );
+        assert!(nir_dir
+            .join("210101_1200")
+            .join("210101_120005000.iiq")
+            .exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
 }
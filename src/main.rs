@@ -1,16 +1,145 @@
 #![cfg(feature = "cli")]
 
-use std::path::PathBuf;
-use std::time::Duration;
+use std::io::{self, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, Context, Result};
+use chrono::{FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
+use regex::Regex;
+use serde::Deserialize;
 
-use ix_match::{find_dir_by_pattern, process_images, revert_changes};
+use ix_match::{
+    band_files, find_band_dirs, find_dir_by_pattern_with_policy, find_session_dirs, process_images,
+    process_images_with_progress, process_sessions, revert_changes, sweep_thresholds, FileOp,
+    FileStatus, IIQFile, IxMatchError, MatchReport, MatchStrategy, MovePlan, MultipleDirPolicy,
+    Phase, ProcessConfig, TieBreak, Timings, Verbosity,
+};
+#[cfg(feature = "watch")]
+use ix_match::{watch_images, WatchConfig};
 
-fn parse_duration_millis(arg: &str) -> Result<Duration> {
-    let millis = arg.parse::<u64>()?;
-    Ok(Duration::from_millis(millis))
+/// Parse a duration like `500ms`, `1s` or `1.5s`. Bare integers are taken as milliseconds.
+fn parse_duration(arg: &str) -> Result<Duration> {
+    let arg = arg.trim();
+    if let Ok(millis) = arg.parse::<u64>() {
+        return Ok(Duration::from_millis(millis));
+    }
+
+    let (value, secs_per_unit) = if let Some(value) = arg.strip_suffix("ms") {
+        (value, 0.001)
+    } else if let Some(value) = arg.strip_suffix('s') {
+        (value, 1.0)
+    } else {
+        bail!("Invalid duration '{arg}', expected e.g. 500, 500ms or 1.5s");
+    };
+
+    let value = value
+        .trim()
+        .parse::<f64>()
+        .with_context(|| format!("Invalid duration '{arg}'"))?;
+    Duration::try_from_secs_f64(value * secs_per_unit)
+        .with_context(|| format!("Invalid duration '{arg}'"))
+}
+
+/// Parse a datetime in the same format as IIQ file names, e.g. `240101_120000000`.
+fn parse_stem_datetime(arg: &str) -> Result<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(arg, "%y%m%d_%H%M%S%3f")
+        .with_context(|| format!("Invalid datetime '{arg}', expected e.g. 240101_120000000"))
+}
+
+/// Parse a time to look for files modified since, either how long ago, e.g. `30m`, `2h` or
+/// `1d`, or a local time like `2024-01-01T12:00:00`, `2024-01-01` or `240101_120000000`.
+fn parse_since(arg: &str) -> Result<SystemTime> {
+    let arg = arg.trim();
+    let datetime = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%y%m%d_%H%M%S%3f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(arg, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(arg, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        });
+    if let Some(datetime) = datetime {
+        return Local
+            .from_local_datetime(&datetime)
+            .earliest()
+            .map(SystemTime::from)
+            .with_context(|| format!("'{arg}' is not a valid local time"));
+    }
+
+    let (value, secs_per_unit) = if let Some(value) = arg.strip_suffix('d') {
+        (value, 86_400)
+    } else if let Some(value) = arg.strip_suffix('h') {
+        (value, 3_600)
+    } else if let Some(value) = arg.strip_suffix('m') {
+        (value, 60)
+    } else {
+        (arg, 0)
+    };
+    let ago = if secs_per_unit == 0 {
+        parse_duration(arg).ok()
+    } else {
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(|value| Duration::try_from_secs_f64(value * secs_per_unit as f64).ok())
+    };
+    ago.and_then(|ago| SystemTime::now().checked_sub(ago))
+        .with_context(|| {
+            format!("Invalid time '{arg}', expected e.g. 2h, 30m or 2024-01-01T12:00:00")
+        })
+}
+
+/// Parse a time of day like `08:00` or `18:30:00`.
+fn parse_time_of_day(arg: &str) -> Result<NaiveTime> {
+    let arg = arg.trim();
+    NaiveTime::parse_from_str(arg, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(arg, "%H:%M"))
+        .with_context(|| format!("Invalid time of day '{arg}', expected e.g. 08:00"))
+}
+
+/// The paths in a file list, one per line, skipping blank lines and `#` comments.
+fn parse_file_list(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Read a file list, see [`parse_file_list`], from `path`, or from stdin if it is `-`.
+fn read_file_list(path: &Path) -> Result<Vec<PathBuf>> {
+    let content = if path == Path::new("-") {
+        io::read_to_string(io::stdin()).context("Failed to read the file list from stdin")?
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file list {}", path.display()))?
+    };
+    Ok(parse_file_list(&content))
+}
+
+/// Parse a regex, checking it before anything is read.
+fn parse_regex(arg: &str) -> Result<Regex> {
+    Regex::new(arg).with_context(|| format!("Invalid regex '{arg}'"))
+}
+
+/// Parse a UTC offset in minutes, e.g. `-480` for a clock set to UTC-8.
+fn parse_utc_offset(arg: &str) -> Result<FixedOffset> {
+    let minutes = arg
+        .trim()
+        .parse::<i32>()
+        .with_context(|| format!("Invalid UTC offset '{arg}', expected minutes, e.g. -480"))?;
+    minutes
+        .checked_mul(60)
+        .and_then(FixedOffset::east_opt)
+        .with_context(|| format!("UTC offset '{arg}' is out of range"))
 }
 
 fn parse_canonical_path(arg: &str) -> Result<PathBuf> {
@@ -18,6 +147,65 @@ fn parse_canonical_path(arg: &str) -> Result<PathBuf> {
     Ok(path)
 }
 
+/// Parse the path of a directory that has to exist, made absolute.
+fn parse_existing_dir(arg: &str) -> Result<PathBuf> {
+    let path = std::fs::canonicalize(arg).with_context(|| format!("'{arg}' not found"))?;
+    if !path.is_dir() {
+        bail!("'{arg}' is not a directory");
+    }
+    Ok(path)
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum MatchBy {
+    /// Pair files closest in time, within the threshold
+    Time,
+    /// Pair the n-th RGB file with the n-th NIR file
+    Order,
+    /// Pair files with the same frame index at the end of the name, or by time without one
+    Index,
+    /// Pair each RGB file with every NIR file within the threshold closest to it
+    ManyToOne,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Prefer {
+    /// Pair with the file captured first
+    Earlier,
+    /// Pair with the file captured last
+    Later,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OnMultiple {
+    /// Stop with an error
+    Fail,
+    /// Use the most recently modified directory
+    Newest,
+    /// Use the directory with the most files
+    MostFiles,
+}
+
+#[cfg(feature = "exif")]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    /// Counts and offsets as text
+    Text,
+    /// A GeoJSON FeatureCollection of the matched pairs with a GPS position in their headers
+    Geojson,
+}
+
+/// How the results are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    Text,
+    Summary,
+    UnmatchedOnly,
+    Pairs,
+    #[cfg(feature = "exif")]
+    GeoJson,
+}
+
 /// Match RGB and NIR IIQ files and move unmatched images to a new subdirectory.
 /// Helps to sort images from an aerial survey using PhaseOne cameras as a preprocessing step for
 /// converting the files with IX-Capture.
@@ -28,6 +216,10 @@ struct Args {
     #[arg(default_value = ".", value_parser = parse_canonical_path)]
     iiq_dir: PathBuf,
 
+    /// Read default options from this file instead of IIQ_DIR/ix-match.toml
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
     /// Dry run (do not move files)
     #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
     dry_run: bool,
@@ -37,10 +229,45 @@ struct Args {
     #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
     revert: bool,
 
+    /// Process the directories even if they contain 'unmatched' or 'empty' folders from a
+    /// previous run
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    force: bool,
+
+    /// Copy files instead of moving them, leaving the originals in place
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    copy: bool,
+
+    /// With --copy, check each copy against the original with a checksum, failing if it doesn't
+    /// match
+    #[cfg(feature = "verify")]
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false", requires = "copy")]
+    verify: bool,
+
     /// Keep empty files (do not filter out files with 0 bytes)
     #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
     keep_empty: bool,
 
+    /// Leave unmatched files where they are instead of moving them to unmatched/, still
+    /// reporting them
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    keep_unmatched_in_place: bool,
+
+    /// After moving, check every file is where it was moved to and none went missing, failing
+    /// the run if not
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    check_locations: bool,
+
+    /// Write a <stem>.match.json next to each matched file, recording its partner and the
+    /// offset between them
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    write_sidecar: bool,
+
+    /// Treat files smaller than this many bytes as empty too, e.g. transfers that were cut off
+    /// after the header
+    #[arg(long, default_value = "0")]
+    min_size_bytes: u64,
+
     /// Pattern for finding directory containing RGB files
     #[arg(long, default_value = "CAMERA_RGB")]
     rgb_pattern: String,
@@ -49,62 +276,1102 @@ struct Args {
     #[arg(long, default_value = "CAMERA_NIR")]
     nir_pattern: String,
 
-    /// Threshold for matching images in milliseconds
-    #[arg(short, long, default_value = "500", value_parser = parse_duration_millis)]
+    /// Directory containing the RGB files, used as is instead of looking for one matching
+    /// --rgb-pattern
+    #[arg(long, value_parser = parse_existing_dir, conflicts_with_all = ["band_pattern", "recursive"])]
+    rgb_dir: Option<PathBuf>,
+
+    /// Directory containing the NIR files, used as is instead of looking for one matching
+    /// --nir-pattern
+    #[arg(long, value_parser = parse_existing_dir, conflicts_with_all = ["band_pattern", "recursive"])]
+    nir_dir: Option<PathBuf>,
+
+    /// One pattern finding both band directories (e.g. 'CAMERA_*'), instead of --rgb-pattern
+    /// and --nir-pattern. They are told apart by --rgb-suffix and --nir-suffix
+    #[arg(long, conflicts_with_all = ["rgb_pattern", "nir_pattern", "recursive"])]
+    band_pattern: Option<String>,
+
+    /// Match the RGB and NIR files found together in IIQ_DIR (or --rgb-dir), told apart by
+    /// name: files whose name matches this regex (e.g. '_RGB\.iiq$') are RGB, the rest NIR
+    #[arg(long, value_parser = parse_regex, conflicts_with_all = ["nir_dir", "band_pattern", "recursive"])]
+    band_regex: Option<Regex>,
+
+    /// With --band-pattern, how the name of the RGB directory ends
+    #[arg(long, default_value = "_RGB")]
+    rgb_suffix: String,
+
+    /// With --band-pattern, how the name of the NIR directory ends
+    #[arg(long, default_value = "_NIR")]
+    nir_suffix: String,
+
+    /// What to do when more than one directory matches the RGB or NIR pattern
+    #[arg(long, value_enum, default_value = "fail")]
+    on_multiple: OnMultiple,
+
+    /// Threshold for matching images, e.g. 500ms or 1.5s (bare numbers are milliseconds)
+    #[arg(short, long, default_value = "500", value_parser = parse_duration)]
     thresh: Duration,
 
-    /// Verbose output
-    #[arg(short, long)]
-    verbose: bool,
+    /// Threshold instead of --thresh when the NIR file was captured before the RGB file
+    #[arg(long, value_parser = parse_duration)]
+    thresh_before: Option<Duration>,
+
+    /// Threshold instead of --thresh when the NIR file was captured after the RGB file
+    #[arg(long, value_parser = parse_duration)]
+    thresh_after: Option<Duration>,
+
+    /// Move pairs further apart than --thresh, but within this looser threshold, to review/
+    /// instead of unmatched/
+    #[arg(long, value_parser = parse_duration)]
+    review_thresh: Option<Duration>,
+
+    /// How to pair RGB and NIR files
+    #[arg(long, value_enum, default_value = "time")]
+    match_by: MatchBy,
+
+    /// Which file to pair with when two are equally close in time
+    #[arg(long, value_enum, default_value = "earlier")]
+    tie_break: Prefer,
+
+    /// With --match-by order, fail if the offset between paired files changes direction
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    monotonic: bool,
+
+    /// UTC offset of the RGB camera clock in minutes, e.g. -480 for UTC-8. Capture times are
+    /// converted to UTC before matching
+    #[arg(long, default_value = "0", allow_negative_numbers = true, value_parser = parse_utc_offset)]
+    rgb_tz_offset: FixedOffset,
+
+    /// UTC offset of the NIR camera clock in minutes
+    #[arg(long, default_value = "0", allow_negative_numbers = true, value_parser = parse_utc_offset)]
+    nir_tz_offset: FixedOffset,
+
+    /// Split into flight lines wherever the RGB files are more than this far apart, e.g. 30s,
+    /// and take the NIR clock's drift out of each line before matching
+    #[arg(long, value_name = "GAP", value_parser = parse_duration)]
+    line_offsets: Option<Duration>,
+
+    /// chrono format of the capture time at the start of the RGB file names, e.g.
+    /// `%Y%m%d_%H%M%S` (default: "%y%m%d_%H%M%S%3f")
+    #[arg(long)]
+    rgb_datetime_format: Option<String>,
+
+    /// chrono format of the capture time at the start of the NIR file names
+    #[arg(long)]
+    nir_datetime_format: Option<String>,
+
+    /// Regex finding the capture time in the file names, for names where it isn't at the start,
+    /// e.g. '_(\d{6}_\d{9})$' for SITE12_240101_120000000.iiq. The first capture group, or
+    /// else the whole match, is parsed with the band's datetime format
+    #[arg(long, value_parser = parse_regex)]
+    datetime_regex: Option<Regex>,
+
+    /// Only consider files captured at or after this time (same format as file names,
+    /// e.g. 240101_120000000)
+    #[arg(long, value_parser = parse_stem_datetime)]
+    after: Option<NaiveDateTime>,
+
+    /// Only consider files captured at or before this time (same format as file names)
+    #[arg(long, value_parser = parse_stem_datetime)]
+    before: Option<NaiveDateTime>,
+
+    /// Only consider files modified on disk since this time, either how long ago (e.g. 2h, 30m
+    /// or 1d) or a local time (e.g. 2024-01-01T12:00:00)
+    #[arg(long, value_parser = parse_since)]
+    since: Option<SystemTime>,
+
+    /// Only match files captured from this time of day on (e.g. 08:00), moving the others to
+    /// 'offhours'. Camera clock time, as in the file names
+    #[arg(long, requires = "tod_end", value_parser = parse_time_of_day)]
+    tod_start: Option<NaiveTime>,
+
+    /// Only match files captured before this time of day (e.g. 18:00). Earlier than
+    /// --tod-start for a window spanning midnight
+    #[arg(long, requires = "tod_start", value_parser = parse_time_of_day)]
+    tod_end: Option<NaiveTime>,
+
+    /// Exit with an error if any RGB or NIR file could not be matched
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    strict: bool,
+
+    /// How many directory levels below the RGB and NIR directories to search for IIQ files
+    /// (default: unlimited)
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// Skip files and directories matching this glob, relative to the RGB and NIR directories,
+    /// e.g. `**/thumbnails`. Can be repeated
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// Extension of the files to search the RGB directory for, case-sensitive. Can be repeated
+    /// or comma separated, e.g. `iiq,IIQ`
+    #[arg(long, value_name = "EXT", default_value = "iiq", value_delimiter = ',')]
+    rgb_ext: Vec<String>,
+
+    /// Extension of the files to search the NIR directory for, see --rgb-ext
+    #[arg(long, value_name = "EXT", default_value = "iiq", value_delimiter = ',')]
+    nir_ext: Vec<String>,
+
+    /// Match the RGB files listed in this file, one per line, instead of searching the RGB
+    /// directory. `-` reads the list from stdin
+    #[arg(long, value_name = "FILE", conflicts_with = "recursive")]
+    rgb_list: Option<PathBuf>,
+
+    /// Match the NIR files listed in this file instead of searching the NIR directory
+    #[arg(long, value_name = "FILE", conflicts_with = "recursive")]
+    nir_list: Option<PathBuf>,
+
+    /// Write where the unmatched RGB files ended up to this file, one per line, to match them
+    /// again later with --rgb-list
+    #[arg(long, value_name = "FILE")]
+    unmatched_rgb_list: Option<PathBuf>,
+
+    /// Write where the unmatched NIR files ended up to this file, see --unmatched-rgb-list
+    #[arg(long, value_name = "FILE")]
+    unmatched_nir_list: Option<PathBuf>,
+
+    /// Do not search symlinked directories
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    no_follow_links: bool,
+
+    /// Take capture times from the EXIF data in the IIQ file headers instead of the file names
+    #[cfg(feature = "exif")]
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    header_time: bool,
+
+    /// Check that IIQ files start with valid magic bytes, moving any that don't to 'corrupt'
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    validate: bool,
+
+    /// Keep watching the directories, matching files as they are copied in
+    #[cfg(feature = "watch")]
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false",
+          conflicts_with_all = ["dry_run", "revert", "recursive", "sweep", "output_dir", "rgb_list", "nir_list"])]
+    watch: bool,
+
+    /// With --watch, how long a file's size must stay the same before it is matched
+    #[cfg(feature = "watch")]
+    #[arg(long, default_value = "2s", value_parser = parse_duration)]
+    stable_for: Duration,
+
+    /// With --watch, stop and sort the remaining files after this long without new files
+    #[cfg(feature = "watch")]
+    #[arg(long, value_parser = parse_duration)]
+    idle_timeout: Option<Duration>,
+
+    /// Move matched pairs where one file is more than this many times the size of the other to
+    /// 'suspect', e.g. 1.5
+    #[arg(long)]
+    size_ratio_max: Option<f64>,
+
+    /// Move matched pairs with a quality below this to 'suspect', where quality goes from 1 for
+    /// files captured at the same moment to 0 for files a threshold apart, e.g. 0.2
+    #[arg(long)]
+    min_quality: Option<f64>,
+
+    /// Stop without moving the matched files if fewer than this fraction of the smaller band's
+    /// files match, e.g. 0.9, as when pointed at the wrong directories
+    #[arg(long)]
+    min_match_rate: Option<f64>,
+
+    /// Warn if one band has more than this many times as many files as the other, e.g. 2, or
+    /// with --strict, stop before matching
+    #[arg(long, value_name = "RATIO")]
+    max_count_skew: Option<f64>,
+
+    /// Warn if the match threshold is more than this fraction of the median time between
+    /// frames, e.g. 0.5, so several frames could match the same file, or with --strict, stop
+    /// before matching
+    #[arg(long, value_name = "RATIO")]
+    max_thresh_ratio: Option<f64>,
+
+    /// Move matched files into a subdirectory of the RGB and NIR directories, 'matched' unless
+    /// a name is given, instead of their root
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "matched")]
+    matched_dir: Option<String>,
+
+    /// Move unmatched files into a subdirectory with this name instead of 'unmatched'
+    #[arg(long, value_name = "NAME")]
+    unmatched_dir: Option<String>,
+
+    /// Move empty files into a subdirectory with this name instead of 'empty'
+    #[arg(long, value_name = "NAME")]
+    empty_dir: Option<String>,
+
+    /// Keep the subdirectories files were found in when moving them, e.g. to
+    /// 'unmatched/lineA/240101_1200', instead of moving them all into one directory
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    preserve_structure: bool,
+
+    /// Before matching, move the IIQ files found in subdirectories of the RGB and NIR
+    /// directories into them, renaming files whose names are already taken
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    flatten: bool,
+
+    /// Put the sorted files in 'rgb' and 'nir' under this directory instead of in the RGB and
+    /// NIR directories. With --copy, the source directories are left untouched
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["revert", "recursive"])]
+    output_dir: Option<PathBuf>,
+
+    /// Keep the scanned capture times in this file, so unchanged files are not read again on
+    /// the next run
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// Only match files not matched by earlier runs recorded in this file, then add this run's
+    /// matches to it
+    #[arg(long, value_name = "MANIFEST")]
+    incremental: Option<PathBuf>,
+
+    /// How many files to read at once (default: one per CPU core). 1 reads them one at a time
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+
+    /// Move or copy as many files at once as --threads reads, for network storage
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    parallel_moves: bool,
+
+    /// Print the results as a table
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    summary: bool,
+
+    /// Print nothing but the unmatched files, with the offset to the nearest file of the other
+    /// band for those over the threshold
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false", conflicts_with = "summary")]
+    report_unmatched_only: bool,
+
+    /// Print nothing but the matched pairs, one per line in capture order, with their offset
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false", conflicts_with_all = ["summary", "report_unmatched_only"])]
+    print_pairs: bool,
+
+    /// Write the moves a dry run would make to this file, as CSV if it ends in .csv and JSON
+    /// otherwise
+    #[arg(long, value_name = "PATH", requires = "dry_run")]
+    report_file: Option<PathBuf>,
+
+    /// Format to print the results in
+    #[cfg(feature = "exif")]
+    #[arg(long, value_enum, default_value = "text", conflicts_with_all = ["summary", "report_unmatched_only", "print_pairs"])]
+    report: ReportFormat,
+
+    /// Verbose output, printing each file as it is moved
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Print nothing but errors
+    #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    quiet: bool,
 
     /// Case-sensitive pattern matching on directory names
     #[arg(short, long, action=clap::ArgAction::SetTrue, default_value = "false")]
     case_sensitive: bool,
+
+    /// Process every directory under IIQ_DIR that contains both an RGB and a NIR directory
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    recursive: bool,
+
+    /// Move the files without showing what a dry run would do and asking first
+    #[arg(short, long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    yes: bool,
+
+    /// Do not show a progress bar (it is only drawn when stderr is a terminal)
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    no_progress: bool,
+
+    /// Print how long scanning, reading, matching and moving the files took, to stderr
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false")]
+    timings: bool,
+
+    /// Print how many pairs match at each of these thresholds (e.g. 100,200,300,500) and exit
+    /// without moving anything, to help pick --thresh
+    #[arg(long, value_delimiter = ',', value_parser = parse_duration,
+          conflicts_with_all = ["revert", "recursive"])]
+    sweep: Vec<Duration>,
+
+    /// Print each file with the capture time and size read for it, marking the files whose
+    /// names could not be parsed, and exit without moving anything
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value = "false",
+          conflicts_with_all = ["revert", "recursive", "sweep"])]
+    list: bool,
+}
+
+/// Map the `-q` and `-v` flags to a verbosity level. `-v` can be given more than once, but there
+/// is nothing above debug output yet.
+fn verbosity(quiet: bool, verbose: u8) -> Verbosity {
+    match (quiet, verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, _) => Verbosity::Debug,
+    }
+}
+
+/// How a run ended, which decides the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Every file was matched, or the files were reverted
+    Complete,
+    /// Some files were left without a match
+    Unmatched,
+    /// The run failed, e.g. because a directory was missing or a file could not be moved, or
+    /// the files weren't where they were moved to
+    Failed,
+    /// The files weren't moved since the confirmation prompt was declined
+    Declined,
+}
+
+impl Outcome {
+    fn from_report(report: &MatchReport) -> Self {
+        if !report.discrepancies.is_empty() {
+            Outcome::Failed
+        } else if report.unmatched_rgb_count + report.unmatched_nir_count > 0 {
+            Outcome::Unmatched
+        } else {
+            Outcome::Complete
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Complete => ExitCode::SUCCESS,
+            Outcome::Unmatched => ExitCode::from(1),
+            Outcome::Failed => ExitCode::from(2),
+            Outcome::Declined => ExitCode::from(3),
+        }
+    }
+}
+
+fn progress_bar(enabled: bool) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    // Draws to stderr, and hides itself when that is not a terminal
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::with_template("{msg:>18} [{bar:40}] {pos}/{len}")
+            .expect("Invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    bar
+}
+
+fn phase_label(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Scanning => "Scanning",
+        Phase::MovingEmpty => "Moving empty",
+        Phase::MovingCorrupt => "Moving corrupt",
+        Phase::MovingOffHours => "Moving off hours",
+        Phase::Matching => "Matching",
+        Phase::MovingMatched => "Moving matched",
+        Phase::MovingUnmatched => "Moving unmatched",
+    }
+}
+
+/// Format the time taken by each part of the run, for `--timings`.
+fn format_timings(timings: &Timings) -> String {
+    format!(
+        "Timings: scanning {:.1?}, reading {:.1?}, matching {:.1?}, moving {:.1?} (total {:.1?})",
+        timings.scanning,
+        timings.reading,
+        timings.matching,
+        timings.moving,
+        timings.total()
+    )
+}
+
+/// Format the report as an aligned table, for `--summary`.
+fn format_summary(report: &MatchReport) -> String {
+    let rows = [
+        ("Total", report.rgb_count, report.nir_count),
+        ("Matched", report.matched_count, report.matched_count),
+        (
+            "Unmatched",
+            report.unmatched_rgb_count,
+            report.unmatched_nir_count,
+        ),
+        ("Empty", report.empty_rgb_count, report.empty_nir_count),
+    ];
+    let mut table = format!("{:<14}{:>10}{:>10}\n", "", "RGB", "NIR");
+    for (label, rgb, nir) in rows {
+        table.push_str(&format!("{:<14}{:>10}{:>10}\n", label, rgb, nir));
+    }
+    let median = match report.delta_stats() {
+        Some(stats) => format!("{} ms", stats.median.as_millis()),
+        None => "-".to_string(),
+    };
+    table.push_str(&format!("{:<14}{:>10}\n", "Median delta", median));
+    table
+}
+
+/// One line for each unmatched file, in capture order, with the offset to the nearest file of
+/// the other band if they were over the threshold, for `--report-unmatched-only`.
+fn format_unmatched(report: &MatchReport) -> String {
+    let mut files: Vec<(&IIQFile, Option<Duration>)> = report
+        .over_threshold
+        .iter()
+        .flat_map(|(rgb, nir, dt)| [(rgb, Some(*dt)), (nir, Some(*dt))])
+        .chain(report.no_candidate.iter().map(|file| (file, None)))
+        .collect();
+    files.sort_by_key(|(file, _)| (file.datetime(), file.path().to_path_buf()));
+    let mut listing = String::new();
+    for (file, dt) in files {
+        listing.push_str(&file.path().display().to_string());
+        if let Some(dt) = dt {
+            listing.push_str(&format!(" (nearest {} ms)", dt.as_millis()));
+        }
+        listing.push('\n');
+    }
+    listing
+}
+
+/// One `RGB name <-> NIR name (offset)` line for each matched pair, in capture order, for
+/// `--print-pairs`.
+fn format_pairs(report: &MatchReport) -> String {
+    let mut pairs: Vec<_> = report.pairs.iter().collect();
+    // Reports merged from several sessions are only sorted within each session
+    pairs.sort_by_key(|(rgb, nir, _)| (rgb.datetime(), rgb.path(), nir.path()));
+    let mut listing = String::new();
+    for (rgb, nir, dt) in pairs {
+        listing.push_str(&format!(
+            "{} <-> {} ({}ms)\n",
+            rgb.name(),
+            nir.name(),
+            dt.as_millis()
+        ));
+    }
+    listing
+}
+
+/// One `name -> capture time -> size` line for each of `paths`, with the error instead for the
+/// files that couldn't be read, and the number of those.
+fn format_listing(
+    paths: &[PathBuf],
+    format: Option<&str>,
+    regex: Option<&Regex>,
+) -> (String, usize) {
+    let mut listing = String::new();
+    let mut failed = 0;
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match IIQFile::with_name_parsing(path, format, regex) {
+            Ok(file) => listing.push_str(&format!(
+                "{} -> {} -> {} bytes\n",
+                name,
+                file.datetime().format("%Y-%m-%d %H:%M:%S%.3f"),
+                file.bytes()
+            )),
+            Err(e) => {
+                failed += 1;
+                listing.push_str(&format!("{} -> FAILED: {}\n", name, e));
+            }
+        }
+    }
+    (listing, failed)
+}
+
+/// Table of the number of pairs matched at each threshold, see --sweep
+fn format_sweep(thresholds: &[Duration], counts: &[usize]) -> String {
+    let mut table = format!("{:<14}{:>10}\n", "Threshold", "Matched");
+    for (threshold, count) in thresholds.iter().zip(counts) {
+        let threshold = format!("{} ms", threshold.as_millis());
+        table.push_str(&format!("{:<14}{:>10}\n", threshold, count));
+    }
+    table
+}
+
+/// Write `paths` one per line, as read by --rgb-list and --nir-list.
+fn write_file_list(path: &Path, paths: &[PathBuf]) -> Result<()> {
+    let content: String = paths
+        .iter()
+        .map(|path| format!("{}\n", path.display()))
+        .collect();
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write file list {}", path.display()))
+}
+
+/// Write the files asked for with --report-file, --unmatched-rgb-list and --unmatched-nir-list.
+fn write_report_files(args: &Args, report: &MatchReport) -> Result<()> {
+    if let Some(path) = &args.report_file {
+        write_plan(path, &report.plan)?;
+    }
+    if let Some(path) = &args.unmatched_rgb_list {
+        write_file_list(path, &report.unmatched_rgb_paths)?;
+    }
+    if let Some(path) = &args.unmatched_nir_list {
+        write_file_list(path, &report.unmatched_nir_paths)?;
+    }
+    Ok(())
+}
+
+/// Write the moves of a dry run to `path`, for --report-file.
+fn write_plan(path: &Path, plan: &MovePlan) -> Result<()> {
+    let csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let contents = if csv { plan.to_csv() } else { plan.to_json() };
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn print_report(report: &MatchReport, output: Output) {
+    print_warnings(report);
+    match output {
+        Output::Text => {}
+        Output::Summary => {
+            print!("{}", format_summary(report));
+            return;
+        }
+        Output::UnmatchedOnly => {
+            print!("{}", format_unmatched(report));
+            return;
+        }
+        Output::Pairs => {
+            print!("{}", format_pairs(report));
+            return;
+        }
+        #[cfg(feature = "exif")]
+        Output::GeoJson => {
+            print!("{}", report.geojson());
+            let missing = report.missing_positions();
+            if missing > 0 {
+                eprintln!("{missing} matched pairs have no GPS position and were left out");
+            }
+            return;
+        }
+    }
+
+    print!("{report}");
+}
+
+/// Print the results of the dry run shown before asking to move the files. They go to stderr,
+/// like the prompt, so stdout only has the results of the real run in the selected format.
+fn print_preview(report: &MatchReport) {
+    print_warnings(report);
+    eprint!("{report}");
+}
+
+fn print_warnings(report: &MatchReport) {
+    if let Some(skew) = report.count_skew {
+        eprintln!(
+            "Warning: {} RGB and {} NIR files, {:.1} times as many in one band, check the directories",
+            report.rgb_count, report.nir_count, skew
+        );
+    }
+    for discrepancy in &report.discrepancies {
+        eprintln!("Error: {discrepancy}");
+    }
+    if let Some(interval) = report.frame_interval {
+        eprintln!(
+            "Warning: frames are only {} ms apart, close to the match threshold, so matches may be ambiguous",
+            interval.as_millis()
+        );
+    }
+}
+
+/// The RGB and NIR directories: the ones given with --rgb-dir and --nir-dir, or else the ones
+/// under `iiq_dir` matching the patterns.
+fn band_dirs(args: &Args, iiq_dir: &Path, follow_links: bool) -> Result<(PathBuf, PathBuf)> {
+    if args.band_regex.is_some() {
+        // One mixed directory for both bands
+        let dir = args.rgb_dir.as_deref().unwrap_or(iiq_dir);
+        return Ok((dir.to_path_buf(), dir.to_path_buf()));
+    }
+    if let Some(pattern) = &args.band_pattern {
+        return find_band_dirs(
+            iiq_dir,
+            pattern,
+            &args.rgb_suffix,
+            &args.nir_suffix,
+            args.case_sensitive,
+            follow_links,
+        )
+        .ok_or_else(|| anyhow::anyhow!("RGB or NIR directory not found"));
+    }
+
+    let dir_policy = match args.on_multiple {
+        OnMultiple::Fail => MultipleDirPolicy::Fail,
+        OnMultiple::Newest => MultipleDirPolicy::Newest,
+        OnMultiple::MostFiles => MultipleDirPolicy::MostFiles,
+    };
+    let find = |pattern: &str| {
+        find_dir_by_pattern_with_policy(
+            iiq_dir,
+            pattern,
+            args.case_sensitive,
+            follow_links,
+            dir_policy,
+        )
+    };
+    let rgb_dir = match &args.rgb_dir {
+        Some(dir) => dir.clone(),
+        None => {
+            find(&args.rgb_pattern).ok_or_else(|| anyhow::anyhow!("RGB directory not found"))?
+        }
+    };
+    let nir_dir = match &args.nir_dir {
+        Some(dir) => dir.clone(),
+        None => {
+            find(&args.nir_pattern).ok_or_else(|| anyhow::anyhow!("NIR directory not found"))?
+        }
+    };
+    Ok((rgb_dir, nir_dir))
+}
+
+/// Name of the config file looked for in IIQ_DIR.
+const CONFIG_FILE_NAME: &str = "ix-match.toml";
+
+/// Defaults for the flags, read from a TOML file. Flags given on the command line take
+/// precedence.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileConfig {
+    thresh: Option<ThreshValue>,
+    rgb_pattern: Option<String>,
+    nir_pattern: Option<String>,
+    matched_dir: Option<String>,
+    unmatched_dir: Option<String>,
+    empty_dir: Option<String>,
+    rgb_ext: Option<Vec<String>>,
+    nir_ext: Option<Vec<String>>,
+}
+
+/// A threshold in a config file, either a number of milliseconds or a duration like `1.5s`.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum ThreshValue {
+    Millis(u64),
+    Text(String),
+}
+
+impl FileConfig {
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+/// Fill in the flags not given on the command line from the `--config` file, or from
+/// `ix-match.toml` in IIQ_DIR if there is one.
+fn apply_config_file(mut args: Args, matches: &ArgMatches) -> Result<Args> {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None => {
+            let path = args.iiq_dir.join(CONFIG_FILE_NAME);
+            if !path.is_file() {
+                return Ok(args);
+            }
+            path
+        }
+    };
+    let file = FileConfig::load(&path)?;
+    let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+    if let Some(thresh) = file.thresh.filter(|_| unset("thresh")) {
+        args.thresh = match thresh {
+            ThreshValue::Millis(millis) => Duration::from_millis(millis),
+            ThreshValue::Text(text) => parse_duration(&text)
+                .with_context(|| format!("Invalid thresh in {}", path.display()))?,
+        };
+    }
+    if let Some(pattern) = file.rgb_pattern.filter(|_| unset("rgb_pattern")) {
+        args.rgb_pattern = pattern;
+    }
+    if let Some(pattern) = file.nir_pattern.filter(|_| unset("nir_pattern")) {
+        args.nir_pattern = pattern;
+    }
+    if let Some(name) = file.matched_dir.filter(|_| unset("matched_dir")) {
+        args.matched_dir = Some(name);
+    }
+    if let Some(name) = file.unmatched_dir.filter(|_| unset("unmatched_dir")) {
+        args.unmatched_dir = Some(name);
+    }
+    if let Some(name) = file.empty_dir.filter(|_| unset("empty_dir")) {
+        args.empty_dir = Some(name);
+    }
+    if let Some(extensions) = file.rgb_ext.filter(|_| unset("rgb_ext")) {
+        args.rgb_ext = extensions;
+    }
+    if let Some(extensions) = file.nir_ext.filter(|_| unset("nir_ext")) {
+        args.nir_ext = extensions;
+    }
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match apply_config_file(args, &matches).and_then(run) {
+        Ok(outcome) => outcome.into(),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            Outcome::Failed.into()
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let iiq_dir = args.iiq_dir;
+/// Whether to show what a run would do and ask before moving any files
+fn needs_confirmation(args: &Args) -> bool {
+    !args.yes && !args.dry_run
+}
+
+/// Ask on stdin whether to go ahead and move the files.
+fn ask_to_continue() -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        bail!("Not running interactively, pass --yes to move the files without asking");
+    }
+    eprint!("Move the files? [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn run(args: Args) -> Result<Outcome> {
+    let confirm = needs_confirmation(&args);
+    let iiq_dir = args.iiq_dir.clone();
+    let verbosity = verbosity(args.quiet, args.verbose);
+    let quiet = verbosity == Verbosity::Quiet;
+    let output = if args.summary {
+        Output::Summary
+    } else if args.report_unmatched_only {
+        Output::UnmatchedOnly
+    } else if args.print_pairs {
+        Output::Pairs
+    } else {
+        Output::Text
+    };
+    #[cfg(feature = "exif")]
+    let output = match args.report {
+        ReportFormat::Geojson => Output::GeoJson,
+        ReportFormat::Text => output,
+    };
+    #[cfg(feature = "verify")]
+    let copy_op = if args.verify {
+        FileOp::VerifiedCopy
+    } else {
+        FileOp::Copy
+    };
+    #[cfg(not(feature = "verify"))]
+    let copy_op = FileOp::Copy;
+
+    if args.rgb_list.as_deref() == Some(Path::new("-"))
+        && args.nir_list.as_deref() == Some(Path::new("-"))
+    {
+        bail!("Only one of --rgb-list and --nir-list can be read from stdin");
+    }
+    let rgb_paths = args.rgb_list.as_deref().map(read_file_list).transpose()?;
+    let nir_paths = args.nir_list.as_deref().map(read_file_list).transpose()?;
+
+    let config = ProcessConfig {
+        match_threshold: args.thresh,
+        match_threshold_before: args.thresh_before,
+        match_threshold_after: args.thresh_after,
+        review_threshold: args.review_thresh,
+        keep_empty_files: args.keep_empty,
+        keep_unmatched_in_place: args.keep_unmatched_in_place,
+        check_locations: args.check_locations,
+        write_sidecars: args.write_sidecar,
+        min_size_bytes: args.min_size_bytes,
+        dry_run: args.dry_run,
+        verbosity,
+        force: args.force,
+        strict: args.strict,
+        max_depth: args.max_depth,
+        exclude: args.exclude.clone(),
+        rgb_extensions: args.rgb_ext.clone(),
+        nir_extensions: args.nir_ext.clone(),
+        rgb_paths,
+        nir_paths,
+        follow_links: !args.no_follow_links,
+        validate: args.validate,
+        size_ratio_max: args.size_ratio_max,
+        min_quality: args.min_quality,
+        min_match_rate: args.min_match_rate,
+        max_count_skew: args.max_count_skew,
+        max_thresh_interval_ratio: args.max_thresh_ratio,
+        cache: args.cache.clone(),
+        threads: args.threads,
+        parallel_moves: args.parallel_moves,
+        manifest: args.incremental.clone(),
+        matched_subdir: args.matched_dir.clone(),
+        subdir_names: [
+            (FileStatus::Unmatched, &args.unmatched_dir),
+            (FileStatus::Empty, &args.empty_dir),
+        ]
+        .into_iter()
+        .filter_map(|(status, name)| Some((status, name.clone()?)))
+        .collect(),
+        preserve_structure: args.preserve_structure,
+        flatten: args.flatten,
+        output_dir: args.output_dir.clone(),
+        #[cfg(feature = "exif")]
+        header_datetime: args.header_time,
+        #[cfg(feature = "exif")]
+        read_gps: args.report == ReportFormat::Geojson,
+        file_op: if args.copy { copy_op } else { FileOp::Move },
+        match_strategy: match args.match_by {
+            MatchBy::Time => MatchStrategy::ByTime,
+            MatchBy::Order => MatchStrategy::ByOrder {
+                validate_monotonic: args.monotonic,
+            },
+            MatchBy::Index => MatchStrategy::ByIndex,
+            MatchBy::ManyToOne => MatchStrategy::ManyToOne,
+        },
+        tie_break: match args.tie_break {
+            Prefer::Earlier => TieBreak::PreferEarlier,
+            Prefer::Later => TieBreak::PreferLater,
+        },
+        rgb_utc_offset: args.rgb_tz_offset,
+        nir_utc_offset: args.nir_tz_offset,
+        line_offset_gap: args.line_offsets,
+        rgb_datetime_format: args.rgb_datetime_format.clone(),
+        nir_datetime_format: args.nir_datetime_format.clone(),
+        datetime_regex: args.datetime_regex.as_ref().map(|r| r.as_str().to_string()),
+        band_regex: args.band_regex.as_ref().map(|r| r.as_str().to_string()),
+        start: args.after,
+        end: args.before,
+        modified_since: args.since,
+        time_of_day: args.tod_start.zip(args.tod_end),
+        ..Default::default()
+    };
 
-    let rgb_dir = find_dir_by_pattern(&iiq_dir, &args.rgb_pattern, args.case_sensitive)
-        .ok_or_else(|| anyhow::anyhow!("RGB directory not found"))?;
+    if args.recursive {
+        if args.revert {
+            let sessions = find_session_dirs(
+                &iiq_dir,
+                &args.rgb_pattern,
+                &args.nir_pattern,
+                args.case_sensitive,
+                config.follow_links,
+            );
+            let mut outcome = Outcome::Complete;
+            for (rgb_dir, nir_dir) in sessions {
+                match revert_changes(&rgb_dir, &nir_dir, &config) {
+                    Ok((rgb_count, nir_count)) if !quiet => {
+                        println!(
+                            "{}: RGB: {rgb_count}, NIR: {nir_count} files reverted to original directories",
+                            rgb_dir.parent().unwrap_or(&iiq_dir).display()
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        outcome = Outcome::Failed;
+                    }
+                }
+            }
+            return Ok(outcome);
+        }
 
-    let nir_dir = find_dir_by_pattern(&iiq_dir, &args.nir_pattern, args.case_sensitive)
-        .ok_or_else(|| anyhow::anyhow!("NIR directory not found"))?;
+        if confirm {
+            let preview = ProcessConfig {
+                dry_run: true,
+                strict: false,
+                ..config.clone()
+            };
+            match process_sessions(
+                &iiq_dir,
+                &args.rgb_pattern,
+                &args.nir_pattern,
+                args.case_sensitive,
+                &preview,
+            ) {
+                Ok(reports) => {
+                    let mut total = MatchReport::default();
+                    for (_, report) in reports {
+                        total.merge(report);
+                    }
+                    print_preview(&total);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return Ok(Outcome::Failed);
+                }
+            }
+            if !ask_to_continue()? {
+                return Ok(Outcome::Declined);
+            }
+        }
+
+        let outcome = match process_sessions(
+            &iiq_dir,
+            &args.rgb_pattern,
+            &args.nir_pattern,
+            args.case_sensitive,
+            &config,
+        ) {
+            Ok(reports) => {
+                let mut total = MatchReport::default();
+                // GeoJSON and the unmatched files are printed once for all the sessions
+                let per_session = matches!(output, Output::Text | Output::Summary);
+                for (session_dir, report) in reports {
+                    if !quiet && per_session {
+                        println!("{}", session_dir.display());
+                        print_report(&report, output);
+                    }
+                    total.merge(report);
+                }
+                write_report_files(&args, &total)?;
+                if !quiet {
+                    if per_session {
+                        println!("Total");
+                    }
+                    print_report(&total, output);
+                    if args.timings {
+                        eprintln!("{}", format_timings(&total.timings));
+                    }
+                }
+                Outcome::from_report(&total)
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                match e {
+                    IxMatchError::Unmatched { .. } => Outcome::Unmatched,
+                    _ => Outcome::Failed,
+                }
+            }
+        };
+        return Ok(outcome);
+    }
+
+    let (rgb_dir, nir_dir) = band_dirs(&args, &iiq_dir, config.follow_links)?;
 
     if args.revert {
-        match revert_changes(&rgb_dir, &nir_dir, args.dry_run, args.verbose) {
-            Ok((rgb_count, nir_count)) => {
+        match revert_changes(&rgb_dir, &nir_dir, &config) {
+            Ok((rgb_count, nir_count)) if !quiet => {
                 println!(
                     "RGB: {rgb_count}, NIR: {nir_count} files reverted to original directories"
                 );
             }
-            Err(e) => eprintln!("Error: {}", e),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return Ok(Outcome::Failed);
+            }
         }
-        return Ok(());
+        return Ok(Outcome::Complete);
     }
 
-    match process_images(
-        &rgb_dir,
-        &nir_dir,
-        args.thresh,
-        args.keep_empty,
-        args.dry_run,
-        args.verbose,
-    ) {
-        Ok((rgb_count, nir_count, matched_count, empty_rgb_files, empty_nir_files)) => {
-            println!("RGB: {rgb_count}, NIR: {nir_count} ({matched_count} match)");
-            println!("Empty files: RGB {empty_rgb_files}, NIR: {empty_nir_files}");
+    if args.list {
+        let (rgb_paths, nir_paths) = band_files(&rgb_dir, &nir_dir, &config)?;
+        let mut failed = 0;
+        for (band, dir, paths, format) in [
+            ("RGB", &rgb_dir, rgb_paths, &config.rgb_datetime_format),
+            ("NIR", &nir_dir, nir_paths, &config.nir_datetime_format),
+        ] {
+            let (listing, band_failed) =
+                format_listing(&paths, format.as_deref(), args.datetime_regex.as_ref());
+            println!("{band}: {}", dir.display());
+            print!("{listing}");
+            failed += band_failed;
         }
-        Err(e) => eprintln!("Error: {}", e),
+        if failed > 0 {
+            eprintln!("{failed} files could not be parsed");
+            return Ok(Outcome::Failed);
+        }
+        return Ok(Outcome::Complete);
     }
 
-    Ok(())
+    if !args.sweep.is_empty() {
+        let counts = sweep_thresholds(&rgb_dir, &nir_dir, &args.sweep, &config)?;
+        print!("{}", format_sweep(&args.sweep, &counts));
+        return Ok(Outcome::Complete);
+    }
+
+    // Watching moves the files already there as well as new ones, so it asks first too
+    if confirm {
+        let preview = ProcessConfig {
+            dry_run: true,
+            strict: false,
+            ..config.clone()
+        };
+        match process_images(&rgb_dir, &nir_dir, &preview) {
+            Ok(report) => print_preview(&report),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return Ok(Outcome::Failed);
+            }
+        }
+        if !ask_to_continue()? {
+            return Ok(Outcome::Declined);
+        }
+    }
+
+    #[cfg(feature = "watch")]
+    if args.watch {
+        let watch = WatchConfig {
+            stable_for: args.stable_for,
+            idle_timeout: args.idle_timeout,
+            ..Default::default()
+        };
+        if !quiet {
+            println!("Watching for new files, press Ctrl-C to stop");
+        }
+        let result = watch_images(&rgb_dir, &nir_dir, &config, &watch, |batch| {
+            if !quiet {
+                println!("Matched {} new pairs", batch.matched_count);
+            }
+        });
+        return Ok(print_result(result, output, verbosity, args.timings));
+    }
+
+    // Verbose output would be interleaved with the bar
+    let bar = progress_bar(!args.no_progress && verbosity == Verbosity::Normal);
+    let result = process_images_with_progress(&rgb_dir, &nir_dir, &config, |p| {
+        bar.set_message(phase_label(p.phase));
+        bar.set_length(p.total as u64);
+        bar.set_position(p.current as u64);
+    });
+    bar.finish_and_clear();
+
+    match &result {
+        Ok(report) => write_report_files(&args, report)?,
+        Err(IxMatchError::Unmatched { report, .. }) => write_report_files(&args, report)?,
+        Err(_) => {}
+    }
+    Ok(print_result(result, output, verbosity, args.timings))
+}
+
+fn print_result(
+    result: ix_match::Result<MatchReport>,
+    output: Output,
+    verbosity: Verbosity,
+    timings: bool,
+) -> Outcome {
+    let quiet = verbosity == Verbosity::Quiet;
+    match result {
+        Ok(report) => {
+            if !quiet {
+                print_report(&report, output);
+                if timings {
+                    eprintln!("{}", format_timings(&report.timings));
+                }
+            }
+            Outcome::from_report(&report)
+        }
+        Err(IxMatchError::Unmatched { rgb, nir, report }) => {
+            if !quiet {
+                print_report(&report, output);
+                if timings {
+                    eprintln!("{}", format_timings(&report.timings));
+                }
+            }
+            eprintln!("Error: {rgb} RGB and {nir} NIR files were not matched");
+            Outcome::Unmatched
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Outcome::Failed
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ix_match::{find_dir_by_pattern, process_images, Discrepancy};
     use tempfile::tempdir;
 
     #[test]
@@ -115,11 +1382,11 @@ mod tests {
         std::fs::create_dir_all(&rgb_dir).unwrap();
         std::fs::create_dir_all(&nir_dir).unwrap();
 
-        let rgb_files = vec![
+        let rgb_files = [
             "240101_120000010_Camera_RGB.iiq",
             "240101_120000020_Camera_RGB.iiq",
         ];
-        let nir_files = vec![
+        let nir_files = [
             "240101_120000010_Camera_NIR.iiq",
             "240101_120000020_Camera_NIR.iiq",
         ];
@@ -132,25 +1399,451 @@ mod tests {
 
         let args = Args::try_parse_from(vec!["."]).unwrap();
         let rgb_dir =
-            find_dir_by_pattern(&temp_dir, &args.rgb_pattern, args.case_sensitive).unwrap();
+            find_dir_by_pattern(&temp_dir, &args.rgb_pattern, args.case_sensitive, true).unwrap();
         let nir_dir =
-            find_dir_by_pattern(&temp_dir, &args.nir_pattern, args.case_sensitive).unwrap();
-
-        let (rgb_count, nir_count, matched_count, empty_rgb_files, empty_nir_files) =
-            process_images(
-                &rgb_dir,
-                &nir_dir,
-                args.thresh,
-                args.keep_empty,
-                args.dry_run,
-                args.verbose,
+            find_dir_by_pattern(&temp_dir, &args.nir_pattern, args.case_sensitive, true).unwrap();
+
+        let config = ProcessConfig {
+            match_threshold: args.thresh,
+            keep_empty_files: args.keep_empty,
+            dry_run: args.dry_run,
+            verbosity: verbosity(args.quiet, args.verbose),
+            ..Default::default()
+        };
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        assert_eq!(report.rgb_count, 2);
+        assert_eq!(report.nir_count, 2);
+        assert_eq!(report.matched_count, 2);
+        assert_eq!(report.empty_rgb_count, 0);
+        assert_eq!(report.empty_nir_count, 0);
+    }
+
+    #[test]
+    fn test_band_dirs_explicit() {
+        let dir = tempdir().unwrap();
+        for name in ["CAMERA_RGB", "CAMERA_NIR", "left", "right"] {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        let iiq_dir = dir.path().canonicalize().unwrap();
+        let path = |name: &str| iiq_dir.join(name).to_string_lossy().into_owned();
+        let band_dirs_for = |extra: &[&str]| {
+            let mut args = vec!["ix-match".to_string(), path("")];
+            args.extend(extra.iter().map(|s| s.to_string()));
+            band_dirs(&Args::try_parse_from(args).unwrap(), &iiq_dir, true).unwrap()
+        };
+
+        assert_eq!(
+            band_dirs_for(&[]),
+            (iiq_dir.join("CAMERA_RGB"), iiq_dir.join("CAMERA_NIR"))
+        );
+        assert_eq!(
+            band_dirs_for(&["--rgb-dir", &path("left")]),
+            (iiq_dir.join("left"), iiq_dir.join("CAMERA_NIR"))
+        );
+        assert_eq!(
+            band_dirs_for(&[
+                "--rgb-dir",
+                &path("left"),
+                "--nir-dir",
+                &path("right"),
+                "--rgb-pattern",
+                "CAMERA_RGB",
+            ]),
+            (iiq_dir.join("left"), iiq_dir.join("right"))
+        );
+        assert_eq!(
+            band_dirs_for(&["--band-regex", "_RGB"]),
+            (iiq_dir.clone(), iiq_dir.clone())
+        );
+        assert_eq!(
+            band_dirs_for(&["--band-regex", "_RGB", "--rgb-dir", &path("left")]),
+            (iiq_dir.join("left"), iiq_dir.join("left"))
+        );
+        assert!(Args::try_parse_from(["ix-match", "--rgb-dir", &path("missing")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1s").unwrap(), Duration::from_secs(1));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(
+            parse_duration("0.25ms").unwrap(),
+            Duration::from_micros(250)
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_rejects_malformed() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("fast").is_err());
+        assert!(parse_duration("1.5").is_err());
+        assert!(parse_duration("10m").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(Args::try_parse_from(["ix-match", "--thresh", "abc"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_file_list() {
+        let content =
+            "# Picked for line 3\n/data/rgb/240101_120000000.iiq\n\n  240101_120001000.iiq  \n";
+        assert_eq!(
+            parse_file_list(content),
+            [
+                PathBuf::from("/data/rgb/240101_120000000.iiq"),
+                PathBuf::from("240101_120001000.iiq")
+            ]
+        );
+        assert!(parse_file_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let ago = |arg| {
+            SystemTime::now()
+                .duration_since(parse_since(arg).unwrap())
+                .unwrap()
+        };
+        assert!((Duration::from_secs(7199)..Duration::from_secs(7201)).contains(&ago("2h")));
+        assert!((Duration::from_secs(1799)..Duration::from_secs(1801)).contains(&ago("30m")));
+        assert!((Duration::from_secs(86_399)..Duration::from_secs(86_401)).contains(&ago("1d")));
+
+        let noon = Local.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap().into();
+        assert_eq!(parse_since("2024-01-01T12:00:00").unwrap(), noon);
+        assert_eq!(parse_since("240101_120000000").unwrap(), noon);
+
+        assert!(parse_since("yesterday").is_err());
+        assert!(parse_since("-2h").is_err());
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let report = MatchReport {
+            rgb_count: 12,
+            nir_count: 11,
+            matched_count: 10,
+            unmatched_rgb_count: 2,
+            unmatched_nir_count: 1,
+            deltas: vec![Duration::from_millis(100)],
+            ..Default::default()
+        };
+        assert_eq!(
+            format_summary(&report),
+            concat!(
+                "                     RGB       NIR\n",
+                "Total                 12        11\n",
+                "Matched               10        10\n",
+                "Unmatched              2         1\n",
+                "Empty                  0         0\n",
+                "Median delta      100 ms\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_unmatched_lists() {
+        let temp_dir = tempdir().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        std::fs::create_dir_all(&rgb_dir).unwrap();
+        std::fs::create_dir_all(&nir_dir).unwrap();
+        for name in ["210101_120000000.iiq", "210101_120010000.iiq"] {
+            std::fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        for name in ["210101_120000100.iiq", "210101_120020000.iiq"] {
+            std::fs::write(nir_dir.join(name), "content").unwrap();
+        }
+
+        let report = process_images(&rgb_dir, &nir_dir, &ProcessConfig::default()).unwrap();
+        let rgb_list = temp_dir.path().join("rgb.txt");
+        let nir_list = temp_dir.path().join("nir.txt");
+        let args = Args::try_parse_from([
+            "ix-match",
+            "--unmatched-rgb-list",
+            rgb_list.to_str().unwrap(),
+            "--unmatched-nir-list",
+            nir_list.to_str().unwrap(),
+        ])
+        .unwrap();
+        write_report_files(&args, &report).unwrap();
+
+        let rgb_dir = rgb_dir.canonicalize().unwrap();
+        let nir_dir = nir_dir.canonicalize().unwrap();
+        assert_eq!(
+            read_file_list(&rgb_list).unwrap(),
+            vec![rgb_dir.join("unmatched/210101_120010000.iiq")]
+        );
+        assert_eq!(
+            read_file_list(&nir_list).unwrap(),
+            vec![nir_dir.join("unmatched/210101_120020000.iiq")]
+        );
+    }
+
+    #[test]
+    fn test_write_plan() {
+        let temp_dir = tempdir().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        std::fs::create_dir_all(&rgb_dir).unwrap();
+        std::fs::create_dir_all(&nir_dir).unwrap();
+        std::fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        std::fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        std::fs::write(nir_dir.join("210101_120005000.iiq"), "content").unwrap();
+
+        let config = ProcessConfig {
+            matched_subdir: Some("matched".to_string()),
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+
+        let csv_path = temp_dir.path().join("plan.csv");
+        write_plan(&csv_path, &report.plan).unwrap();
+        let expected = format!(
+            "status,from,to\nmatched,{},{}\nmatched,{},{}\nunmatched,{},{}\n",
+            rgb_dir.join("210101_120000000.iiq").display(),
+            rgb_dir.join("matched/210101_120000000.iiq").display(),
+            nir_dir.join("210101_120000100.iiq").display(),
+            nir_dir.join("matched/210101_120000100.iiq").display(),
+            nir_dir.join("210101_120005000.iiq").display(),
+            nir_dir.join("unmatched/210101_120005000.iiq").display(),
+        );
+        assert_eq!(std::fs::read_to_string(&csv_path).unwrap(), expected);
+
+        let json_path = temp_dir.path().join("plan.json");
+        write_plan(&json_path, &report.plan).unwrap();
+        let json = std::fs::read_to_string(&json_path).unwrap();
+        assert!(json.starts_with(r#"[{"status":"matched","from":"#));
+        assert_eq!(json.matches(r#""status":"#).count(), 3);
+
+        // The dry run left every file where it was
+        assert!(nir_dir.join("210101_120005000.iiq").exists());
+        assert!(!nir_dir.join("unmatched").exists());
+    }
+
+    #[test]
+    fn test_format_pairs() {
+        let temp_dir = tempdir().unwrap();
+        let rgb_dir = temp_dir.path().join("CAMERA_RGB").join("240101_1200");
+        let nir_dir = temp_dir.path().join("CAMERA_NIR").join("240101_1200");
+        std::fs::create_dir_all(&rgb_dir).unwrap();
+        std::fs::create_dir_all(&nir_dir).unwrap();
+        for name in [
+            "240101_120000020_Camera_RGB.iiq",
+            "240101_120000010_Camera_RGB.iiq",
+        ] {
+            std::fs::write(rgb_dir.join(name), "content").unwrap();
+        }
+        for name in [
+            "240101_120000110_Camera_NIR.iiq",
+            "240101_120000015_Camera_NIR.iiq",
+        ] {
+            std::fs::write(nir_dir.join(name), "content").unwrap();
+        }
+
+        let config = ProcessConfig {
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = process_images(&rgb_dir, &nir_dir, &config).unwrap();
+        assert_eq!(
+            format_pairs(&report),
+            concat!(
+                "240101_120000010_Camera_RGB.iiq <-> 240101_120000015_Camera_NIR.iiq (5ms)\n",
+                "240101_120000020_Camera_RGB.iiq <-> 240101_120000110_Camera_NIR.iiq (90ms)\n",
+            )
+        );
+        assert_eq!(format_pairs(&MatchReport::default()), "");
+    }
+
+    #[test]
+    fn test_format_unmatched() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("RGB")).unwrap();
+        std::fs::create_dir(dir.path().join("NIR")).unwrap();
+        let file = |path: &str| {
+            let path = dir.path().join(path);
+            std::fs::write(&path, b"iiq").unwrap();
+            IIQFile::new(&path).unwrap()
+        };
+        let report = MatchReport {
+            matched_count: 1,
+            pairs: vec![(
+                file("RGB/210101_120000000.iiq"),
+                file("NIR/210101_120000100.iiq"),
+                Duration::from_millis(100),
+            )],
+            over_threshold: vec![(
+                file("RGB/210101_120020000.iiq"),
+                file("NIR/210101_120020700.iiq"),
+                Duration::from_millis(700),
+            )],
+            no_candidate: vec![file("NIR/210101_120010000.iiq")],
+            ..Default::default()
+        };
+        let path = |path: &str| dir.path().join(path).display().to_string();
+        assert_eq!(
+            format_unmatched(&report),
+            format!(
+                "{}\n{} (nearest 700 ms)\n{} (nearest 700 ms)\n",
+                path("NIR/210101_120010000.iiq"),
+                path("RGB/210101_120020000.iiq"),
+                path("NIR/210101_120020700.iiq"),
+            )
+        );
+        assert_eq!(format_unmatched(&MatchReport::default()), "");
+    }
+
+    #[test]
+    fn test_needs_confirmation() {
+        let parse = |args: &[&str]| Args::try_parse_from(args).unwrap();
+        assert!(needs_confirmation(&parse(&["ix-match"])));
+        assert!(!needs_confirmation(&parse(&["ix-match", "--yes"])));
+        assert!(!needs_confirmation(&parse(&["ix-match", "-y"])));
+        assert!(!needs_confirmation(&parse(&["ix-match", "--dry-run"])));
+        #[cfg(feature = "watch")]
+        {
+            assert!(needs_confirmation(&parse(&["ix-match", "--watch"])));
+            assert!(!needs_confirmation(&parse(&[
+                "ix-match", "--watch", "--yes"
+            ])));
+        }
+    }
+
+    #[test]
+    fn test_format_listing() {
+        let dir = tempdir().unwrap();
+        let good = dir.path().join("240101_120000500.iiq");
+        let bad = dir.path().join("240199_120000000.iiq");
+        std::fs::write(&good, "content").unwrap();
+        std::fs::write(&bad, "content").unwrap();
+
+        let (listing, failed) = format_listing(&[good.clone(), bad], None, None);
+        assert_eq!(failed, 1);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(
+            lines[0],
+            "240101_120000500.iiq -> 2024-01-01 12:00:00.500 -> 7 bytes"
+        );
+        assert!(lines[1].starts_with("240199_120000000.iiq -> FAILED: "));
+
+        let (listing, failed) = format_listing(&[good], Some("%y%m%d_%H%M%S"), None);
+        assert_eq!(failed, 0);
+        assert_eq!(
+            listing,
+            "240101_120000500.iiq -> 2024-01-01 12:00:00.000 -> 7 bytes\n"
+        );
+    }
+
+    #[test]
+    fn test_format_sweep() {
+        let thresholds = [100, 500].map(Duration::from_millis);
+        assert_eq!(
+            format_sweep(&thresholds, &[8, 10]),
+            concat!(
+                "Threshold        Matched\n",
+                "100 ms                 8\n",
+                "500 ms                10\n",
             )
+        );
+        let args = Args::try_parse_from(["ix-match", "--sweep", "100,200ms,1s"]).unwrap();
+        assert_eq!(
+            args.sweep,
+            [100, 200, 1000].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[test]
+    fn test_outcome_from_report() {
+        let mut report = MatchReport {
+            rgb_count: 2,
+            nir_count: 2,
+            matched_count: 2,
+            ..Default::default()
+        };
+        assert_eq!(Outcome::from_report(&report), Outcome::Complete);
+        report.unmatched_nir_count = 1;
+        assert_eq!(Outcome::from_report(&report), Outcome::Unmatched);
+        report.discrepancies = vec![Discrepancy::CountChanged {
+            expected: 4,
+            found: 3,
+        }];
+        assert_eq!(Outcome::from_report(&report), Outcome::Failed);
+    }
+
+    #[test]
+    fn test_declined_exit_code() {
+        // A declined prompt doesn't look like a finished run to scripts
+        let declined = ExitCode::from(Outcome::Declined);
+        assert_ne!(declined, ExitCode::from(Outcome::Complete));
+        assert_ne!(declined, ExitCode::from(Outcome::Unmatched));
+        assert_ne!(declined, ExitCode::from(Outcome::Failed));
+    }
+
+    #[test]
+    fn test_apply_config_file() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE_NAME),
+            "thresh = \"250ms\"\nrgb-pattern = \"RGB*\"\nnir-ext = [\"IIQ\", \"iiq\"]\nunmatched-dir = \"no_match\"\n",
+        )
+        .unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let parse = |argv: &[&str]| {
+            let matches = Args::command().try_get_matches_from(argv).unwrap();
+            let args = Args::from_arg_matches(&matches).unwrap();
+            apply_config_file(args, &matches).unwrap()
+        };
+
+        let args = parse(&["ix-match", dir]);
+        assert_eq!(args.thresh, Duration::from_millis(250));
+        assert_eq!(args.rgb_pattern, "RGB*");
+        assert_eq!(args.nir_pattern, "CAMERA_NIR");
+        assert_eq!(args.rgb_ext, ["iiq"]);
+        assert_eq!(args.nir_ext, ["IIQ", "iiq"]);
+        assert_eq!(args.unmatched_dir.as_deref(), Some("no_match"));
+
+        // Flags override the file
+        let args = parse(&["ix-match", dir, "--thresh", "100", "--nir-ext", "IIQ"]);
+        assert_eq!(args.thresh, Duration::from_millis(100));
+        assert_eq!(args.rgb_pattern, "RGB*");
+        assert_eq!(args.nir_ext, ["IIQ"]);
+        let args = parse(&["ix-match", dir, "--unmatched-dir", "leftover"]);
+        assert_eq!(args.unmatched_dir.as_deref(), Some("leftover"));
+
+        // Unknown keys are most likely typos
+        let config = temp_dir.path().join("other.toml");
+        std::fs::write(&config, "threshold = 100\n").unwrap();
+        let matches = Args::command()
+            .try_get_matches_from(["ix-match", dir, "--config", config.to_str().unwrap()])
             .unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+        assert!(apply_config_file(args, &matches).is_err());
+    }
 
-        assert_eq!(rgb_count, 2);
-        assert_eq!(nir_count, 2);
-        assert_eq!(matched_count, 2);
-        assert_eq!(empty_rgb_files, 0);
-        assert_eq!(empty_nir_files, 0);
+    #[test]
+    fn test_verbosity() {
+        let parse = |args: &[&str]| {
+            let args = Args::try_parse_from(args).unwrap();
+            verbosity(args.quiet, args.verbose)
+        };
+        assert_eq!(parse(&["ix-match"]), Verbosity::Normal);
+        assert_eq!(parse(&["ix-match", "-q"]), Verbosity::Quiet);
+        assert_eq!(parse(&["ix-match", "-v"]), Verbosity::Debug);
+        assert_eq!(parse(&["ix-match", "-vv"]), Verbosity::Debug);
+        assert_eq!(
+            parse(&["ix-match", "--verbose", "--verbose"]),
+            Verbosity::Debug
+        );
+        assert!(Args::try_parse_from(["ix-match", "-q", "-v"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_stem_datetime() {
+        let dt = parse_stem_datetime("240101_120000500").unwrap();
+        assert_eq!(dt.to_string(), "2024-01-01 12:00:00.500");
+        assert!(parse_stem_datetime("2024-01-01").is_err());
     }
 }
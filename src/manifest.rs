@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{IxMatchError, Result};
+use crate::IIQFile;
+
+/// First line of a manifest file, bumped whenever the format changes
+const MANIFEST_HEADER: &str = "ix-match manifest v1";
+
+/// The files matched by earlier runs, saved to disk so later runs on the same directories can
+/// leave them alone and only match the files that have arrived since.
+///
+/// Files are recorded by name, which they keep when they are moved, along with their band.
+/// Unmatched files are not recorded, so they can still be matched to a partner that arrives
+/// later.
+#[derive(Debug)]
+pub struct Manifest {
+    path: PathBuf,
+    rgb: HashSet<String>,
+    nir: HashSet<String>,
+}
+
+impl Manifest {
+    /// Load the manifest at `path`. A missing or empty manifest file gives an empty manifest,
+    /// and any other file fails with [`IxMatchError::InvalidManifest`] rather than being
+    /// overwritten when the manifest is saved.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut manifest = Manifest {
+            path: path.to_path_buf(),
+            rgb: HashSet::new(),
+            nir: HashSet::new(),
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(manifest),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut lines = content.lines();
+        match lines.next() {
+            None => return Ok(manifest),
+            Some(MANIFEST_HEADER) => {}
+            Some(_) => {
+                return Err(IxMatchError::InvalidManifest {
+                    path: path.to_path_buf(),
+                })
+            }
+        }
+        for line in lines {
+            match line.split_once('\t') {
+                Some(("rgb", name)) => manifest.rgb.insert(name.to_string()),
+                Some(("nir", name)) => manifest.nir.insert(name.to_string()),
+                _ => continue,
+            };
+        }
+        Ok(manifest)
+    }
+
+    /// Write the recorded files back to the manifest file.
+    pub fn save(&self) -> Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(&self.path)?);
+        writeln!(writer, "{}", MANIFEST_HEADER)?;
+        for (band, names) in [("rgb", &self.rgb), ("nir", &self.nir)] {
+            let mut names: Vec<&String> = names.iter().collect();
+            names.sort();
+            for name in names {
+                writeln!(writer, "{}\t{}", band, name)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Number of matched pairs recorded
+    pub fn len(&self) -> usize {
+        self.rgb.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rgb.is_empty()
+    }
+
+    /// Whether the RGB file at `path` was matched by an earlier run.
+    pub(crate) fn contains_rgb(&self, path: &Path) -> bool {
+        contains(&self.rgb, path)
+    }
+
+    /// Whether the NIR file at `path` was matched by an earlier run.
+    pub(crate) fn contains_nir(&self, path: &Path) -> bool {
+        contains(&self.nir, path)
+    }
+
+    /// Record the files of each matched pair.
+    pub(crate) fn record_pairs<'a>(
+        &mut self,
+        pairs: impl IntoIterator<Item = (&'a IIQFile, &'a IIQFile)>,
+    ) {
+        for (rgb, nir) in pairs {
+            self.rgb.insert(rgb.name().to_string());
+            self.nir.insert(nir.name().to_string());
+        }
+    }
+}
+
+fn contains(names: &HashSet<String>, path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| names.contains(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_path = temp_dir.path().join("manifest.txt");
+        let rgb = IIQFile::new_unchecked(&temp_dir.path().join("210101_120000000.iiq"), 1).unwrap();
+        let nir = IIQFile::new_unchecked(&temp_dir.path().join("210101_120000100.iiq"), 1).unwrap();
+
+        let mut manifest = Manifest::load(&manifest_path).unwrap();
+        assert!(manifest.is_empty());
+        manifest.record_pairs([(&rgb, &nir)]);
+        manifest.save().unwrap();
+
+        // Files are found by name wherever they were moved to, and only in their own band
+        let manifest = Manifest::load(&manifest_path).unwrap();
+        assert_eq!(manifest.len(), 1);
+        assert!(manifest.contains_rgb(Path::new("/elsewhere/210101_120000000.iiq")));
+        assert!(!manifest.contains_nir(Path::new("/elsewhere/210101_120000000.iiq")));
+        assert!(manifest.contains_nir(nir.path()));
+        assert!(!manifest.contains_rgb(Path::new("210101_120001000.iiq")));
+    }
+
+    #[test]
+    fn test_manifest_invalid() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_path = temp_dir.path().join("manifest.txt");
+
+        fs::write(&manifest_path, "").unwrap();
+        assert!(Manifest::load(&manifest_path).unwrap().is_empty());
+
+        // Some other file given by mistake is left alone
+        fs::write(&manifest_path, "notes\nrgb\t210101_120000000.iiq\n").unwrap();
+        assert!(matches!(
+            Manifest::load(&manifest_path),
+            Err(IxMatchError::InvalidManifest { path }) if path == manifest_path
+        ));
+        assert!(fs::read_to_string(&manifest_path)
+            .unwrap()
+            .starts_with("notes"));
+    }
+}
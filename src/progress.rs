@@ -0,0 +1,116 @@
+use std::time::Instant;
+
+use crate::report::Timings;
+
+/// The stages of [`process_images_with_progress`](crate::process_images_with_progress), in the
+/// order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Scanning,
+    MovingEmpty,
+    MovingCorrupt,
+    MovingOffHours,
+    Matching,
+    MovingMatched,
+    MovingUnmatched,
+}
+
+/// A progress update. Each phase starts with `current == 0` and counts up to `total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    pub current: usize,
+    pub total: usize,
+}
+
+pub(crate) struct ProgressTracker<'a> {
+    callback: &'a mut dyn FnMut(Progress),
+    progress: Progress,
+    /// Whether the first phase has started yet, before which the directories are searched
+    started: bool,
+    phase_start: Instant,
+    timings: Timings,
+    /// Span of the current phase, closed when the next one starts
+    #[cfg(feature = "tracing")]
+    span: Option<tracing::span::EnteredSpan>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(callback: &'a mut dyn FnMut(Progress)) -> Self {
+        ProgressTracker {
+            callback,
+            progress: Progress {
+                phase: Phase::Scanning,
+                current: 0,
+                total: 0,
+            },
+            started: false,
+            phase_start: Instant::now(),
+            timings: Timings::default(),
+            #[cfg(feature = "tracing")]
+            span: None,
+        }
+    }
+
+    /// Close the span of the current phase with the number of files it handled, and open one
+    /// for `phase`.
+    #[cfg(feature = "tracing")]
+    fn enter_span(&mut self, phase: Phase, total: usize) {
+        if let Some(span) = self.span.take() {
+            tracing::info!(files = self.progress.current, "phase done");
+            drop(span);
+        }
+        let span = match phase {
+            Phase::Scanning => tracing::info_span!("scanning", total),
+            Phase::Matching => tracing::info_span!("matching", total),
+            Phase::MovingEmpty
+            | Phase::MovingCorrupt
+            | Phase::MovingOffHours
+            | Phase::MovingMatched
+            | Phase::MovingUnmatched => tracing::info_span!("moving", ?phase, total),
+        };
+        self.span = Some(span.entered());
+    }
+
+    /// Add the time since the current phase started to its timing.
+    fn record_time(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.phase_start;
+        self.phase_start = now;
+        let timing = match self.progress.phase {
+            _ if !self.started => &mut self.timings.scanning,
+            Phase::Scanning => &mut self.timings.reading,
+            Phase::Matching => &mut self.timings.matching,
+            Phase::MovingEmpty
+            | Phase::MovingCorrupt
+            | Phase::MovingOffHours
+            | Phase::MovingMatched
+            | Phase::MovingUnmatched => &mut self.timings.moving,
+        };
+        *timing += elapsed;
+    }
+
+    /// How long each phase took so far, counting the current one up to now.
+    pub(crate) fn timings(&mut self) -> Timings {
+        self.record_time();
+        self.timings
+    }
+
+    pub(crate) fn start(&mut self, phase: Phase, total: usize) {
+        self.record_time();
+        #[cfg(feature = "tracing")]
+        self.enter_span(phase, total);
+        self.started = true;
+        self.progress = Progress {
+            phase,
+            current: 0,
+            total,
+        };
+        (self.callback)(self.progress);
+    }
+
+    pub(crate) fn advance(&mut self, n: usize) {
+        self.progress.current += n;
+        (self.callback)(self.progress);
+    }
+}
@@ -0,0 +1,801 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{FileStatus, IIQFile};
+
+/// Where a file was captured, in decimal degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Summary of a matching run, as returned by [`process_images`](crate::process_images).
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MatchReport {
+    pub rgb_count: usize,
+    pub nir_count: usize,
+    pub matched_count: usize,
+    /// Matched pairs whose file sizes differ by more than the allowed ratio, or whose quality
+    /// is below the minimum, moved to `suspect/`. These are included in `matched_count`.
+    pub suspect_count: usize,
+    /// Pairs further apart than the match threshold, but within
+    /// [`ProcessConfig::review_threshold`](crate::ProcessConfig::review_threshold), moved to
+    /// `review/`. These are in neither `matched_count` nor the unmatched counts.
+    pub review_count: usize,
+    pub unmatched_rgb_count: usize,
+    pub unmatched_nir_count: usize,
+    pub empty_rgb_count: usize,
+    pub empty_nir_count: usize,
+    /// Files that failed validation and were moved to `corrupt/`
+    pub corrupt_rgb_count: usize,
+    pub corrupt_nir_count: usize,
+    /// Files captured outside the time of day window, moved to `offhours/`
+    pub off_hours_rgb_count: usize,
+    pub off_hours_nir_count: usize,
+    /// Combined size of the matched RGB and NIR files
+    pub matched_bytes: u64,
+    /// Combined size of the unmatched RGB and NIR files
+    pub unmatched_bytes: u64,
+    /// Where the unmatched RGB files are after the run, e.g. to match them again once the rest
+    /// of a card is copied, with [`ProcessConfig::rgb_paths`](crate::ProcessConfig::rgb_paths)
+    pub unmatched_rgb_paths: Vec<PathBuf>,
+    /// Where the unmatched NIR files are after the run, see
+    /// [`unmatched_rgb_paths`](Self::unmatched_rgb_paths)
+    pub unmatched_nir_paths: Vec<PathBuf>,
+    /// Time offset between the files of each matched pair
+    pub deltas: Vec<Duration>,
+    /// Matched (RGB, NIR) pairs and their time offset, sorted by RGB capture time. Paths are
+    /// where the files were found, before they were moved.
+    pub pairs: Vec<(IIQFile, IIQFile, Duration)>,
+    /// Capture position of each of the `pairs`, from the RGB file or else the NIR file. Only
+    /// read with the `exif` feature when `ProcessConfig::read_gps` is set, and empty otherwise.
+    pub positions: Vec<Option<GpsPosition>>,
+    /// [`match_quality`] of each of the `pairs`
+    pub qualities: Vec<f64>,
+    /// Unmatched (RGB, NIR) pairs that were closest to each other, but further apart than the
+    /// match threshold, with their time offset. Sorted by RGB capture time.
+    pub over_threshold: Vec<(IIQFile, IIQFile, Duration)>,
+    /// Unmatched files, from either band, that had no candidate in the other band at all,
+    /// such as a frame one camera missed
+    pub no_candidate: Vec<IIQFile>,
+    /// The larger band's file count over the smaller one's, if it was more than
+    /// [`ProcessConfig::max_count_skew`](crate::ProcessConfig::max_count_skew), which usually
+    /// means the wrong directories or a camera that stopped
+    pub count_skew: Option<f64>,
+    /// The median time between frames of the band firing fastest, if the match threshold was
+    /// more than [`ProcessConfig::max_thresh_interval_ratio`](crate::ProcessConfig::max_thresh_interval_ratio)
+    /// of it, so several frames could fall within the threshold of the same file
+    pub frame_interval: Option<Duration>,
+    /// How long each part of the run took
+    pub timings: Timings,
+    /// Files not where the run left them, only checked with
+    /// [`ProcessConfig::check_locations`](crate::ProcessConfig::check_locations)
+    pub discrepancies: Vec<Discrepancy>,
+    /// Where each file would have been moved to, only filled in with
+    /// [`ProcessConfig::dry_run`](crate::ProcessConfig::dry_run)
+    pub plan: MovePlan,
+}
+
+impl MatchReport {
+    pub fn delta_stats(&self) -> Option<DeltaStats> {
+        DeltaStats::from_deltas(&self.deltas)
+    }
+
+    /// Add the counts and deltas of `other` to this report.
+    pub fn merge(&mut self, other: MatchReport) {
+        self.rgb_count += other.rgb_count;
+        self.nir_count += other.nir_count;
+        self.matched_count += other.matched_count;
+        self.suspect_count += other.suspect_count;
+        self.review_count += other.review_count;
+        self.unmatched_rgb_count += other.unmatched_rgb_count;
+        self.unmatched_nir_count += other.unmatched_nir_count;
+        self.empty_rgb_count += other.empty_rgb_count;
+        self.empty_nir_count += other.empty_nir_count;
+        self.corrupt_rgb_count += other.corrupt_rgb_count;
+        self.corrupt_nir_count += other.corrupt_nir_count;
+        self.off_hours_rgb_count += other.off_hours_rgb_count;
+        self.off_hours_nir_count += other.off_hours_nir_count;
+        self.matched_bytes += other.matched_bytes;
+        self.unmatched_bytes += other.unmatched_bytes;
+        self.unmatched_rgb_paths.extend(other.unmatched_rgb_paths);
+        self.unmatched_nir_paths.extend(other.unmatched_nir_paths);
+        self.deltas.extend(other.deltas);
+        // Keep the positions lined up with the pairs when only one report has them
+        if !self.positions.is_empty() || !other.positions.is_empty() {
+            let len = self.pairs.len() + other.pairs.len();
+            self.positions.resize(self.pairs.len(), None);
+            self.positions.extend(other.positions);
+            self.positions.resize(len, None);
+        }
+        self.pairs.extend(other.pairs);
+        self.qualities.extend(other.qualities);
+        self.over_threshold.extend(other.over_threshold);
+        self.no_candidate.extend(other.no_candidate);
+        self.count_skew = match (self.count_skew, other.count_skew) {
+            (Some(skew), Some(other)) => Some(skew.max(other)),
+            (skew, other) => skew.or(other),
+        };
+        self.frame_interval = match (self.frame_interval, other.frame_interval) {
+            (Some(interval), Some(other)) => Some(interval.min(other)),
+            (interval, other) => interval.or(other),
+        };
+        self.timings.merge(other.timings);
+        self.discrepancies.extend(other.discrepancies);
+        self.plan.moves.extend(other.plan.moves);
+    }
+
+    /// The matched pairs grouped by RGB file, each with the NIR files it was paired with. There
+    /// is more than one only with [`MatchStrategy::ManyToOne`](crate::MatchStrategy::ManyToOne).
+    pub fn groups(&self) -> Vec<(&IIQFile, Vec<&IIQFile>)> {
+        let mut groups: Vec<(&IIQFile, Vec<&IIQFile>)> = Vec::new();
+        for (rgb, nir, _) in &self.pairs {
+            match groups.last_mut() {
+                // Pairs are sorted by RGB capture time, so a group's pairs are next to each other
+                Some((last, nirs)) if last.path() == rgb.path() => nirs.push(nir),
+                _ => groups.push((rgb, vec![nir])),
+            }
+        }
+        groups
+    }
+
+    /// Number of matched pairs without a known capture position, which are left out of
+    /// [`geojson`](Self::geojson).
+    pub fn missing_positions(&self) -> usize {
+        self.pairs.len() - self.positions.iter().flatten().count()
+    }
+
+    /// Format the matched pairs with a capture position as a GeoJSON FeatureCollection of
+    /// Point features, with the file names, RGB capture time and offset as properties.
+    pub fn geojson(&self) -> String {
+        let features: Vec<String> = self
+            .pairs
+            .iter()
+            .zip(&self.positions)
+            .filter_map(|((rgb, nir, dt), position)| {
+                let position = position.as_ref()?;
+                Some(format!(
+                    concat!(
+                        r#"{{"type":"Feature","geometry":{{"type":"Point","coordinates":[{},{}]}},"#,
+                        r#""properties":{{"rgb_name":{},"nir_name":{},"datetime":"{}","delta_ms":{}}}}}"#
+                    ),
+                    position.longitude,
+                    position.latitude,
+                    json_string(rgb.name()),
+                    json_string(nir.name()),
+                    rgb.datetime().format("%Y-%m-%dT%H:%M:%S%.3f"),
+                    dt.as_millis()
+                ))
+            })
+            .collect();
+        format!(
+            "{{\"type\":\"FeatureCollection\",\"features\":[{}]}}\n",
+            features.join(",")
+        )
+    }
+
+    /// Compare the pairs of this report with those of a `newer` run of the same files, e.g. with
+    /// a different threshold or offset. Pairs are told apart by the paths of their files, so
+    /// compare reports of dry runs, or of runs reverted in between.
+    pub fn diff(&self, newer: &MatchReport) -> ReportDiff {
+        let key = |(rgb, nir, _): &(IIQFile, IIQFile, Duration)| {
+            (rgb.path().to_path_buf(), nir.path().to_path_buf())
+        };
+        let old_pairs: HashSet<_> = self.pairs.iter().map(key).collect();
+        let new_pairs: HashSet<_> = newer.pairs.iter().map(key).collect();
+        let pairs_without = |pairs: &[(IIQFile, IIQFile, Duration)], other: &HashSet<_>| {
+            pairs
+                .iter()
+                .filter(|pair| !other.contains(&key(pair)))
+                .map(|(rgb, nir, _)| (rgb.clone(), nir.clone()))
+                .collect::<Vec<_>>()
+        };
+        let added = pairs_without(&newer.pairs, &old_pairs);
+        let removed = pairs_without(&self.pairs, &new_pairs);
+
+        // The partner of each file of either band before the change
+        let mut old_partners: HashMap<&Path, &IIQFile> = HashMap::new();
+        for (rgb, nir) in &removed {
+            old_partners.insert(rgb.path(), nir);
+            old_partners.insert(nir.path(), rgb);
+        }
+        let mut repaired = Vec::new();
+        for (rgb, nir) in &added {
+            for (file, partner) in [(rgb, nir), (nir, rgb)] {
+                if let Some(before) = old_partners.get(file.path()) {
+                    repaired.push(PartnerChange {
+                        file: file.clone(),
+                        before: (*before).clone(),
+                        after: partner.clone(),
+                    });
+                }
+            }
+        }
+
+        ReportDiff {
+            added,
+            removed,
+            repaired,
+        }
+    }
+
+    /// Count matched pairs per `bucket_width` wide bucket of time offset, starting at 0. There
+    /// are at most [`MAX_HISTOGRAM_BUCKETS`], the last of which also counts any longer offsets,
+    /// e.g. those of pairs matched by order.
+    pub fn delta_histogram(&self, bucket_width: Duration) -> Vec<usize> {
+        histogram(&self.deltas, bucket_width)
+    }
+}
+
+/// The counts, sizes and offsets of the run, a few lines as printed by the CLI.
+impl fmt::Display for MatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "RGB: {}, NIR: {} ({} match)",
+            self.rgb_count, self.nir_count, self.matched_count
+        )?;
+        writeln!(
+            f,
+            "Empty files: RGB {}, NIR: {}",
+            self.empty_rgb_count, self.empty_nir_count
+        )?;
+        if self.corrupt_rgb_count > 0 || self.corrupt_nir_count > 0 {
+            writeln!(
+                f,
+                "Corrupt files: RGB {}, NIR: {}",
+                self.corrupt_rgb_count, self.corrupt_nir_count
+            )?;
+        }
+        if self.off_hours_rgb_count > 0 || self.off_hours_nir_count > 0 {
+            writeln!(
+                f,
+                "Off hours files: RGB {}, NIR: {}",
+                self.off_hours_rgb_count, self.off_hours_nir_count
+            )?;
+        }
+        if self.suspect_count > 0 {
+            writeln!(f, "Suspect pairs: {}", self.suspect_count)?;
+        }
+        if self.review_count > 0 {
+            writeln!(f, "Pairs to review: {}", self.review_count)?;
+        }
+        if !self.over_threshold.is_empty() || !self.no_candidate.is_empty() {
+            let closest = self.over_threshold.iter().map(|(_, _, dt)| *dt).min();
+            writeln!(
+                f,
+                "Unmatched: {} pairs over threshold{}, {} files without a candidate",
+                self.over_threshold.len(),
+                closest
+                    .map(|dt| format!(" (closest {} ms)", dt.as_millis()))
+                    .unwrap_or_default(),
+                self.no_candidate.len()
+            )?;
+        }
+        writeln!(
+            f,
+            "Data: {} matched, {} unmatched",
+            format_bytes(self.matched_bytes),
+            format_bytes(self.unmatched_bytes)
+        )?;
+        if let Some(stats) = self.delta_stats() {
+            writeln!(
+                f,
+                "Delta stats: min/mean/median/max {}/{}/{}/{} ms",
+                stats.min.as_millis(),
+                stats.mean.as_millis(),
+                stats.median.as_millis(),
+                stats.max.as_millis()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A difference between where a run moved files and what is on disk afterwards, see
+/// [`ProcessConfig::check_locations`](crate::ProcessConfig::check_locations).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Discrepancy {
+    /// A file moved or copied to `path` is not there
+    Missing { path: PathBuf },
+    /// A file moved away from `path` is still there too
+    NotRemoved { path: PathBuf },
+    /// The band directories hold a different number of files than before, counting any copies
+    CountChanged { expected: usize, found: usize },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::Missing { path } => write!(f, "{} is missing", path.display()),
+            Discrepancy::NotRemoved { path } => {
+                write!(f, "{} was moved but is still there", path.display())
+            }
+            Discrepancy::CountChanged { expected, found } => {
+                write!(f, "found {found} files after the run, expected {expected}")
+            }
+        }
+    }
+}
+
+/// How the pairs of two runs differ, see [`MatchReport::diff`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportDiff {
+    /// (RGB, NIR) pairs only in the newer run, in its order
+    pub added: Vec<(IIQFile, IIQFile)>,
+    /// (RGB, NIR) pairs only in the older run, in its order
+    pub removed: Vec<(IIQFile, IIQFile)>,
+    /// Files of either band that were matched in both runs, but to different files
+    pub repaired: Vec<PartnerChange>,
+}
+
+impl ReportDiff {
+    /// Whether both runs paired up the same files
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A file matched to `before` in the older run and to `after` in the newer one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartnerChange {
+    pub file: IIQFile,
+    pub before: IIQFile,
+    pub after: IIQFile,
+}
+
+/// A file a dry run would have moved, and where to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlannedMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub status: FileStatus,
+}
+
+/// Every move a dry run would have made, in the order it would have made them. Files that
+/// would be renamed on a name collision are listed at the path they collide with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MovePlan {
+    pub moves: Vec<PlannedMove>,
+}
+
+impl MovePlan {
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Format the plan as a JSON array of `{"status", "from", "to"}` objects, where the status
+    /// is `matched` or the [`FileStatus::dir_name`] of the other files.
+    pub fn to_json(&self) -> String {
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|planned| {
+                format!(
+                    r#"{{"status":{},"from":{},"to":{}}}"#,
+                    json_string(status_name(planned.status)),
+                    json_string(&planned.from.to_string_lossy()),
+                    json_string(&planned.to.to_string_lossy())
+                )
+            })
+            .collect();
+        format!("[{}]\n", moves.join(","))
+    }
+
+    /// Format the plan as CSV, with a `status,from,to` header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("status,from,to\n");
+        for planned in &self.moves {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                status_name(planned.status),
+                csv_field(&planned.from.to_string_lossy()),
+                csv_field(&planned.to.to_string_lossy())
+            ));
+        }
+        csv
+    }
+}
+
+/// Time spent in each part of a run, to see whether e.g. a slow network drive makes scanning or
+/// moving the bottleneck.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timings {
+    /// Searching the directories for files
+    pub scanning: Duration,
+    /// Reading the capture time and size of each file, the
+    /// [`Phase::Scanning`](crate::Phase::Scanning) progress phase
+    pub reading: Duration,
+    /// Pairing up the files
+    pub matching: Duration,
+    /// Moving or copying files into place, including the empty and corrupt ones
+    pub moving: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.scanning + self.reading + self.matching + self.moving
+    }
+
+    fn merge(&mut self, other: Timings) {
+        self.scanning += other.scanning;
+        self.reading += other.reading;
+        self.matching += other.matching;
+        self.moving += other.moving;
+    }
+}
+
+/// Distribution of time offsets between matched pairs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeltaStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+}
+
+impl DeltaStats {
+    /// Returns `None` if there are no deltas.
+    pub fn from_deltas(deltas: &[Duration]) -> Option<Self> {
+        if deltas.is_empty() {
+            return None;
+        }
+
+        let mut sorted = deltas.to_vec();
+        sorted.sort();
+
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2
+        } else {
+            sorted[mid]
+        };
+
+        let total: u128 = sorted.iter().map(Duration::as_nanos).sum();
+        let mean = Duration::from_nanos((total / sorted.len() as u128) as u64);
+
+        Some(DeltaStats {
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean,
+            median,
+        })
+    }
+}
+
+/// How trustworthy a match with time offset `delta` is, from 1 for files captured at the same
+/// moment down to 0 for files `threshold` or more apart.
+pub fn match_quality(delta: Duration, threshold: Duration) -> f64 {
+    if threshold.is_zero() {
+        return if delta.is_zero() { 1.0 } else { 0.0 };
+    }
+    (1.0 - delta.as_secs_f64() / threshold.as_secs_f64()).clamp(0.0, 1.0)
+}
+
+/// Quote and escape `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The sidecar of `file`, from the `band` band, matched with `partner`, see
+/// [`ProcessConfig::write_sidecars`](crate::ProcessConfig::write_sidecars).
+pub(crate) fn sidecar_json(
+    file: &IIQFile,
+    band: &str,
+    partner: &IIQFile,
+    delta: Duration,
+) -> String {
+    format!(
+        "{{\"file\":{},\"band\":{},\"partner\":{},\"delta_ms\":{}}}\n",
+        json_string(file.name()),
+        json_string(band),
+        json_string(partner.name()),
+        delta.as_millis()
+    )
+}
+
+/// `bytes` in B, KB, MB, GB or TB, e.g. `1.5 KB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn status_name(status: FileStatus) -> &'static str {
+    status.dir_name().unwrap_or("matched")
+}
+
+/// Quote `value` for a CSV file if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The most buckets [`MatchReport::delta_histogram`] gives
+pub const MAX_HISTOGRAM_BUCKETS: usize = 1000;
+
+fn histogram(deltas: &[Duration], bucket_width: Duration) -> Vec<usize> {
+    let width = bucket_width.as_nanos().max(1);
+    let last = MAX_HISTOGRAM_BUCKETS - 1;
+    let mut counts = Vec::new();
+    for delta in deltas {
+        let bucket = usize::try_from(delta.as_nanos() / width).map_or(last, |b| b.min(last));
+        if bucket >= counts.len() {
+            counts.resize(bucket + 1, 0);
+        }
+        counts[bucket] += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&ms| Duration::from_millis(ms)).collect()
+    }
+
+    #[test]
+    fn test_delta_stats() {
+        let stats = DeltaStats::from_deltas(&millis(&[40, 10, 30, 20, 100])).unwrap();
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(stats.mean, Duration::from_millis(40));
+        assert_eq!(stats.median, Duration::from_millis(30));
+
+        let stats = DeltaStats::from_deltas(&millis(&[40, 10, 30, 20])).unwrap();
+        assert_eq!(stats.median, Duration::from_millis(25));
+
+        assert!(DeltaStats::from_deltas(&[]).is_none());
+    }
+
+    #[test]
+    fn test_geojson() {
+        let file = |name: &str| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap();
+        let pair = |rgb: &str, nir: &str, ms| (file(rgb), file(nir), Duration::from_millis(ms));
+        let report = MatchReport {
+            pairs: vec![
+                pair("210101_120000000_RGB.iiq", "210101_120000100_NIR.iiq", 100),
+                pair("210101_120001000_RGB.iiq", "210101_120001050_NIR.iiq", 50),
+            ],
+            positions: vec![
+                Some(GpsPosition {
+                    latitude: 50.5,
+                    longitude: -125.25,
+                }),
+                None,
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(report.missing_positions(), 1);
+        assert_eq!(
+            report.geojson(),
+            concat!(
+                r#"{"type":"FeatureCollection","features":["#,
+                r#"{"type":"Feature","geometry":{"type":"Point","coordinates":[-125.25,50.5]},"#,
+                r#""properties":{"rgb_name":"210101_120000000_RGB.iiq","#,
+                r#""nir_name":"210101_120000100_NIR.iiq","#,
+                r#""datetime":"2021-01-01T12:00:00.000","delta_ms":100}}]}"#,
+                "\n"
+            )
+        );
+        assert_eq!(json_string("a\"b\\c\n"), r#""a\"b\\c\u000a""#);
+
+        // Reports without positions count every pair as missing
+        let mut total = MatchReport::default();
+        total.merge(MatchReport {
+            pairs: report.pairs.clone(),
+            ..Default::default()
+        });
+        total.merge(report);
+        assert_eq!(total.positions.len(), 4);
+        assert_eq!(total.missing_positions(), 3);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(1500), "1.5 KB");
+        assert_eq!(format_bytes(150_000_000_000), "150.0 GB");
+    }
+
+    #[test]
+    fn test_display() {
+        let file = |name: &str| IIQFile::new_unchecked(&PathBuf::from(name), 1500).unwrap();
+        assert_eq!(
+            file("rgb/210101_120000000.iiq").to_string(),
+            "210101_120000000.iiq @ 2021-01-01 12:00:00.000 (1.5 KB)"
+        );
+
+        let report = MatchReport {
+            rgb_count: 3,
+            nir_count: 2,
+            matched_count: 2,
+            unmatched_rgb_count: 1,
+            empty_nir_count: 1,
+            matched_bytes: 6000,
+            unmatched_bytes: 1500,
+            deltas: millis(&[100, 300]),
+            over_threshold: vec![(
+                file("rgb/210101_120005000.iiq"),
+                file("nir/210101_120005800.iiq"),
+                Duration::from_millis(800),
+            )],
+            ..Default::default()
+        };
+        assert_eq!(
+            report.to_string(),
+            concat!(
+                "RGB: 3, NIR: 2 (2 match)\n",
+                "Empty files: RGB 0, NIR: 1\n",
+                "Unmatched: 1 pairs over threshold (closest 800 ms), 0 files without a candidate\n",
+                "Data: 6.0 KB matched, 1.5 KB unmatched\n",
+                "Delta stats: min/mean/median/max 100/200/200/300 ms\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let file = |name: &str| IIQFile::new_unchecked(&PathBuf::from(name), 1).unwrap();
+        let pair = |rgb: &str, nir: &str| (file(rgb), file(nir), Duration::ZERO);
+        let older = MatchReport {
+            pairs: vec![
+                pair("rgb/210101_120000000.iiq", "nir/210101_120000100.iiq"),
+                pair("rgb/210101_120001000.iiq", "nir/210101_120001100.iiq"),
+            ],
+            ..Default::default()
+        };
+        // A larger offset pairs the second RGB frame with the next NIR frame instead
+        let newer = MatchReport {
+            pairs: vec![
+                pair("rgb/210101_120000000.iiq", "nir/210101_120000100.iiq"),
+                pair("rgb/210101_120001000.iiq", "nir/210101_120001900.iiq"),
+            ],
+            ..Default::default()
+        };
+
+        let diff = older.diff(&newer);
+        assert_eq!(
+            diff.added,
+            vec![(
+                file("rgb/210101_120001000.iiq"),
+                file("nir/210101_120001900.iiq")
+            )]
+        );
+        assert_eq!(
+            diff.removed,
+            vec![(
+                file("rgb/210101_120001000.iiq"),
+                file("nir/210101_120001100.iiq")
+            )]
+        );
+        assert_eq!(
+            diff.repaired,
+            vec![PartnerChange {
+                file: file("rgb/210101_120001000.iiq"),
+                before: file("nir/210101_120001100.iiq"),
+                after: file("nir/210101_120001900.iiq"),
+            }]
+        );
+        assert!(!diff.is_empty());
+        assert!(newer.diff(&newer).is_empty());
+    }
+
+    #[test]
+    fn test_move_plan_formats() {
+        let plan = MovePlan {
+            moves: vec![
+                PlannedMove {
+                    from: PathBuf::from("rgb/a.iiq"),
+                    to: PathBuf::from("rgb/matched/a.iiq"),
+                    status: FileStatus::Matched,
+                },
+                PlannedMove {
+                    from: PathBuf::from("nir/b,\"c\".iiq"),
+                    to: PathBuf::from("nir/unmatched/b,\"c\".iiq"),
+                    status: FileStatus::Unmatched,
+                },
+            ],
+        };
+
+        assert_eq!(
+            plan.to_json(),
+            concat!(
+                r#"[{"status":"matched","from":"rgb/a.iiq","to":"rgb/matched/a.iiq"},"#,
+                r#"{"status":"unmatched","from":"nir/b,\"c\".iiq","to":"nir/unmatched/b,\"c\".iiq"}]"#,
+                "\n"
+            )
+        );
+        assert_eq!(
+            plan.to_csv(),
+            concat!(
+                "status,from,to\n",
+                "matched,rgb/a.iiq,rgb/matched/a.iiq\n",
+                "unmatched,\"nir/b,\"\"c\"\".iiq\",\"nir/unmatched/b,\"\"c\"\".iiq\"\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_match_quality() {
+        let threshold = Duration::from_millis(500);
+        assert_eq!(match_quality(Duration::ZERO, threshold), 1.0);
+        assert_eq!(match_quality(Duration::from_millis(5), threshold), 0.99);
+        assert_eq!(match_quality(Duration::from_millis(250), threshold), 0.5);
+        assert_eq!(match_quality(Duration::from_millis(500), threshold), 0.0);
+        // Positional matches can be further apart than the threshold
+        assert_eq!(match_quality(Duration::from_secs(3), threshold), 0.0);
+
+        assert_eq!(match_quality(Duration::ZERO, Duration::ZERO), 1.0);
+        assert_eq!(match_quality(Duration::from_millis(1), Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn test_delta_histogram() {
+        let report = MatchReport {
+            deltas: millis(&[0, 5, 120, 130, 150, 450]),
+            ..Default::default()
+        };
+        let hist = report.delta_histogram(Duration::from_millis(100));
+        assert_eq!(hist, vec![2, 3, 0, 0, 1]);
+
+        // Far apart pairs, as matched by order, share the last bucket
+        let report = MatchReport {
+            deltas: vec![
+                Duration::from_millis(5),
+                Duration::from_secs(3600),
+                Duration::MAX,
+            ],
+            ..Default::default()
+        };
+        let hist = report.delta_histogram(Duration::from_millis(1));
+        assert_eq!(hist.len(), MAX_HISTOGRAM_BUCKETS);
+        assert_eq!(hist[5], 1);
+        assert_eq!(hist[MAX_HISTOGRAM_BUCKETS - 1], 2);
+        assert_eq!(hist.iter().sum::<usize>(), 3);
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::error::Result;
+use crate::{
+    filesystem::Journal, match_quality, matched_dir, output_subdirs, process_images, split_by_band,
+    transfer_into, IIQCollection, JoinedIIQCollection, MatchReport, ProcessConfig,
+};
+
+/// Options controlling [`watch_images`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchConfig {
+    /// How long a file's size has to stay the same before it is taken to be fully written
+    pub stable_for: Duration,
+    /// How often to look for new files and re-check those still being written
+    pub poll_interval: Duration,
+    /// Stop watching after this long without any new files. `None` watches until the watcher
+    /// fails.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            stable_for: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(500),
+            idle_timeout: None,
+        }
+    }
+}
+
+/// Tracks file sizes to tell when a file has stopped growing.
+#[derive(Debug, Default)]
+struct StabilityTracker {
+    seen: HashMap<PathBuf, (u64, Instant)>,
+}
+
+impl StabilityTracker {
+    /// Record the current size of `path`, returning whether it has not changed for `stable_for`.
+    fn update(&mut self, path: &Path, bytes: u64, now: Instant, stable_for: Duration) -> bool {
+        match self.seen.get(path) {
+            Some(&(last_bytes, since)) if last_bytes == bytes => now - since >= stable_for,
+            _ => {
+                self.seen.insert(path.to_path_buf(), (bytes, now));
+                false
+            }
+        }
+    }
+}
+
+/// Find the files in `dir` that are waiting to be matched and have finished being written.
+fn stable_files(
+    dir: &Path,
+    extensions: &[String],
+    config: &ProcessConfig,
+    watch: &WatchConfig,
+    tracker: &mut StabilityTracker,
+    handled: &HashSet<PathBuf>,
+) -> Result<Vec<PathBuf>> {
+    let dir = dir.canonicalize()?;
+    let output_dirs: Vec<PathBuf> = output_subdirs(config).iter().map(|n| dir.join(n)).collect();
+    let now = Instant::now();
+
+    let mut stable = Vec::new();
+    for path in config.find_files(&dir, extensions)? {
+        if handled.contains(&path) || output_dirs.iter().any(|d| path.starts_with(d)) {
+            continue;
+        }
+        // The file may have been moved or removed since the scan
+        let Ok(metadata) = path.metadata() else {
+            continue;
+        };
+        // Empty files may still be being created, so leave them for the final pass
+        let bytes = metadata.len();
+        if bytes > 0 && tracker.update(&path, bytes, now, watch.stable_for) {
+            stable.push(path);
+        }
+    }
+    Ok(stable)
+}
+
+/// Match the stable files waiting in each band directory, moving the matched pairs into place.
+fn match_stable_files(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    watch: &WatchConfig,
+    tracker: &mut StabilityTracker,
+    handled: &mut HashSet<PathBuf>,
+) -> Result<MatchReport> {
+    let datetime_regex = config.compiled_datetime_regex()?;
+    let rgb_paths = stable_files(
+        rgb_dir,
+        &config.rgb_extensions,
+        config,
+        watch,
+        tracker,
+        handled,
+    )?;
+    let (rgb_paths, nir_paths) = match config.compiled_band_regex()? {
+        // Both bands in one directory, told apart by name
+        Some(regex) => split_by_band(rgb_paths, &regex),
+        None => (
+            rgb_paths,
+            stable_files(
+                nir_dir,
+                &config.nir_extensions,
+                config,
+                watch,
+                tracker,
+                handled,
+            )?,
+        ),
+    };
+    let mut rgb_collection = IIQCollection::with_name_parsing(
+        &rgb_paths,
+        config.rgb_datetime_format.as_deref(),
+        datetime_regex.as_ref(),
+    )?;
+    let mut nir_collection = IIQCollection::with_name_parsing(
+        &nir_paths,
+        config.nir_datetime_format.as_deref(),
+        datetime_regex.as_ref(),
+    )?;
+    rgb_collection.convert_to_utc(config.rgb_utc_offset);
+    nir_collection.convert_to_utc(config.nir_utc_offset);
+    if rgb_collection.is_empty() || nir_collection.is_empty() {
+        return Ok(MatchReport::default());
+    }
+
+    let (before, after) = config.directional_thresholds();
+    let threshold = before.max(after);
+    let joined = JoinedIIQCollection::new(&rgb_collection, &nir_collection, config.tie_break)
+        .with_direction_limits(before, after);
+    let pairs = joined.get_matched_pairs(&threshold);
+    let matched_rgb = joined.get_matched_rgb(&threshold);
+    let matched_nir = joined.get_matched_nir(&threshold);
+
+    for (files, dir) in [(&matched_rgb, rgb_dir), (&matched_nir, nir_dir)] {
+        let dest = matched_dir(&dir.canonicalize()?, config);
+        let mut journal = Journal::default();
+        if !config.dry_run {
+            journal.create_dir_all(&dest)?;
+            transfer_into(files.paths(), dir, &dest, config, &mut journal, &mut || {})?;
+        }
+        handled.extend(files.iter().map(|file| file.path.clone()));
+        // Where they landed, which may be renamed on a clash or kept in their subdirectory
+        handled.extend(journal.destinations().into_values().map(Path::to_path_buf));
+    }
+
+    Ok(MatchReport {
+        rgb_count: matched_rgb.len(),
+        nir_count: matched_nir.len(),
+        matched_count: matched_rgb.len(),
+        matched_bytes: matched_rgb.total_bytes() + matched_nir.total_bytes(),
+        deltas: joined.get_matched_deltas(&threshold),
+        qualities: pairs
+            .iter()
+            .map(|(rgb, nir, dt)| match_quality(*dt, config.pair_threshold(rgb, nir)))
+            .collect(),
+        pairs,
+        ..Default::default()
+    })
+}
+
+/// Whether the event is a file being created, written or removed. Scanning the directories
+/// causes access events, which must not count as activity.
+fn is_change(event: &Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+}
+
+/// Watch the RGB and NIR directories, matching files as they are copied in.
+///
+/// Files are only considered once their size has stopped changing for
+/// [`WatchConfig::stable_for`], so partly written files are left alone. Matched pairs are moved
+/// into place as soon as both files are ready, and `on_matched` is called with a report of each
+/// batch. Files are always paired by time, within the match threshold; unmatched files are
+/// left where they are, in case their partner is still to come.
+///
+/// When watching stops, the directories are processed with [`process_images`] as usual, which
+/// also sorts out the empty and unmatched files, and its report is returned.
+pub fn watch_images(
+    rgb_dir: &Path,
+    nir_dir: &Path,
+    config: &ProcessConfig,
+    watch: &WatchConfig,
+    mut on_matched: impl FnMut(&MatchReport),
+) -> Result<MatchReport> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(rgb_dir, RecursiveMode::Recursive)?;
+    watcher.watch(nir_dir, RecursiveMode::Recursive)?;
+
+    let mut tracker = StabilityTracker::default();
+    let mut handled = HashSet::new();
+    let mut last_activity = Instant::now();
+    loop {
+        // Scanning causes events of its own, so scan at most once per interval rather than
+        // on every event
+        thread::sleep(watch.poll_interval);
+        let mut disconnected = false;
+        loop {
+            match rx.try_recv() {
+                Ok(event) => {
+                    if is_change(&event?) {
+                        last_activity = Instant::now();
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+        if disconnected {
+            break;
+        }
+
+        let report =
+            match_stable_files(rgb_dir, nir_dir, config, watch, &mut tracker, &mut handled)?;
+        if report.matched_count > 0 {
+            on_matched(&report);
+            last_activity = Instant::now();
+        }
+
+        if watch
+            .idle_timeout
+            .is_some_and(|timeout| last_activity.elapsed() >= timeout)
+        {
+            break;
+        }
+    }
+    drop(watcher);
+
+    process_images(rgb_dir, nir_dir, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CollisionPolicy;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stability_tracker() {
+        let mut tracker = StabilityTracker::default();
+        let path = Path::new("210101_120000000.iiq");
+        let stable_for = Duration::from_secs(2);
+        let start = Instant::now();
+
+        assert!(!tracker.update(path, 10, start, stable_for));
+        assert!(!tracker.update(path, 10, start + Duration::from_secs(1), stable_for));
+        // Still growing, so the clock restarts
+        assert!(!tracker.update(path, 20, start + Duration::from_secs(2), stable_for));
+        assert!(!tracker.update(path, 20, start + Duration::from_secs(3), stable_for));
+        assert!(tracker.update(path, 20, start + Duration::from_secs(4), stable_for));
+    }
+
+    #[test]
+    fn test_match_stable_files_band_regex() {
+        let temp_dir = TempDir::new().unwrap();
+        let mixed_dir = temp_dir.path().join("mixed");
+        fs::create_dir_all(&mixed_dir).unwrap();
+        for name in [
+            "210101_120000000_RGB.iiq",
+            "210101_120000100_NIR.iiq",
+            "210101_120010000_RGB.iiq",
+        ] {
+            fs::write(mixed_dir.join(name), "content").unwrap();
+        }
+        let config = ProcessConfig::builder()
+            .band_regex(r"_RGB\.iiq$")
+            .matched_subdir("matched")
+            .build();
+        let watch = WatchConfig {
+            stable_for: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut tracker = StabilityTracker::default();
+        let mut handled = HashSet::new();
+        let mut poll = || {
+            match_stable_files(
+                &mixed_dir,
+                &mixed_dir,
+                &config,
+                &watch,
+                &mut tracker,
+                &mut handled,
+            )
+            .unwrap()
+        };
+        // The files are first seen, then stable
+        assert_eq!(poll().matched_count, 0);
+        let report = poll();
+
+        // The one real pair, not each file with itself
+        assert_eq!(report.matched_count, 1);
+        let matched = mixed_dir.join("matched");
+        assert!(matched.join("210101_120000000_RGB.iiq").exists());
+        assert!(matched.join("210101_120000100_NIR.iiq").exists());
+        assert!(mixed_dir.join("210101_120010000_RGB.iiq").exists());
+    }
+
+    #[test]
+    fn test_match_stable_files_renamed() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("matched")).unwrap();
+        fs::create_dir_all(&nir_dir).unwrap();
+        fs::write(rgb_dir.join("210101_120000000.iiq"), "content").unwrap();
+        fs::write(nir_dir.join("210101_120000100.iiq"), "content").unwrap();
+        // Left from an earlier flight, so the new RGB file is renamed
+        fs::write(rgb_dir.join("matched/210101_120000000.iiq"), "old").unwrap();
+        let config = ProcessConfig::builder()
+            .matched_subdir("matched")
+            .collision_policy(CollisionPolicy::Rename)
+            .build();
+        let watch = WatchConfig {
+            stable_for: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut tracker = StabilityTracker::default();
+        let mut handled = HashSet::new();
+        for _ in 0..2 {
+            match_stable_files(
+                &rgb_dir,
+                &nir_dir,
+                &config,
+                &watch,
+                &mut tracker,
+                &mut handled,
+            )
+            .unwrap();
+        }
+
+        let renamed = rgb_dir
+            .canonicalize()
+            .unwrap()
+            .join("matched/210101_120000000-1.iiq");
+        assert!(renamed.exists());
+        assert!(handled.contains(&renamed));
+        let old = rgb_dir
+            .canonicalize()
+            .unwrap()
+            .join("matched/210101_120000000.iiq");
+        assert!(!handled.contains(&old));
+    }
+
+    #[test]
+    fn test_watch_images() {
+        let temp_dir = TempDir::new().unwrap();
+        let rgb_dir = temp_dir.path().join("rgb");
+        let nir_dir = temp_dir.path().join("nir");
+        fs::create_dir_all(rgb_dir.join("210101_1200")).unwrap();
+        fs::create_dir_all(nir_dir.join("210101_1200")).unwrap();
+
+        fs::write(
+            rgb_dir.join("210101_1200").join("210101_120000000.iiq"),
+            "content",
+        )
+        .unwrap();
+        fs::write(
+            nir_dir.join("210101_1200").join("210101_120000100.iiq"),
+            "content",
+        )
+        .unwrap();
+
+        // The second pair arrives while watching
+        let (late_rgb, late_nir) = (rgb_dir.clone(), nir_dir.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            fs::write(
+                late_rgb.join("210101_1200").join("210101_120001000.iiq"),
+                "content",
+            )
+            .unwrap();
+            fs::write(
+                late_nir.join("210101_1200").join("210101_120001100.iiq"),
+                "content",
+            )
+            .unwrap();
+        });
+
+        let watch = WatchConfig {
+            stable_for: Duration::from_millis(100),
+            poll_interval: Duration::from_millis(50),
+            idle_timeout: Some(Duration::from_millis(1000)),
+        };
+        let mut batches = Vec::new();
+        let report = watch_images(
+            &rgb_dir,
+            &nir_dir,
+            &ProcessConfig::default(),
+            &watch,
+            |report| batches.push(report.matched_count),
+        )
+        .unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(batches.iter().sum::<usize>(), 2);
+        assert_eq!(report.matched_count, 2);
+        for name in ["210101_120000000.iiq", "210101_120001000.iiq"] {
+            assert!(rgb_dir.join(name).exists());
+        }
+        for name in ["210101_120000100.iiq", "210101_120001100.iiq"] {
+            assert!(nir_dir.join(name).exists());
+        }
+    }
+}